@@ -4,6 +4,9 @@ use winres::{
     VersionInfo,
 };
 
+// the date conversion of the crash reports
+include!("src/civil.rs");
+
 fn main() {
     if std::env::var_os("CARGO_CFG_WINDOWS").is_some() {
         embed_manifest(
@@ -14,6 +17,10 @@ fn main() {
         .expect("unable to embed manifest file");
     }
     println!("cargo:rerun-if-changed=build.rs");
+    // Build details shown in the about dialog
+    watch_git_head();
+    println!("cargo:rustc-env=GIT_COMMIT={}", git_commit());
+    println!("cargo:rustc-env=BUILD_DATE={}", build_date());
     // Get version from Cargo.toml
     let version = std::env::var("CARGO_PKG_VERSION").unwrap();
     let version_parts: Vec<&str> = version.split('.').collect();
@@ -26,9 +33,9 @@ fn main() {
     
     // Set version information
     res.set_version_info(VersionInfo::PRODUCTVERSION, 
-        (major << 48) | (minor << 32) | (patch << 16) | 0);
+        (major << 48) | (minor << 32) | (patch << 16));
     res.set_version_info(VersionInfo::FILEVERSION, 
-        (major << 48) | (minor << 32) | (patch << 16) | 0);
+        (major << 48) | (minor << 32) | (patch << 16));
     
     // Set version strings and file info
     res.set("FileVersion", &format!("{}.{}.{}.0", major, minor, patch));
@@ -40,6 +47,50 @@ fn main() {
     res.set("OriginalFilename", &format!("{}.exe", std::env::var("CARGO_PKG_NAME").unwrap()));
     res.set("InternalName", &std::env::var("CARGO_PKG_NAME").unwrap_or_default());
     
-    // Compile the resources
-    res.compile().unwrap();
-}
\ No newline at end of file
+    // Compile the resources (rc.exe / windres are only available for windows targets)
+    if std::env::var_os("CARGO_CFG_WINDOWS").is_some() {
+        res.compile().unwrap();
+    }
+}
+
+// HEAD only changes when switching branches, a commit moves the branch it
+// points to, a loose ref or after `git gc` a line in packed-refs. The next
+// commit after gc writes a new loose ref, so its folder is watched: cargo
+// looks at every file in a folder it's given.
+fn watch_git_head() {
+    let git = std::path::Path::new(".git");
+    let mut watched = vec![git.join("HEAD"), git.join("packed-refs")];
+    if let Ok(head) = std::fs::read_to_string(git.join("HEAD")) {
+        if let Some(reference) = head.strip_prefix("ref: ") {
+            if let Some(folder) = git.join(reference.trim()).parent() {
+                watched.push(folder.to_path_buf());
+            }
+        }
+    }
+    // a file that isn't there would rerun the script on every build
+    for path in watched.iter().filter(|p| p.exists()) {
+        println!("cargo:rerun-if-changed={}", path.display());
+    }
+}
+
+fn git_commit() -> String {
+    std::process::Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .and_then(|o| String::from_utf8(o.stdout).ok())
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+// utc date as yyyy-mm-dd, without pulling a date crate into the build
+fn build_date() -> String {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
@@ -1,9 +1,14 @@
 use std::{
-    io::Error,
     thread,
     mem,
+    path::PathBuf,
+};
+use std::sync::{
+    Arc,
+    Mutex,
+    PoisonError,
+    atomic::{AtomicBool, Ordering},
 };
-use std::sync::{Arc, Mutex};
 
 use windows::Win32::{
     Foundation::*,
@@ -11,18 +16,36 @@ use windows::Win32::{
     System::DataExchange::*,
 };
 
+use crate::application::Application;
+use crate::crash;
+use crate::diagnostics::{self, Level, Record};
+use crate::error::{AppError, Context};
 use crate::window::Window;
 use crate::thread_safe::ThreadSafeHwnd;
+use crate::progress::{ProgressState, ProgressUpdate};
+use crate::startup::StartupPlan;
+use crate::tasks::{self, TaskOptions, TaskSink};
 
 #[derive(Clone, Default)]
 pub struct App {
     // Channel sender for thread communication
     window: Option<ThreadSafeHwnd>,
+    // set by the ui to ask the running task to stop
+    cancel: Arc<AtomicBool>,
+    // set by the ui to hold the running task
+    pause: Arc<AtomicBool>,
+    // set when the last task panicked
+    failed: Arc<AtomicBool>,
+    // name of the task GO runs
+    task: String,
+    // and what it runs with
+    task_options: TaskOptions,
 }
 
 impl App {
     pub fn new() -> Self {
         Self {
+            task: tasks::DEFAULT_TASK.to_string(),
             ..Default::default()
         }
     }
@@ -31,30 +54,113 @@ impl App {
         self.window = Some(ThreadSafeHwnd(hwnd));
     }
 
-    pub fn run_progress_bar(&self, app: Arc<Mutex<App>>) {
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::SeqCst)
+    }
+
+    pub fn pause(&self, pause: bool) {
+        self.pause.store(pause, Ordering::SeqCst);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.pause.load(Ordering::SeqCst)
+    }
+
+    pub fn task(&self) -> &str {
+        &self.task
+    }
+
+    /// The task GO runs from now on.
+    pub fn set_task(&mut self, task: &str) {
+        self.task = task.to_string();
+    }
+
+    pub fn task_options(&self) -> &TaskOptions {
+        &self.task_options
+    }
+
+    /// The settings GO runs the tasks with from now on.
+    pub fn set_task_options(&mut self, options: TaskOptions) {
+        self.task_options = options;
+    }
+
+    pub fn is_failed(&self) -> bool {
+        self.failed.load(Ordering::SeqCst)
+    }
+
+    // blocks while the ui holds the task, returns false when cancelled
+    fn wait_while_paused(&self, progress: &ProgressUpdate) -> bool {
+        if !self.is_paused() {
+            return !self.is_cancelled();
+        }
+        let paused = progress.clone().with_state(ProgressState::Paused);
+        self.post_message(Window::APP_UPDATE_PROGRESS, paused);
+        while self.is_paused() && !self.is_cancelled() {
+            thread::sleep(std::time::Duration::from_millis(100));
+        }
+        self.post_message(Window::APP_UPDATE_PROGRESS, progress.clone());
+        !self.is_cancelled()
+    }
+
+    pub fn run_progress_bar(&self, app: Arc<Mutex<App>>, path: PathBuf) {
+        self.cancel.store(false, Ordering::SeqCst);
+        self.pause.store(false, Ordering::SeqCst);
+        self.failed.store(false, Ordering::SeqCst);
         thread::spawn(move || {
-            let app = app.lock().unwrap();
-            let mut progress: (usize, usize, String);
+            // the task runs under catch, a panic can't poison the lock
+            let app = app.lock().unwrap_or_else(PoisonError::into_inner);
+            let record = Record::new(Level::Info, "worker", "task started")
+                .field("task", &app.task)
+                .field("path", path.display());
+            diagnostics::write(&record);
             app.post_message(Window::CTRL_EN_DIS, false);
-            for i in 0..30 {
-                thread::sleep(std::time::Duration::from_millis(300));
-                let msg = format!("進度{}/30", (i+1).to_string());
-                progress = (i+1, 30, msg);
-                app.post_message(Window::APP_UPDATE_PROGRESS, progress);
-                let msg = format!("append line {} to results", (i+1).to_string());
-                app.post_message(Window::APP_UPDATE_RESULT, msg);
+            let started = std::time::Instant::now();
+            let task = tasks::find(&app.task, &app.task_options);
+            let outcome = crash::catch("worker", || match &task {
+                Some(task) => task.run(&path, &*app),
+                None => Err(AppError::config(format!("unknown task {}", app.task))),
+            });
+            let failure = match &outcome {
+                Ok(Ok(())) => None,
+                Ok(Err(e)) => Some(e.to_string()),
+                Err(report) => Some(report.describe(&report.save())),
+            };
+            if failure.is_some() {
+                app.failed.store(true, Ordering::SeqCst);
             }
+            let record = match (&outcome, app.is_cancelled()) {
+                (Err(report), _) => Record::new(Level::Error, "worker", "task panicked")
+                    .field("message", &report.message),
+                (Ok(Err(e)), _) => Record::new(Level::Error, "worker", "task failed")
+                    .field("error", e),
+                (Ok(Ok(())), true) => Record::new(Level::Warn, "worker", "task cancelled"),
+                (Ok(Ok(())), false) => Record::new(Level::Info, "worker", "task done"),
+            };
+            diagnostics::write(&record.field("ms", started.elapsed().as_millis()));
             app.post_message(Window::CTRL_EN_DIS, true);
+            if let Some(text) = failure {
+                app.post_message(Window::APP_TASK_FAILED, text);
+            }
         });
     }
 
-    pub fn run(app: Self) -> Result<(), Error> {
-        Window::new(
+    pub fn run(app: Self, plan: StartupPlan) -> Result<(), AppError> {
+        let control = plan.control;
+        let window = Window::open(
             "windows-app",
             800, 
             600,
-            app
-        )?;
+            app,
+            plan
+        ).context("建立主視窗")?;
+        if control {
+            Window::serve_control(window);
+        }
+        Application::run();
 
         Ok(())
     }
@@ -82,3 +188,22 @@ impl App {
         }
     }
 }
+
+// a task on the worker reports to the window
+impl TaskSink for App {
+    fn progress(&self, update: &ProgressUpdate) {
+        self.post_message(Window::APP_UPDATE_PROGRESS, update.clone());
+    }
+
+    fn line(&self, line: &str) {
+        self.post_message(Window::APP_UPDATE_RESULT, line.to_string());
+    }
+
+    fn error_line(&self, line: &str) {
+        self.post_message(Window::APP_ERROR_LINE, line.to_string());
+    }
+
+    fn keep_going(&self, progress: &ProgressUpdate) -> bool {
+        self.wait_while_paused(progress)
+    }
+}
@@ -0,0 +1,201 @@
+use std::cell::{Cell, RefCell};
+
+use windows::core::*;
+use windows::Win32::{
+    Foundation::*,
+    System::LibraryLoader::GetModuleHandleW,
+    UI::WindowsAndMessaging::*,
+};
+
+use crate::cli;
+use crate::crash;
+use crate::dialog::report_crash;
+use crate::error::AppError;
+use crate::window_registry::{WindowRegistry, WindowRole};
+
+thread_local! {
+    // windows belong to the thread that created them, so does the registry
+    static WINDOWS: RefCell<WindowRegistry<HWND>> = RefCell::new(WindowRegistry::new());
+    // set once a window procedure panicked
+    static CRASHED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// What `create_window` passes to a new window: its state, taken in
+/// WM_NCCREATE, and the reason WM_CREATE failed, which CreateWindowExW
+/// itself can't tell.
+pub struct Creation<T> {
+    state: Option<T>,
+    error: Option<AppError>,
+}
+
+impl<T> Creation<T> {
+    pub fn new(state: T) -> Self {
+        Self { state: Some(state), error: None }
+    }
+
+    /// The creation data from the lparam of WM_NCCREATE or WM_CREATE.
+    ///
+    /// # Safety
+    /// Only for windows made by `create_window` with a `Creation<T>`.
+    pub unsafe fn from_lparam<'a>(lparam: LPARAM) -> &'a mut Self {
+        let cs = lparam.0 as *const CREATESTRUCTW;
+        &mut *((*cs).lpCreateParams as *mut Self)
+    }
+
+    pub fn take(&mut self) -> Option<T> {
+        self.state.take()
+    }
+
+    /// WM_CREATE returns -1 after this, `create_window` returns `error`.
+    pub fn fail(&mut self, error: AppError) {
+        self.error = Some(error);
+    }
+}
+
+/// Top-level windows of the ui thread and the one message loop that
+/// drives them.
+pub struct Application;
+
+impl Application {
+    /// Registers a window class the first time it's asked for.
+    pub fn register_class(class: &str, wndproc: WNDPROC) -> Result<()> {
+        if WINDOWS.with(|w| w.borrow().is_class_registered(class)) {
+            return Ok(());
+        }
+        unsafe {
+            let instance = GetModuleHandleW(None)?;
+            let class_name = HSTRING::from(class);
+            let wc = WNDCLASSW {
+                hCursor: LoadCursorW(None, IDC_ARROW)?,
+                hInstance: instance.into(),
+                lpszClassName: PCWSTR(class_name.as_ptr()),
+                style: CS_HREDRAW | CS_VREDRAW,
+                lpfnWndProc: wndproc,
+                ..Default::default()
+            };
+            if RegisterClassW(&wc) == 0 {
+                return Err(Error::from_win32());
+            }
+        }
+        WINDOWS.with(|w| w.borrow_mut().register_class(class));
+        Ok(())
+    }
+
+    /// Creates a top-level window of a registered class, `creation` is
+    /// handed to WM_NCCREATE and WM_CREATE.
+    pub fn create_window<T>(
+        class: &str,
+        title: &str,
+        style: WINDOW_STYLE,
+        width: i32,
+        height: i32,
+        role: WindowRole,
+        creation: &mut Creation<T>,
+    ) -> std::result::Result<HWND, AppError> {
+        unsafe {
+            let instance = GetModuleHandleW(None)?;
+            let param = creation as *mut Creation<T> as *const core::ffi::c_void;
+            let hwnd = CreateWindowExW(
+                WINDOW_EX_STYLE::default(),
+                &HSTRING::from(class),
+                &HSTRING::from(title),
+                style,
+                CW_USEDEFAULT,
+                CW_USEDEFAULT,
+                width,
+                height,
+                None,
+                None,
+                instance,
+                Some(param),
+            )
+            .map_err(|e| creation.error.take().unwrap_or_else(|| e.into()))?;
+            WINDOWS.with(|w| w.borrow_mut().add(hwnd, role, class));
+            Ok(hwnd)
+        }
+    }
+
+    /// Open window of `class`, if any.
+    pub fn find_window(class: &str) -> Option<HWND> {
+        WINDOWS.with(|w| w.borrow().find_class(class))
+    }
+
+    /// To be called from WM_NCDESTROY of every window created here. Closes
+    /// the tool windows and ends the loop after the last main window.
+    pub fn window_destroyed(hwnd: HWND) {
+        let quit = WINDOWS.with(|w| w.borrow_mut().remove(hwnd));
+        if !quit {
+            return;
+        }
+        let tools = WINDOWS.with(|w| w.borrow().tools());
+        unsafe {
+            for tool in tools {
+                let _ = DestroyWindow(tool);
+            }
+            PostQuitMessage(0);
+        }
+    }
+
+    /// Runs the window procedure `proc`. A panic must not unwind into
+    /// user32: it's reported and the application ends, the windows only
+    /// get the default handling while the dialog is up.
+    pub fn guard(
+        window: HWND,
+        message: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+        proc: impl FnOnce() -> LRESULT,
+    ) -> LRESULT {
+        if CRASHED.get() {
+            return unsafe { DefWindowProcW(window, message, wparam, lparam) };
+        }
+        match crash::catch("window", proc) {
+            Ok(result) => result,
+            Err(report) => {
+                CRASHED.set(true);
+                report_crash(None, &report);
+                std::process::exit(cli::EXIT_PANIC);
+            },
+        }
+    }
+
+    pub fn add_dialog(dialog: HWND) {
+        WINDOWS.with(|w| w.borrow_mut().add_dialog(dialog));
+    }
+
+    pub fn remove_dialog(dialog: HWND) {
+        WINDOWS.with(|w| w.borrow_mut().remove_dialog(dialog));
+    }
+
+    /// Runs the message loop until the last main window is destroyed,
+    /// returns the exit code of WM_QUIT.
+    pub fn run() -> i32 {
+        unsafe {
+            let mut message = MSG::default();
+            while GetMessageW(&mut message, None, 0, 0).into() {
+                if Self::is_dialog_message(&message) {
+                    continue;
+                }
+                // translates keystrokes (key down, key up) into characters
+                let _ = TranslateMessage(&message);
+                DispatchMessageW(&message);
+            }
+            message.wParam.0 as i32
+        }
+    }
+
+    // modeless dialogs and the tab navigation of the windows
+    fn is_dialog_message(message: &MSG) -> bool {
+        let dialogs = WINDOWS.with(|w| w.borrow().dialogs().to_vec());
+        unsafe {
+            for dialog in dialogs {
+                if IsDialogMessageW(dialog, message).as_bool() {
+                    return true;
+                }
+            }
+            let root = GetAncestor(message.hwnd, GA_ROOT);
+            let registered = WINDOWS.with(|w| w.borrow().contains(root));
+            registered && IsDialogMessageW(root, message).as_bool()
+        }
+    }
+}
@@ -0,0 +1,460 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use crate::digest::{self, Algorithm};
+use crate::error::{AppError, Context};
+use crate::progress::ProgressUpdate;
+use crate::scan::{Scan, ScanOptions};
+use crate::tasks::{Task, TaskSink};
+
+// files are read in pieces this big, cancel is checked between them
+const CHUNK: usize = 1024 * 1024;
+
+/// What the checksum task does, from the command line.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChecksumOptions {
+    pub algorithm: Algorithm,
+    /// Taken from the selected folder unless absolute, the name
+    /// sha256sum users expect by default.
+    pub manifest: Option<PathBuf>,
+    /// Check the folder against the manifest instead of writing it.
+    pub verify: bool,
+}
+
+/// A line of a manifest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub hash: String,
+    /// Below the folder, with `/` between the names.
+    pub path: String,
+}
+
+/// A line as sha256sum writes it. A path with a backslash or a line break
+/// has them escaped and the line starts with a backslash.
+pub fn manifest_line(hash: &str, path: &str) -> String {
+    match path.contains(['\\', '\n', '\r']) {
+        true => {
+            let escaped = path.replace('\\', "\\\\").replace('\n', "\\n").replace('\r', "\\r");
+            format!("\\{}  {}", hash, escaped)
+        },
+        false => format!("{}  {}", hash, path),
+    }
+}
+
+/// Reads a manifest of sha256sum, sha1sum or md5sum, text or binary mode.
+/// Returns the entries and the numbers of the lines that aren't one.
+pub fn parse_manifest(text: &str, algorithm: Algorithm) -> (Vec<Entry>, Vec<usize>) {
+    let mut entries = Vec::new();
+    let mut bad = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match parse_line(line, algorithm) {
+            Some(entry) => entries.push(entry),
+            None => bad.push(i + 1),
+        }
+    }
+    (entries, bad)
+}
+
+fn parse_line(line: &str, algorithm: Algorithm) -> Option<Entry> {
+    let (escaped, line) = match line.strip_prefix('\\') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    let (hash, rest) = line.split_at_checked(algorithm.hex_len())?;
+    if !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    // a space, then a space for text mode or * for binary
+    let path = rest.strip_prefix("  ").or_else(|| rest.strip_prefix(" *"))?;
+    let path = match escaped {
+        true => unescape(path)?,
+        false => path.to_string(),
+    };
+    let path = path.strip_prefix("./").map(str::to_string).unwrap_or(path);
+    match path.is_empty() {
+        true => None,
+        false => Some(Entry { hash: hash.to_ascii_lowercase(), path }),
+    }
+}
+
+fn unescape(path: &str) -> Option<String> {
+    let mut out = String::new();
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.push(match chars.next()? {
+                '\\' => '\\',
+                'n' => '\n',
+                'r' => '\r',
+                _ => return None,
+            }),
+            c => out.push(c),
+        }
+    }
+    Some(out)
+}
+
+/// Hashes the file at `path`. `read` gets the size of every piece read
+/// and stops the hashing with false, then it's None.
+pub fn hash_file(
+    path: &Path, algorithm: Algorithm, read: &mut dyn FnMut(u64) -> bool
+) -> io::Result<Option<String>> {
+    let mut file = File::open(path)?;
+    let mut hasher = algorithm.hasher();
+    let mut buffer = vec![0; CHUNK];
+    loop {
+        let n = match file.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        hasher.update(&buffer[..n]);
+        if !read(n as u64) {
+            return Ok(None);
+        }
+    }
+    Ok(Some(digest::hex(&hasher.finish())))
+}
+
+// the bar follows the bytes, the count the files
+struct Progress {
+    files: usize,
+    bytes: u64,
+    done_files: usize,
+    done_bytes: u64,
+}
+
+impl Progress {
+    fn update(&self) -> ProgressUpdate {
+        let fraction = match self.bytes {
+            0 => self.done_files as f64 / self.files.max(1) as f64,
+            bytes => self.done_bytes as f64 / bytes as f64,
+        };
+        ProgressUpdate {
+            stage: "計算".to_string(),
+            fraction: Some(fraction),
+            ..ProgressUpdate::new(self.done_files, self.files)
+        }
+    }
+}
+
+/// A file the walk found, (path below the folder, full path, size).
+type Found = (String, PathBuf, u64);
+
+/// Hashes every file under the selected folder into a manifest, or checks
+/// the folder against one.
+pub struct Checksum {
+    options: ChecksumOptions,
+}
+
+impl Checksum {
+    pub const NAME: &'static str = "checksum";
+
+    /// Writes SHA256SUMS into the folder.
+    pub fn new() -> Self {
+        Self::with_options(ChecksumOptions::default())
+    }
+
+    pub fn with_options(options: ChecksumOptions) -> Self {
+        Self { options }
+    }
+
+    // the manifest while it's written, next to it as list.md5.partial
+    fn partial(manifest: &Path) -> PathBuf {
+        let mut name = manifest.as_os_str().to_owned();
+        name.push(".partial");
+        PathBuf::from(name)
+    }
+
+    // the files of `root` but the manifest, None when cancelled
+    fn files(root: &Path, manifest: &Path, sink: &dyn TaskSink) -> Result<Option<Vec<Found>>, AppError> {
+        // a partial one is left behind when writing it failed
+        let skip: Vec<String> = [manifest.to_path_buf(), Self::partial(manifest)]
+            .iter()
+            .filter_map(|file| file.strip_prefix(root).ok())
+            .map(|p| p.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/"))
+            .collect();
+        let mut files = Vec::new();
+        let walked = Scan::walk(root, &ScanOptions::default(), sink, &mut |path, full, size| {
+            if !skip.iter().any(|s| s == path) {
+                files.push((path.to_string(), full.to_path_buf(), size));
+            }
+        })?;
+        files.sort();
+        Ok(walked.map(|_| files))
+    }
+
+    // hashes `files`, calling `hashed` with each outcome; false when cancelled
+    fn hash_all(
+        files: &[Found], algorithm: Algorithm, sink: &dyn TaskSink,
+        hashed: &mut dyn FnMut(&str, io::Result<String>),
+    ) -> bool {
+        let mut progress = Progress {
+            files: files.len(),
+            bytes: files.iter().map(|f| f.2).sum(),
+            done_files: 0,
+            done_bytes: 0,
+        };
+        sink.progress(&progress.update());
+        for (path, full, _) in files {
+            let mut read = |n| {
+                progress.done_bytes += n;
+                let update = progress.update();
+                sink.progress(&update);
+                sink.keep_going(&update)
+            };
+            match hash_file(full, algorithm, &mut read) {
+                Ok(Some(hash)) => hashed(path, Ok(hash)),
+                Ok(None) => return false,
+                Err(e) => hashed(path, Err(e)),
+            }
+            progress.done_files += 1;
+            let update = progress.update();
+            sink.progress(&update);
+            // an empty file reads nothing, cancel is checked here too
+            if !sink.keep_going(&update) {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn create(root: &Path, manifest: &Path, algorithm: Algorithm, sink: &dyn TaskSink) -> Result<(), AppError> {
+        let Some(files) = Self::files(root, manifest, sink)? else {
+            sink.line("已取消");
+            return Ok(());
+        };
+        let mut text = String::new();
+        let mut failed = 0;
+        let done = Self::hash_all(&files, algorithm, sink, &mut |path, hash| match hash {
+            Ok(hash) => {
+                let line = manifest_line(&hash, path);
+                sink.line(&line);
+                text.push_str(&line);
+                text.push('\n');
+            },
+            Err(e) => {
+                failed += 1;
+                sink.error_line(&format!("無法讀取 {}: {}", path, e));
+            },
+        });
+        if !done {
+            sink.line("已取消，沒有寫入清單");
+            return Ok(());
+        }
+        // a manifest is never left half written
+        let partial = Self::partial(manifest);
+        fs::write(&partial, text)
+            .and_then(|_| fs::rename(&partial, manifest))
+            .with_context(|| format!("寫入 {}", manifest.display()))?;
+        sink.line(&format!("{} 個檔案寫入 {}", files.len() - failed, manifest.display()));
+        match failed {
+            0 => Ok(()),
+            n => Err(AppError::task(format!("{} 個檔案無法讀取", n))),
+        }
+    }
+
+    fn verify(root: &Path, manifest: &Path, algorithm: Algorithm, sink: &dyn TaskSink) -> Result<(), AppError> {
+        let text = fs::read_to_string(manifest).with_context(|| format!("讀取 {}", manifest.display()))?;
+        let (entries, bad) = parse_manifest(&text, algorithm);
+        for line in &bad {
+            sink.error_line(&format!("清單第 {} 行不是 {} 的格式", line, algorithm.name()));
+        }
+        let Some(files) = Self::files(root, manifest, sink)? else {
+            sink.line("已取消");
+            return Ok(());
+        };
+        let expected: BTreeMap<&str, &str> = entries.iter().map(|e| (e.path.as_str(), e.hash.as_str())).collect();
+        let present: BTreeSet<&str> = files.iter().map(|f| f.0.as_str()).collect();
+        let listed: Vec<Found> = files.iter().filter(|f| expected.contains_key(f.0.as_str())).cloned().collect();
+        let (mut matched, mut mismatched) = (0, 0);
+        let done = Self::hash_all(&listed, algorithm, sink, &mut |path, hash| match hash {
+            Ok(hash) if expected.get(path) == Some(&hash.as_str()) => matched += 1,
+            Ok(_) => {
+                mismatched += 1;
+                sink.error_line(&format!("不符: {}", path));
+            },
+            Err(e) => {
+                mismatched += 1;
+                sink.error_line(&format!("無法讀取 {}: {}", path, e));
+            },
+        });
+        if !done {
+            sink.line("已取消");
+            return Ok(());
+        }
+        let missing: Vec<_> = expected.keys().filter(|p| !present.contains(*p)).collect();
+        for path in &missing {
+            sink.error_line(&format!("缺少: {}", path));
+        }
+        let extra: Vec<_> = present.iter().filter(|p| !expected.contains_key(*p)).collect();
+        for path in &extra {
+            sink.error_line(&format!("多出: {}", path));
+        }
+        sink.line(&format!(
+            "相符 {}，不符 {}，缺少 {}，多出 {}",
+            matched, mismatched, missing.len(), extra.len()
+        ));
+        match mismatched + missing.len() + extra.len() + bad.len() {
+            0 => Ok(()),
+            _ => Err(AppError::task(format!("{} 和資料夾不符", manifest.display()))),
+        }
+    }
+}
+
+impl Default for Checksum {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Task for Checksum {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn run(&self, path: &Path, sink: &dyn TaskSink) -> Result<(), AppError> {
+        let options = self.options.clone();
+        let root = match path.as_os_str().is_empty() {
+            true => PathBuf::from("."),
+            false => path.to_path_buf(),
+        };
+        let manifest = root.join(
+            options.manifest.unwrap_or_else(|| PathBuf::from(options.algorithm.manifest_name()))
+        );
+        match options.verify {
+            true => {
+                sink.line(&format!("用 {} 驗證 {}", manifest.display(), root.display()));
+                Self::verify(&root, &manifest, options.algorithm, sink)
+            },
+            false => {
+                sink.line(&format!("計算 {} 的 {}", root.display(), options.algorithm.name()));
+                Self::create(&root, &manifest, options.algorithm, sink)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tasks::testing::{temp_folder, Recorder};
+
+    const ABC: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    #[test]
+    fn manifest_lines() {
+        assert_eq!(manifest_line(ABC, "a/b.txt"), format!("{}  a/b.txt", ABC));
+        assert_eq!(manifest_line("00", "a\\b\nc"), "\\00  a\\\\b\\nc");
+        let text = format!(
+            "{h}  a.txt\r\n\n{h} *./bin/b.dat\n\\{h}  x\\\\y\\nz\nnot a line\n{short}  c\n{h} c\n",
+            h = ABC.to_uppercase(),
+            short = &ABC[..40],
+        );
+        let (entries, bad) = parse_manifest(&text, Algorithm::Sha256);
+        let paths: Vec<_> = entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["a.txt", "bin/b.dat", "x\\y\nz"]);
+        assert!(entries.iter().all(|e| e.hash == ABC));
+        assert_eq!(bad, [5, 6, 7]);
+        let (entries, bad) = parse_manifest(&format!("{}  c\n", &ABC[..40]), Algorithm::Sha1);
+        assert_eq!((entries.len(), bad.len()), (1, 0));
+    }
+
+    fn folder(name: &str) -> PathBuf {
+        let root = temp_folder(&format!("sum-{}", name));
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("abc.txt"), "abc").unwrap();
+        fs::write(root.join("empty"), "").unwrap();
+        fs::write(root.join("sub/big.bin"), vec![7u8; CHUNK + 10]).unwrap();
+        root
+    }
+
+    fn run(root: &Path, options: ChecksumOptions) -> (Result<(), AppError>, Recorder) {
+        let sink = Recorder::default();
+        let result = Checksum::with_options(options).run(root, &sink);
+        (result, sink)
+    }
+
+    #[test]
+    fn hashes_in_pieces() {
+        let root = folder("hash");
+        let mut pieces = Vec::new();
+        let hash = hash_file(&root.join("sub/big.bin"), Algorithm::Sha256, &mut |n| {
+            pieces.push(n);
+            true
+        });
+        assert_eq!(pieces, [CHUNK as u64, 10]);
+        let mut direct = Algorithm::Sha256.hasher();
+        direct.update(&vec![7u8; CHUNK + 10]);
+        assert_eq!(hash.unwrap(), Some(digest::hex(&direct.finish())));
+        let stopped = hash_file(&root.join("sub/big.bin"), Algorithm::Sha256, &mut |_| false).unwrap();
+        assert_eq!(stopped, None);
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn writes_then_verifies() {
+        let root = folder("verify");
+        let (result, sink) = run(&root, ChecksumOptions::default());
+        result.unwrap();
+        let manifest = fs::read_to_string(root.join("SHA256SUMS")).unwrap();
+        let lines: Vec<_> = manifest.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], format!("{}  abc.txt", ABC));
+        assert!(lines[2].ends_with("  sub/big.bin"));
+        assert_eq!(sink.progress.borrow().last().map(|p| (p.done, p.total, p.fraction)), Some((3, 3, Some(1.0))));
+
+        let verify = ChecksumOptions { verify: true, ..Default::default() };
+        let (result, sink) = run(&root, verify.clone());
+        result.unwrap();
+        assert_eq!(sink.lines.borrow().last().unwrap(), "相符 3，不符 0，缺少 0，多出 0");
+
+        fs::write(root.join("abc.txt"), "abd").unwrap();
+        fs::remove_file(root.join("empty")).unwrap();
+        fs::write(root.join("sub/new"), "").unwrap();
+        let (result, sink) = run(&root, verify);
+        assert!(result.is_err());
+        assert_eq!(*sink.errors.borrow(), ["不符: abc.txt", "缺少: empty", "多出: sub/new"]);
+        assert_eq!(sink.lines.borrow().last().unwrap(), "相符 1，不符 1，缺少 1，多出 1");
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn other_algorithms_and_manifests() {
+        let root = folder("md5");
+        let options = ChecksumOptions {
+            algorithm: Algorithm::Md5,
+            manifest: Some(PathBuf::from("sub/list.md5")),
+            verify: false,
+        };
+        run(&root, options.clone()).0.unwrap();
+        let manifest = fs::read_to_string(root.join("sub/list.md5")).unwrap();
+        assert!(manifest.starts_with("900150983cd24fb0d6963f7d28e17f72  abc.txt\n"));
+        // the manifest doesn't list itself
+        assert!(!manifest.contains("list.md5"));
+        assert!(!root.join("sub/list.md5.partial").exists());
+        // nor what's left of a write that failed
+        fs::write(root.join("sub/list.md5.partial"), "").unwrap();
+        run(&root, ChecksumOptions { verify: true, ..options }).0.unwrap();
+
+        let (result, _) = run(&root, ChecksumOptions { verify: true, ..Default::default() });
+        assert!(result.unwrap_err().to_string().starts_with("讀取 "));
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn cancel_writes_nothing() {
+        let root = folder("cancel");
+        let sink = Recorder { stop_after: Some(2), ..Default::default() };
+        Checksum::with_options(ChecksumOptions::default()).run(&root, &sink).unwrap();
+        assert!(!root.join("SHA256SUMS").exists());
+        assert!(sink.lines.borrow().last().unwrap().starts_with("已取消"));
+        let _ = fs::remove_dir_all(root);
+    }
+}
@@ -0,0 +1,20 @@
+// build.rs include!s this file for the build date, so it stays free of
+// anything from the crate
+
+/// The (year, month, day) `days` after 1970-01-01, Howard Hinnant's
+/// algorithm, see http://howardhinnant.github.io/date_algorithms.html
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = match mp < 10 {
+        true => mp + 3,
+        false => mp - 9,
+    };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
@@ -0,0 +1,321 @@
+use std::path::PathBuf;
+
+use crate::digest::Algorithm;
+use crate::error::AppError;
+use crate::run_command::RunCommand;
+use crate::tasks::{self, TaskOptions};
+
+// exit codes of the process
+pub const EXIT_OK: i32 = 0;
+/// The task ran and failed, or the window couldn't be opened.
+pub const EXIT_FAILED: i32 = 1;
+/// Bad command line.
+pub const EXIT_USAGE: i32 = 2;
+/// A panic, the code the rust runtime uses.
+pub const EXIT_PANIC: i32 = 101;
+
+pub const USAGE: &str = "\
+usage: windows-app [<path>] [options]
+       windows-app --no-gui --run <task> [--path <dir>] [--output text|json]
+
+options:
+  <path>, --path <dir>  folder the task works on
+  --task <task>         task GO runs
+  --command <line>      command line the command task runs, implies --task command
+  --include <glob>      the scan task only counts matching files, repeatable
+  --exclude <glob>      the scan task skips matching files and folders, repeatable
+  --max-depth <n>       levels of folders the scan task goes down
+  --follow-symlinks     the scan task follows symbolic links
+  --algorithm <name>    sha256, sha1 or md5 for the checksum task, sha256 by default
+  --manifest <file>     manifest of the checksum task, in the folder unless absolute
+  --verify              the checksum task checks the folder against the manifest
+  --autostart           start the task once the window is up
+  --run <task>          same as --task <task> --autostart
+  --minimized           start with the window minimized
+  --single-instance     hand the command line to a window already open
+  --ipc                 serve JSON-RPC for scripts on \\\\.\\pipe\\windows-app
+  --no-gui              run the task in the console and exit
+  --output text|json    how --no-gui prints progress, json is one object per line
+  --log-level <level>   error, warn, info, debug or trace
+  --log-task-output     copy the task output into the diagnostics log
+  -h, --help            show this text
+";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Output {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Options {
+    pub path: Option<PathBuf>,
+    pub task: Option<String>,
+    pub task_options: TaskOptions,
+    pub autostart: bool,
+    pub minimized: bool,
+    pub single_instance: bool,
+    pub ipc: bool,
+    pub output: Output,
+}
+
+/// What the command line asks for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Gui(Options),
+    /// `task` is always set.
+    Headless(Options),
+    Help,
+}
+
+/// Parses the arguments after the program name.
+pub fn parse(args: &[String]) -> Result<Command, AppError> {
+    let mut options = Options::default();
+    let mut headless = false;
+    let mut output = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        // --name=value and --name value are the same
+        let (name, inline) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name, Some(value.to_string())),
+            _ => (arg.as_str(), None),
+        };
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| args.next().cloned())
+                .ok_or_else(|| AppError::config(format!("{} needs a value", name)))
+        };
+        match name {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--no-gui" => headless = true,
+            "--path" => set_path(&mut options, value()?)?,
+            "--autostart" => options.autostart = true,
+            "--minimized" => options.minimized = true,
+            "--single-instance" => options.single_instance = true,
+            "--ipc" => options.ipc = true,
+            "--run" | "--task" => {
+                options.autostart |= name == "--run";
+                let task = value()?;
+                if !tasks::is_known(&task) {
+                    return Err(AppError::config(format!(
+                        "unknown task {}, there is {}", task, tasks::names().join(", ")
+                    )));
+                }
+                options.task = Some(task);
+            },
+            "--command" => options.task_options.command = Some(value()?),
+            "--include" => options.task_options.scan.include.push(value()?),
+            "--exclude" => options.task_options.scan.exclude.push(value()?),
+            "--max-depth" => {
+                let depth = value()?;
+                let depth = depth
+                    .parse()
+                    .map_err(|_| AppError::config(format!("--max-depth needs a number, not {}", depth)))?;
+                options.task_options.scan.max_depth = Some(depth);
+            },
+            "--follow-symlinks" => options.task_options.scan.follow_symlinks = true,
+            "--algorithm" => {
+                let name = value()?;
+                options.task_options.checksum.algorithm = Algorithm::parse(&name).ok_or_else(|| {
+                    AppError::config(format!("unknown algorithm {}, there is sha256, sha1, md5", name))
+                })?;
+            },
+            "--manifest" => options.task_options.checksum.manifest = Some(PathBuf::from(value()?)),
+            "--verify" => options.task_options.checksum.verify = true,
+            "--output" => {
+                output = Some(match value()?.as_str() {
+                    "text" => Output::Text,
+                    "json" => Output::Json,
+                    other => {
+                        return Err(AppError::config(format!("unknown output {}", other)));
+                    },
+                });
+            },
+            // read by diagnostics::LogConfig
+            "--log-level" => {
+                value()?;
+            },
+            "--log-task-output" => {},
+            other if other.starts_with('-') && other.len() > 1 => {
+                return Err(AppError::config(format!("unknown argument {}", other)));
+            },
+            // what a shortcut or "Open with" passes
+            path => set_path(&mut options, path.to_string())?,
+        }
+    }
+    match (&options.task, &options.task_options.command) {
+        (None, Some(_)) => options.task = Some(RunCommand::NAME.to_string()),
+        (Some(task), None) if task == RunCommand::NAME => {
+            return Err(AppError::config("the command task needs --command <line>"));
+        },
+        _ => {},
+    }
+    match (headless, output) {
+        (true, output) => {
+            if options.task.is_none() {
+                return Err(AppError::config("--no-gui needs --run <task>"));
+            }
+            if options.minimized {
+                return Err(AppError::config("--minimized only applies to the window"));
+            }
+            if options.single_instance {
+                return Err(AppError::config("--single-instance only applies to the window"));
+            }
+            if options.ipc {
+                return Err(AppError::config("--ipc only applies to the window"));
+            }
+            options.output = output.unwrap_or_default();
+            Ok(Command::Headless(options))
+        },
+        (false, Some(_)) => Err(AppError::config("--output only applies with --no-gui")),
+        (false, None) => Ok(Command::Gui(options)),
+    }
+}
+
+fn set_path(options: &mut Options, path: String) -> Result<(), AppError> {
+    if options.path.is_some() {
+        return Err(AppError::config(format!("more than one path, {}", path)));
+    }
+    options.path = Some(PathBuf::from(path));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checksum::ChecksumOptions;
+    use crate::scan::ScanOptions;
+
+    fn args(a: &[&str]) -> Vec<String> {
+        a.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn error(a: &[&str]) -> String {
+        parse(&args(a)).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn headless_run() {
+        let command = parse(&args(&["--path", "C:\\data", "--run", "demo", "--no-gui", "--output=json"]));
+        assert_eq!(
+            command.unwrap(),
+            Command::Headless(Options {
+                path: Some(PathBuf::from("C:\\data")),
+                task: Some("demo".into()),
+                autostart: true,
+                output: Output::Json,
+                ..Default::default()
+            })
+        );
+        let command = parse(&args(&["--no-gui", "--run=demo", "--log-level", "debug"])).unwrap();
+        assert_eq!(
+            command,
+            Command::Headless(Options {
+                task: Some("demo".into()),
+                autostart: true,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn gui_prefill() {
+        let command = parse(&args(&["C:\\My Files", "--autostart", "--task", "demo", "--minimized"]));
+        assert_eq!(
+            command.unwrap(),
+            Command::Gui(Options {
+                path: Some(PathBuf::from("C:\\My Files")),
+                task: Some("demo".into()),
+                autostart: true,
+                minimized: true,
+                ..Default::default()
+            })
+        );
+        let command = parse(&args(&["--run", "demo"])).unwrap();
+        assert!(matches!(command, Command::Gui(Options { autostart: true, .. })));
+        let command = parse(&args(&["--single-instance", "data", "--ipc"])).unwrap();
+        assert!(matches!(command, Command::Gui(Options { single_instance: true, ipc: true, path: Some(_), .. })));
+        // a lone dash is a path, not a flag
+        let command = parse(&args(&["-"])).unwrap();
+        assert!(matches!(command, Command::Gui(Options { path: Some(_), .. })));
+    }
+
+    #[test]
+    fn command_line_of_the_command_task() {
+        let command = parse(&args(&["--no-gui", "--autostart", "--command", "make -j4"])).unwrap();
+        assert_eq!(
+            command,
+            Command::Headless(Options {
+                task: Some("command".into()),
+                task_options: TaskOptions { command: Some("make -j4".into()), ..Default::default() },
+                autostart: true,
+                ..Default::default()
+            })
+        );
+        let command = parse(&args(&["--command=ls", "--task", "demo"])).unwrap();
+        assert!(matches!(command, Command::Gui(Options { task: Some(t), task_options: TaskOptions { command: Some(_), .. }, .. }) if t == "demo"));
+    }
+
+    #[test]
+    fn scan_options() {
+        let command = parse(&args(&[
+            "--no-gui", "--run", "scan", "--include", "*.rs", "--include=*.toml",
+            "--exclude", "target", "--max-depth", "3", "--follow-symlinks",
+        ]));
+        let Ok(Command::Headless(options)) = command else {
+            panic!("{:?}", command);
+        };
+        assert_eq!(
+            options.task_options.scan,
+            ScanOptions {
+                include: vec!["*.rs".into(), "*.toml".into()],
+                exclude: vec!["target".into()],
+                max_depth: Some(3),
+                follow_symlinks: true,
+            }
+        );
+        assert_eq!(error(&["--max-depth", "deep"]), "bad configuration: --max-depth needs a number, not deep");
+    }
+
+    #[test]
+    fn checksum_options() {
+        let command = parse(&args(&["--no-gui", "--run", "checksum", "--algorithm", "MD5", "--verify", "--manifest=a.md5"]));
+        let Ok(Command::Headless(options)) = command else {
+            panic!("{:?}", command);
+        };
+        assert_eq!(
+            options.task_options.checksum,
+            ChecksumOptions { algorithm: Algorithm::Md5, manifest: Some(PathBuf::from("a.md5")), verify: true }
+        );
+        assert_eq!(error(&["--algorithm", "crc"]), "bad configuration: unknown algorithm crc, there is sha256, sha1, md5");
+    }
+
+    #[test]
+    fn gui_by_default() {
+        assert_eq!(parse(&[]).unwrap(), Command::Gui(Options::default()));
+        assert_eq!(parse(&args(&["--log-task-output", "-h"])).unwrap(), Command::Help);
+    }
+
+    #[test]
+    fn bad_command_lines() {
+        assert_eq!(error(&["--no-gui"]), "bad configuration: --no-gui needs --run <task>");
+        assert_eq!(error(&["--run", "fly"]), "bad configuration: unknown task fly, there is demo, command, scan, checksum");
+        assert_eq!(error(&["--run", "command"]), "bad configuration: the command task needs --command <line>");
+        assert_eq!(error(&["--path"]), "bad configuration: --path needs a value");
+        assert_eq!(error(&["--output", "json"]), "bad configuration: --output only applies with --no-gui");
+        assert_eq!(error(&["--no-gui", "--run", "demo", "--output", "xml"]), "bad configuration: unknown output xml");
+        assert_eq!(error(&["-x"]), "bad configuration: unknown argument -x");
+        assert_eq!(error(&["a", "--path", "b"]), "bad configuration: more than one path, b");
+        assert_eq!(
+            error(&["--no-gui", "--run", "demo", "--minimized"]),
+            "bad configuration: --minimized only applies to the window"
+        );
+        assert_eq!(
+            error(&["--no-gui", "--run", "demo", "--single-instance"]),
+            "bad configuration: --single-instance only applies to the window"
+        );
+        assert_eq!(error(&["--no-gui", "--run", "demo", "--ipc"]), "bad configuration: --ipc only applies to the window");
+    }
+}
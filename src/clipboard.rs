@@ -0,0 +1,394 @@
+use std::io;
+
+// size of DROPFILES: pFiles, pt.x, pt.y, fNC, fWide
+const DROPFILES_SIZE: usize = 20;
+const HTML_VERSION: &str = "Version:0.9\r\n";
+const HTML_START_FRAGMENT: &str = "<!--StartFragment-->";
+const HTML_END_FRAGMENT: &str = "<!--EndFragment-->";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Format {
+    /// CF_UNICODETEXT, nul terminated utf-16le.
+    UnicodeText,
+    /// CF_HDROP, a DROPFILES header followed by wide paths.
+    FileList,
+    /// The registered "HTML Format", utf-8 with the CF_HTML header.
+    Html,
+}
+
+/// Raw access to the clipboard, the encoding of each format is done by
+/// [`Clipboard`] so it doesn't depend on the platform.
+pub trait ClipboardBackend {
+    /// Replaces the clipboard content with all `items` at once.
+    fn write(&mut self, items: &[(Format, Vec<u8>)]) -> io::Result<()>;
+    fn read(&mut self, format: Format) -> io::Result<Option<Vec<u8>>>;
+}
+
+pub struct Clipboard<B> {
+    backend: B,
+}
+
+impl<B: ClipboardBackend> Clipboard<B> {
+    pub fn new(backend: B) -> Self {
+        Self { backend }
+    }
+
+    pub fn set_text(&mut self, text: &str) -> io::Result<()> {
+        self.backend.write(&[(Format::UnicodeText, encode_text(text))])
+    }
+
+    /// Sets the text together with an html rendering of it, so rich
+    /// editors keep the line layout.
+    pub fn set_text_and_html(&mut self, text: &str, html_fragment: &str) -> io::Result<()> {
+        self.backend.write(&[
+            (Format::UnicodeText, encode_text(text)),
+            (Format::Html, encode_html(html_fragment, None)),
+        ])
+    }
+
+    pub fn set_files(&mut self, paths: &[String]) -> io::Result<()> {
+        self.backend.write(&[(Format::FileList, encode_file_list(paths))])
+    }
+
+    pub fn text(&mut self) -> io::Result<Option<String>> {
+        Ok(self.backend.read(Format::UnicodeText)?.map(|b| decode_text(&b)))
+    }
+
+    pub fn files(&mut self) -> io::Result<Option<Vec<String>>> {
+        Ok(self.backend.read(Format::FileList)?.and_then(|b| decode_file_list(&b)))
+    }
+
+    pub fn html(&mut self) -> io::Result<Option<String>> {
+        Ok(self.backend.read(Format::Html)?.and_then(|b| decode_html(&b)))
+    }
+}
+
+/// Turns lone `\n` and `\r` into `\r\n`, what windows controls expect.
+pub fn normalize_crlf(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\r' => {
+                if chars.peek() == Some(&'\n') {
+                    chars.next();
+                }
+                out.push_str("\r\n");
+            },
+            '\n' => out.push_str("\r\n"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+pub fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Picks a path out of pasted text: the first non-empty line without the
+/// quotes explorer's "copy as path" adds.
+pub fn path_from_text(text: &str) -> Option<String> {
+    let line = text.lines().map(str::trim).find(|l| !l.is_empty())?;
+    let line = line.strip_prefix('"')
+        .and_then(|l| l.strip_suffix('"'))
+        .unwrap_or(line);
+    Some(line.to_string())
+}
+
+pub fn encode_text(text: &str) -> Vec<u8> {
+    normalize_crlf(text)
+        .encode_utf16()
+        .chain(std::iter::once(0))
+        .flat_map(u16::to_le_bytes)
+        .collect()
+}
+
+pub fn decode_text(bytes: &[u8]) -> String {
+    let wide: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .take_while(|c| *c != 0)
+        .collect();
+    String::from_utf16_lossy(&wide)
+}
+
+pub fn encode_file_list(paths: &[String]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(DROPFILES_SIZE);
+    bytes.extend((DROPFILES_SIZE as u32).to_le_bytes()); // pFiles
+    bytes.extend(0i32.to_le_bytes()); // pt.x
+    bytes.extend(0i32.to_le_bytes()); // pt.y
+    bytes.extend(0i32.to_le_bytes()); // fNC
+    bytes.extend(1i32.to_le_bytes()); // fWide
+    for path in paths {
+        bytes.extend(path.encode_utf16().chain(std::iter::once(0)).flat_map(u16::to_le_bytes));
+    }
+    // the list ends with an empty string
+    bytes.extend(0u16.to_le_bytes());
+    bytes
+}
+
+pub fn decode_file_list(bytes: &[u8]) -> Option<Vec<String>> {
+    if bytes.len() < DROPFILES_SIZE {
+        return None;
+    }
+    let field = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+    let offset = field(0) as usize;
+    let wide = field(16) != 0;
+    let list = bytes.get(offset..)?;
+    let paths: Vec<String> = match wide {
+        true => {
+            let units: Vec<u16> = list
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .collect();
+            units
+                .split(|c| *c == 0)
+                .take_while(|p| !p.is_empty())
+                .map(String::from_utf16_lossy)
+                .collect()
+        },
+        // ansi lists only come from old programs, decode them lossily
+        false => list
+            .split(|c| *c == 0)
+            .take_while(|p| !p.is_empty())
+            .map(|p| String::from_utf8_lossy(p).into_owned())
+            .collect(),
+    };
+    Some(paths)
+}
+
+/// Wraps an html fragment with the CF_HTML header, whose offsets count
+/// bytes from the start of the utf-8 data.
+pub fn encode_html(fragment: &str, source_url: Option<&str>) -> Vec<u8> {
+    // offsets are written with a fixed width so the header length is
+    // known before they are
+    let header_template = |start_html: usize, end_html: usize, start_frag: usize, end_frag: usize| {
+        let mut header = String::from(HTML_VERSION);
+        header.push_str(&format!("StartHTML:{:010}\r\n", start_html));
+        header.push_str(&format!("EndHTML:{:010}\r\n", end_html));
+        header.push_str(&format!("StartFragment:{:010}\r\n", start_frag));
+        header.push_str(&format!("EndFragment:{:010}\r\n", end_frag));
+        if let Some(url) = source_url {
+            header.push_str(&format!("SourceURL:{}\r\n", url));
+        }
+        header
+    };
+    let prefix = format!("<html><body>\r\n{}", HTML_START_FRAGMENT);
+    let suffix = format!("{}\r\n</body></html>", HTML_END_FRAGMENT);
+    let header_len = header_template(0, 0, 0, 0).len();
+    let start_html = header_len;
+    let start_frag = start_html + prefix.len();
+    let end_frag = start_frag + fragment.len();
+    let end_html = end_frag + suffix.len();
+    let mut data = header_template(start_html, end_html, start_frag, end_frag);
+    data.push_str(&prefix);
+    data.push_str(fragment);
+    data.push_str(&suffix);
+    let mut bytes = data.into_bytes();
+    bytes.push(0);
+    bytes
+}
+
+/// Extracts the fragment of CF_HTML data using the offsets in its header.
+pub fn decode_html(bytes: &[u8]) -> Option<String> {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    let bytes = &bytes[..end];
+    let header = String::from_utf8_lossy(&bytes[..bytes.len().min(512)]);
+    let offset = |key: &str| -> Option<usize> {
+        header.lines()
+            .find_map(|l| l.strip_prefix(key))
+            .and_then(|v| v.trim().parse().ok())
+    };
+    let start = offset("StartFragment:")?;
+    let end = offset("EndFragment:")?;
+    let fragment = bytes.get(start..end)?;
+    Some(String::from_utf8_lossy(fragment).into_owned())
+}
+
+#[cfg(windows)]
+pub use win32::Win32Clipboard;
+
+#[cfg(windows)]
+mod win32 {
+    use std::io;
+    use windows::core::*;
+    use windows::Win32::{
+        Foundation::*,
+        System::{
+            DataExchange::*,
+            Memory::*,
+        },
+    };
+    use super::{ClipboardBackend, Format};
+
+    const CF_UNICODETEXT: u32 = 13;
+    const CF_HDROP: u32 = 15;
+
+    pub struct Win32Clipboard {
+        owner: HWND,
+    }
+
+    impl Win32Clipboard {
+        pub fn new(owner: HWND) -> Self {
+            Self { owner }
+        }
+
+        fn format_id(format: Format) -> u32 {
+            match format {
+                Format::UnicodeText => CF_UNICODETEXT,
+                Format::FileList => CF_HDROP,
+                Format::Html => unsafe { RegisterClipboardFormatW(w!("HTML Format")) },
+            }
+        }
+    }
+
+    // closes the clipboard on every return path
+    struct OpenGuard;
+
+    impl OpenGuard {
+        fn open(owner: HWND) -> Result<Self> {
+            unsafe { OpenClipboard(owner)? };
+            Ok(OpenGuard)
+        }
+    }
+
+    impl Drop for OpenGuard {
+        fn drop(&mut self) {
+            unsafe {
+                let _ = CloseClipboard();
+            }
+        }
+    }
+
+    impl ClipboardBackend for Win32Clipboard {
+        fn write(&mut self, items: &[(Format, Vec<u8>)]) -> io::Result<()> {
+            unsafe {
+                let _guard = OpenGuard::open(self.owner)?;
+                EmptyClipboard()?;
+                for (format, data) in items {
+                    let mem = GlobalAlloc(GMEM_MOVEABLE, data.len())?;
+                    let ptr = GlobalLock(mem) as *mut u8;
+                    if ptr.is_null() {
+                        let _ = GlobalFree(mem);
+                        return Err(Error::from_win32().into());
+                    }
+                    std::ptr::copy_nonoverlapping(data.as_ptr(), ptr, data.len());
+                    let _ = GlobalUnlock(mem);
+                    // the clipboard owns the memory once this succeeds
+                    if let Err(e) = SetClipboardData(Self::format_id(*format), HANDLE(mem.0)) {
+                        let _ = GlobalFree(mem);
+                        return Err(e.into());
+                    }
+                }
+                Ok(())
+            }
+        }
+
+        fn read(&mut self, format: Format) -> io::Result<Option<Vec<u8>>> {
+            unsafe {
+                let id = Self::format_id(format);
+                if IsClipboardFormatAvailable(id).is_err() {
+                    return Ok(None);
+                }
+                let _guard = OpenGuard::open(self.owner)?;
+                let handle = GetClipboardData(id)?;
+                let mem = HGLOBAL(handle.0);
+                let ptr = GlobalLock(mem) as *const u8;
+                if ptr.is_null() {
+                    return Ok(None);
+                }
+                let data = std::slice::from_raw_parts(ptr, GlobalSize(mem)).to_vec();
+                let _ = GlobalUnlock(mem);
+                Ok(Some(data))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[derive(Default)]
+    struct MemoryBackend {
+        items: HashMap<Format, Vec<u8>>,
+    }
+
+    impl ClipboardBackend for MemoryBackend {
+        fn write(&mut self, items: &[(Format, Vec<u8>)]) -> io::Result<()> {
+            self.items.clear();
+            for (format, data) in items {
+                self.items.insert(*format, data.clone());
+            }
+            Ok(())
+        }
+
+        fn read(&mut self, format: Format) -> io::Result<Option<Vec<u8>>> {
+            Ok(self.items.get(&format).cloned())
+        }
+    }
+
+    #[test]
+    fn crlf_normalisation() {
+        assert_eq!(normalize_crlf("a\nb\r\nc\rd"), "a\r\nb\r\nc\r\nd");
+        assert_eq!(normalize_crlf("\r\n\n"), "\r\n\r\n");
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let mut clipboard = Clipboard::new(MemoryBackend::default());
+        clipboard.set_text("進度\n1/30").unwrap();
+        assert_eq!(clipboard.text().unwrap().as_deref(), Some("進度\r\n1/30"));
+        assert_eq!(clipboard.files().unwrap(), None);
+    }
+
+    #[test]
+    fn file_list_round_trip() {
+        let paths = vec![r"C:\data".to_string(), r"D:\資料\a.txt".to_string()];
+        let bytes = encode_file_list(&paths);
+        assert_eq!(&bytes[..4], &20u32.to_le_bytes());
+        assert_eq!(&bytes[bytes.len() - 4..], &[0, 0, 0, 0]);
+        let mut clipboard = Clipboard::new(MemoryBackend::default());
+        clipboard.set_files(&paths).unwrap();
+        assert_eq!(clipboard.files().unwrap(), Some(paths));
+        assert_eq!(decode_file_list(&bytes[..10]), None);
+    }
+
+    #[test]
+    fn html_header_offsets_point_at_the_fragment() {
+        let bytes = encode_html("<pre>a &amp; 進度</pre>", Some("about:blank"));
+        let text = String::from_utf8(bytes[..bytes.len() - 1].to_vec()).unwrap();
+        assert!(text.starts_with("Version:0.9\r\nStartHTML:0000000"));
+        let start: usize = text.lines().nth(1).unwrap()[10..].parse().unwrap();
+        assert!(text[start..].starts_with("<html>"));
+        assert!(text.ends_with("</html>"));
+        assert_eq!(decode_html(&bytes).as_deref(), Some("<pre>a &amp; 進度</pre>"));
+    }
+
+    #[test]
+    fn text_and_html_are_set_together() {
+        let mut clipboard = Clipboard::new(MemoryBackend::default());
+        clipboard.set_text_and_html("a<b", &escape_html("a<b")).unwrap();
+        assert_eq!(clipboard.text().unwrap().as_deref(), Some("a<b"));
+        assert_eq!(clipboard.html().unwrap().as_deref(), Some("a&lt;b"));
+    }
+
+    #[test]
+    fn pasted_paths_are_cleaned() {
+        assert_eq!(path_from_text("  \"C:\\data dir\"\r\n").as_deref(), Some("C:\\data dir"));
+        assert_eq!(path_from_text("\n D:\\x \nE:\\y").as_deref(), Some("D:\\x"));
+        assert_eq!(path_from_text(" \r\n"), None);
+    }
+}
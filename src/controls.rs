@@ -0,0 +1,1044 @@
+use std::{fmt, marker::PhantomData};
+
+// window and control styles from winuser.h and commctrl.h, kept here so the
+// style computation doesn't need the win32 bindings
+pub const WS_CHILD: u32 = 0x4000_0000;
+pub const WS_VISIBLE: u32 = 0x1000_0000;
+pub const WS_DISABLED: u32 = 0x0800_0000;
+pub const WS_BORDER: u32 = 0x0080_0000;
+pub const WS_VSCROLL: u32 = 0x0020_0000;
+pub const WS_HSCROLL: u32 = 0x0010_0000;
+pub const WS_TABSTOP: u32 = 0x0001_0000;
+
+pub const BS_DEFPUSHBUTTON: u32 = 0x0001;
+
+pub const ES_CENTER: u32 = 0x0001;
+pub const ES_RIGHT: u32 = 0x0002;
+pub const ES_MULTILINE: u32 = 0x0004;
+pub const ES_PASSWORD: u32 = 0x0020;
+pub const ES_AUTOVSCROLL: u32 = 0x0040;
+pub const ES_AUTOHSCROLL: u32 = 0x0080;
+pub const ES_READONLY: u32 = 0x0800;
+pub const ES_NUMBER: u32 = 0x2000;
+
+pub const PBS_SMOOTH: u32 = 0x01;
+pub const PBS_VERTICAL: u32 = 0x04;
+pub const PBS_MARQUEE: u32 = 0x08;
+
+pub const WM_SIZE: u32 = 0x0005;
+pub const WM_GETTEXTLENGTH: u32 = 0x000E;
+pub const WM_SETFONT: u32 = 0x0030;
+pub const WM_GETFONT: u32 = 0x0031;
+pub const EM_SETSEL: u32 = 0x00B1;
+pub const EM_SCROLLCARET: u32 = 0x00B7;
+pub const EM_LINESCROLL: u32 = 0x00B6;
+pub const EM_GETLINECOUNT: u32 = 0x00BA;
+pub const EM_LIMITTEXT: u32 = 0x00C5;
+pub const PBM_SETPOS: u32 = 0x0402;
+pub const PBM_SETRANGE32: u32 = 0x0406;
+pub const PBM_SETMARQUEE: u32 = 0x040A;
+pub const PBM_SETSTATE: u32 = 0x0410;
+pub const PBST_NORMAL: usize = 1;
+pub const PBST_ERROR: usize = 2;
+pub const PBST_PAUSED: usize = 3;
+pub const SB_SETPARTS: u32 = 0x0404;
+pub const SB_SETTEXTW: u32 = 0x040B;
+pub const TTM_SETMAXTIPWIDTH: u32 = 0x0418;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ControlClass {
+    Button,
+    Edit,
+    ProgressBar,
+}
+
+impl ControlClass {
+    pub fn class_name(&self) -> &'static str {
+        match self {
+            ControlClass::Button => "BUTTON",
+            ControlClass::Edit => "EDIT",
+            ControlClass::ProgressBar => "msctls_progress32",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StyleError {
+    /// 0 is what GetDlgItem and WM_COMMAND use for "no control".
+    ZeroId,
+    PasswordMultiline,
+    PasswordNumber,
+    /// vertical scrolling needs a multiline edit.
+    ScrollWithoutMultiline,
+    EmptyRange(i32, i32),
+}
+
+impl fmt::Display for StyleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StyleError::ZeroId => write!(f, "control id 0 is reserved"),
+            StyleError::PasswordMultiline => write!(f, "a password edit can't be multiline"),
+            StyleError::PasswordNumber => write!(f, "a password edit can't be numeric"),
+            StyleError::ScrollWithoutMultiline => {
+                write!(f, "vertical scrolling needs a multiline edit")
+            },
+            StyleError::EmptyRange(min, max) => write!(f, "empty progress range {}..{}", min, max),
+        }
+    }
+}
+
+impl std::error::Error for StyleError {}
+
+/// Everything CreateWindowExW and the first messages after it need.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ControlSpec {
+    pub id: usize,
+    pub class: ControlClass,
+    pub text: String,
+    pub style: u32,
+    pub ex_style: u32,
+    /// PBM_SETRANGE32 for progress bars.
+    pub range: Option<(i32, i32)>,
+}
+
+/// A child window as the typed handles see it, HWND on windows and a
+/// recording fake in the tests.
+pub trait RawControl: Copy + PartialEq + Default {
+    fn send(&self, msg: u32, wparam: usize, lparam: isize) -> isize;
+    fn text(&self) -> String;
+    fn set_text(&self, text: &str);
+    fn enable(&self, enable: bool);
+    fn set_rect(&self, x: i32, y: i32, width: i32, height: i32);
+    fn style(&self) -> u32;
+    fn set_style(&self, style: u32);
+    /// EM_REPLACESEL, the text has to outlive the call.
+    fn replace_selection(&self, text: &str);
+    /// EM_GETSEL in utf-16 units.
+    fn selection(&self) -> (usize, usize);
+    /// (left, top, right, bottom) on the screen.
+    fn window_rect(&self) -> (i32, i32, i32, i32);
+
+    /// The HFONT the control draws with, 0 for the system font.
+    fn font(&self) -> usize {
+        self.send(WM_GETFONT, 0, 0) as usize
+    }
+
+    fn set_font(&self, font: usize) {
+        // lparam 1: redraw with it at once
+        self.send(WM_SETFONT, font, 1);
+    }
+}
+
+/// What every typed handle can do.
+pub trait ControlHandle<H: RawControl> {
+    fn raw(&self) -> H;
+
+    fn text(&self) -> String {
+        self.raw().text()
+    }
+
+    fn set_text(&self, text: &str) {
+        self.raw().set_text(text)
+    }
+
+    fn enable(&self, enable: bool) {
+        self.raw().enable(enable)
+    }
+
+    fn move_to(&self, x: i32, y: i32, width: i32, height: i32) {
+        self.raw().set_rect(x, y, width, height)
+    }
+
+    fn window_rect(&self) -> (i32, i32, i32, i32) {
+        self.raw().window_rect()
+    }
+
+    fn set_font(&self, font: usize) {
+        self.raw().set_font(font)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ButtonHandle<H>(pub H);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EditHandle<H>(pub H);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProgressBarHandle<H>(pub H);
+
+/// The status bar, it isn't in the registry since it has no layout rect.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StatusBarHandle<H>(pub H);
+
+/// The tooltip window shared by the controls.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TooltipHandle<H>(pub H);
+
+impl<H: RawControl> ControlHandle<H> for ButtonHandle<H> {
+    fn raw(&self) -> H {
+        self.0
+    }
+}
+
+impl<H: RawControl> ControlHandle<H> for EditHandle<H> {
+    fn raw(&self) -> H {
+        self.0
+    }
+}
+
+impl<H: RawControl> ControlHandle<H> for ProgressBarHandle<H> {
+    fn raw(&self) -> H {
+        self.0
+    }
+}
+
+impl<H: RawControl> ControlHandle<H> for StatusBarHandle<H> {
+    fn raw(&self) -> H {
+        self.0
+    }
+}
+
+impl<H: RawControl> ControlHandle<H> for TooltipHandle<H> {
+    fn raw(&self) -> H {
+        self.0
+    }
+}
+
+impl<H: RawControl> EditHandle<H> {
+    /// Adds `line` at the end and scrolls it into view.
+    pub fn append_line(&self, line: &str) {
+        // in utf-16 units, without copying out the whole log
+        let end = self.0.send(WM_GETTEXTLENGTH, 0, 0) as usize;
+        self.select(end, end);
+        self.0.replace_selection(&format!("{}\r\n", line));
+        let lines = self.0.send(EM_GETLINECOUNT, 0, 0);
+        self.0.send(EM_LINESCROLL, 0, lines);
+    }
+
+    /// Selects `start..end` in utf-16 units.
+    pub fn select(&self, start: usize, end: usize) {
+        self.0.send(EM_SETSEL, start, end as isize);
+    }
+
+    pub fn select_all(&self) {
+        self.0.send(EM_SETSEL, 0, -1);
+    }
+
+    pub fn selection(&self) -> (usize, usize) {
+        self.0.selection()
+    }
+
+    pub fn scroll_caret(&self) {
+        self.0.send(EM_SCROLLCARET, 0, 0);
+    }
+
+    /// 0 lifts the limit to what the control can hold.
+    pub fn limit_text(&self, max: usize) {
+        self.0.send(EM_LIMITTEXT, max, 0);
+    }
+}
+
+impl<H: RawControl> ProgressBarHandle<H> {
+    pub fn set_range(&self, min: i32, max: i32) {
+        self.0.send(PBM_SETRANGE32, min as usize, max as isize);
+    }
+
+    pub fn set_pos(&self, pos: usize) {
+        self.0.send(PBM_SETPOS, pos, 0);
+    }
+
+    /// One of the PBST_* states.
+    pub fn set_state(&self, state: usize) {
+        self.0.send(PBM_SETSTATE, state, 0);
+    }
+
+    /// The marquee style can only be switched on the window itself, the
+    /// animation then has to be started with PBM_SETMARQUEE.
+    pub fn set_marquee(&self, marquee: bool) {
+        let style = self.0.style();
+        let new_style = match marquee {
+            true => style | PBS_MARQUEE,
+            false => style & !PBS_MARQUEE,
+        };
+        if new_style != style {
+            self.0.set_style(new_style);
+        }
+        self.0.send(PBM_SETMARQUEE, marquee as usize, 30);
+    }
+}
+
+impl<H: RawControl> StatusBarHandle<H> {
+    /// Puts the bar back at the bottom, after its parent was sized.
+    pub fn reposition(&self) {
+        self.0.send(WM_SIZE, 0, 0);
+    }
+
+    /// The right edge of each part, -1 for one that reaches the end.
+    pub fn set_parts(&self, edges: &[i32]) {
+        self.0.send(SB_SETPARTS, edges.len(), edges.as_ptr() as isize);
+    }
+
+    pub fn set_part_text(&self, part: usize, text: &str) {
+        let text: Vec<u16> = text.encode_utf16().chain(Some(0)).collect();
+        self.0.send(SB_SETTEXTW, part, text.as_ptr() as isize);
+    }
+}
+
+impl<H: RawControl> TooltipHandle<H> {
+    /// Longer tips wrap at `width` pixels.
+    pub fn set_max_width(&self, width: i32) {
+        self.0.send(TTM_SETMAXTIPWIDTH, 0, width as isize);
+    }
+}
+
+/// Id of a control of kind `B`, ids are what WM_COMMAND reports.
+pub struct ControlId<B> {
+    id: usize,
+    kind: PhantomData<fn() -> B>,
+}
+
+impl<B> ControlId<B> {
+    pub const fn new(id: usize) -> Self {
+        Self { id, kind: PhantomData }
+    }
+
+    pub const fn value(&self) -> usize {
+        self.id
+    }
+}
+
+impl<B> Clone for ControlId<B> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<B> Copy for ControlId<B> {}
+
+impl<B> PartialEq<usize> for ControlId<B> {
+    fn eq(&self, other: &usize) -> bool {
+        self.id == *other
+    }
+}
+
+impl<B> fmt::Debug for ControlId<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ControlId({})", self.id)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegistryError {
+    DuplicateId(usize),
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryError::DuplicateId(id) => write!(f, "control id {} is already used", id),
+        }
+    }
+}
+
+impl std::error::Error for RegistryError {}
+
+/// The child windows of a window by id, replaces GetDlgItem.
+#[derive(Clone, Debug, Default)]
+pub struct ControlRegistry<H> {
+    entries: Vec<(usize, ControlClass, H)>,
+}
+
+impl<H: RawControl> ControlRegistry<H> {
+    pub fn new() -> Self {
+        Self { entries: Vec::new() }
+    }
+
+    pub fn insert(&mut self, id: usize, class: ControlClass, raw: H) -> Result<(), RegistryError> {
+        if self.entries.iter().any(|(i, _, _)| *i == id) {
+            return Err(RegistryError::DuplicateId(id));
+        }
+        self.entries.push((id, class, raw));
+        Ok(())
+    }
+
+    /// Swaps the window of a control that was recreated, returns the old one.
+    pub fn replace(&mut self, id: usize, raw: H) -> Option<H> {
+        let entry = self.entries.iter_mut().find(|(i, _, _)| *i == id)?;
+        Some(std::mem::replace(&mut entry.2, raw))
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<H> {
+        let at = self.entries.iter().position(|(i, _, _)| *i == id)?;
+        Some(self.entries.remove(at).2)
+    }
+
+    /// The typed handle, `None` when the id is unknown or of another kind.
+    pub fn get<B: ControlBuilder>(&self, id: ControlId<B>) -> Option<B::Handle<H>> {
+        self.entries
+            .iter()
+            .find(|(i, class, _)| *i == id.value() && *class == B::CLASS)
+            .map(|(_, _, raw)| B::handle(*raw))
+    }
+
+    /// Like `get`, a null handle that ignores everything before the
+    /// control exists.
+    pub fn handle<B: ControlBuilder>(&self, id: ControlId<B>) -> B::Handle<H> {
+        self.get(id).unwrap_or_else(|| B::handle(H::default()))
+    }
+
+    pub fn raw(&self, id: usize) -> Option<H> {
+        self.entries.iter().find(|(i, _, _)| *i == id).map(|(_, _, raw)| *raw)
+    }
+
+    pub fn id_of(&self, raw: H) -> Option<usize> {
+        self.entries.iter().find(|(_, _, r)| *r == raw).map(|(i, _, _)| *i)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (usize, H)> + '_ {
+        self.entries.iter().map(|(i, _, raw)| (*i, *raw))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// A control description the window turns into a child window.
+pub trait ControlBuilder {
+    const CLASS: ControlClass;
+
+    type Handle<H>;
+
+    fn spec(&self) -> Result<ControlSpec, StyleError>;
+
+    fn handle<H>(raw: H) -> Self::Handle<H>;
+}
+
+fn base_style(visible: bool, enabled: bool) -> u32 {
+    let mut style = WS_CHILD;
+    if visible {
+        style |= WS_VISIBLE;
+    }
+    if !enabled {
+        style |= WS_DISABLED;
+    }
+    style
+}
+
+#[derive(Clone, Debug)]
+pub struct Button {
+    id: usize,
+    text: String,
+    default: bool,
+    visible: bool,
+    enabled: bool,
+}
+
+impl Button {
+    pub fn new(id: ControlId<Self>) -> Self {
+        let id = id.value();
+        Self { id, text: String::new(), default: false, visible: true, enabled: true }
+    }
+
+    pub fn text(mut self, text: &str) -> Self {
+        self.text = text.to_string();
+        self
+    }
+
+    /// Drawn with the thick border of the button Enter presses.
+    pub fn default(mut self) -> Self {
+        self.default = true;
+        self
+    }
+
+    pub fn hidden(mut self) -> Self {
+        self.visible = false;
+        self
+    }
+
+    pub fn disabled(mut self) -> Self {
+        self.enabled = false;
+        self
+    }
+}
+
+impl ControlBuilder for Button {
+    const CLASS: ControlClass = ControlClass::Button;
+
+    type Handle<H> = ButtonHandle<H>;
+
+    fn spec(&self) -> Result<ControlSpec, StyleError> {
+        if self.id == 0 {
+            return Err(StyleError::ZeroId);
+        }
+        let mut style = base_style(self.visible, self.enabled) | WS_TABSTOP;
+        if self.default {
+            style |= BS_DEFPUSHBUTTON;
+        }
+        Ok(ControlSpec {
+            id: self.id,
+            class: Self::CLASS,
+            text: self.text.clone(),
+            style,
+            ex_style: 0,
+            range: None,
+        })
+    }
+
+    fn handle<H>(raw: H) -> ButtonHandle<H> {
+        ButtonHandle(raw)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Edit {
+    id: usize,
+    text: String,
+    multiline: bool,
+    read_only: bool,
+    border: bool,
+    password: bool,
+    number: bool,
+    vscroll: bool,
+    hscroll: bool,
+    align: Align,
+    visible: bool,
+    enabled: bool,
+}
+
+impl Edit {
+    pub fn new(id: ControlId<Self>) -> Self {
+        let id = id.value();
+        Self {
+            id,
+            text: String::new(),
+            multiline: false,
+            read_only: false,
+            border: false,
+            password: false,
+            number: false,
+            vscroll: false,
+            hscroll: false,
+            align: Align::Left,
+            visible: true,
+            enabled: true,
+        }
+    }
+
+    pub fn text(mut self, text: &str) -> Self {
+        self.text = text.to_string();
+        self
+    }
+
+    pub fn multiline(mut self) -> Self {
+        self.multiline = true;
+        self
+    }
+
+    pub fn read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+
+    pub fn border(mut self) -> Self {
+        self.border = true;
+        self
+    }
+
+    pub fn password(mut self) -> Self {
+        self.password = true;
+        self
+    }
+
+    pub fn number(mut self) -> Self {
+        self.number = true;
+        self
+    }
+
+    /// Vertical scroll bar, the text follows the caret down.
+    pub fn vscroll(mut self) -> Self {
+        self.vscroll = true;
+        self
+    }
+
+    /// Horizontal scroll bar, a multiline edit stops wrapping.
+    pub fn hscroll(mut self) -> Self {
+        self.hscroll = true;
+        self
+    }
+
+    pub fn align(mut self, align: Align) -> Self {
+        self.align = align;
+        self
+    }
+
+    pub fn hidden(mut self) -> Self {
+        self.visible = false;
+        self
+    }
+
+    pub fn disabled(mut self) -> Self {
+        self.enabled = false;
+        self
+    }
+}
+
+impl ControlBuilder for Edit {
+    const CLASS: ControlClass = ControlClass::Edit;
+
+    type Handle<H> = EditHandle<H>;
+
+    fn spec(&self) -> Result<ControlSpec, StyleError> {
+        if self.id == 0 {
+            return Err(StyleError::ZeroId);
+        }
+        if self.password && self.multiline {
+            return Err(StyleError::PasswordMultiline);
+        }
+        if self.password && self.number {
+            return Err(StyleError::PasswordNumber);
+        }
+        if self.vscroll && !self.multiline {
+            return Err(StyleError::ScrollWithoutMultiline);
+        }
+        let mut style = base_style(self.visible, self.enabled);
+        if !self.read_only {
+            style |= WS_TABSTOP;
+        }
+        if self.border {
+            style |= WS_BORDER;
+        }
+        style |= match self.align {
+            Align::Left => 0,
+            Align::Center => ES_CENTER,
+            Align::Right => ES_RIGHT,
+        };
+        if self.multiline {
+            style |= ES_MULTILINE;
+        }
+        if self.read_only {
+            style |= ES_READONLY;
+        }
+        if self.password {
+            style |= ES_PASSWORD;
+        }
+        if self.number {
+            style |= ES_NUMBER;
+        }
+        if self.vscroll {
+            style |= WS_VSCROLL | ES_AUTOVSCROLL;
+        }
+        // a single line edit scrolls sideways instead of stopping at the
+        // border, a multiline one only when it doesn't wrap
+        if self.hscroll || !self.multiline {
+            style |= ES_AUTOHSCROLL;
+        }
+        if self.hscroll && self.multiline {
+            style |= WS_HSCROLL;
+        }
+        Ok(ControlSpec {
+            id: self.id,
+            class: Self::CLASS,
+            text: self.text.clone(),
+            style,
+            ex_style: 0,
+            range: None,
+        })
+    }
+
+    fn handle<H>(raw: H) -> EditHandle<H> {
+        EditHandle(raw)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ProgressBar {
+    id: usize,
+    range: (i32, i32),
+    smooth: bool,
+    vertical: bool,
+    marquee: bool,
+    visible: bool,
+}
+
+impl ProgressBar {
+    pub fn new(id: ControlId<Self>) -> Self {
+        let id = id.value();
+        Self { id, range: (0, 100), smooth: false, vertical: false, marquee: false, visible: true }
+    }
+
+    pub fn range(mut self, min: i32, max: i32) -> Self {
+        self.range = (min, max);
+        self
+    }
+
+    pub fn smooth(mut self) -> Self {
+        self.smooth = true;
+        self
+    }
+
+    pub fn vertical(mut self) -> Self {
+        self.vertical = true;
+        self
+    }
+
+    /// Starts out as a marquee, see `ProgressState::Indeterminate`.
+    pub fn marquee(mut self) -> Self {
+        self.marquee = true;
+        self
+    }
+
+    pub fn hidden(mut self) -> Self {
+        self.visible = false;
+        self
+    }
+}
+
+impl ControlBuilder for ProgressBar {
+    const CLASS: ControlClass = ControlClass::ProgressBar;
+
+    type Handle<H> = ProgressBarHandle<H>;
+
+    fn spec(&self) -> Result<ControlSpec, StyleError> {
+        if self.id == 0 {
+            return Err(StyleError::ZeroId);
+        }
+        let (min, max) = self.range;
+        if min >= max {
+            return Err(StyleError::EmptyRange(min, max));
+        }
+        let mut style = base_style(self.visible, true);
+        if self.smooth {
+            style |= PBS_SMOOTH;
+        }
+        if self.vertical {
+            style |= PBS_VERTICAL;
+        }
+        if self.marquee {
+            style |= PBS_MARQUEE;
+        }
+        Ok(ControlSpec {
+            id: self.id,
+            class: Self::CLASS,
+            text: String::new(),
+            style,
+            ex_style: 0,
+            range: Some(self.range),
+        })
+    }
+
+    fn handle<H>(raw: H) -> ProgressBarHandle<H> {
+        ProgressBarHandle(raw)
+    }
+}
+
+#[cfg(windows)]
+mod win32 {
+    use windows::core::HSTRING;
+    use windows::core::PWSTR;
+    use windows::Win32::{
+        Foundation::*,
+        UI::{
+            Controls::{TTF_IDISHWND, TTF_SUBCLASS, TTM_ADDTOOLW, TTTOOLINFOW},
+            Input::KeyboardAndMouse::EnableWindow,
+            WindowsAndMessaging::*,
+        },
+    };
+
+    use super::{RawControl, TooltipHandle};
+
+    const EM_GETSEL: u32 = 0x00B0;
+    const EM_REPLACESEL: u32 = 0x00C2;
+    // LPSTR_TEXTCALLBACKW, the tooltip asks for its text with TTN_GETDISPINFOW
+    const TEXT_CALLBACK: PWSTR = PWSTR(-1isize as *mut u16);
+
+    impl RawControl for HWND {
+        fn send(&self, msg: u32, wparam: usize, lparam: isize) -> isize {
+            unsafe { SendMessageW(*self, msg, WPARAM(wparam), LPARAM(lparam)).0 }
+        }
+
+        fn text(&self) -> String {
+            unsafe {
+                let text_length = GetWindowTextLengthW(*self) + 1;
+                let mut buffer = vec![0u16; text_length as usize];
+                let len = GetWindowTextW(*self, &mut buffer);
+                String::from_utf16_lossy(&buffer[..len as usize])
+            }
+        }
+
+        fn set_text(&self, text: &str) {
+            unsafe {
+                let _ = SetWindowTextW(*self, &HSTRING::from(text));
+            }
+        }
+
+        fn enable(&self, enable: bool) {
+            unsafe {
+                let _ = EnableWindow(*self, BOOL(enable as i32));
+            }
+        }
+
+        fn set_rect(&self, x: i32, y: i32, width: i32, height: i32) {
+            unsafe {
+                let _ = SetWindowPos(
+                    *self, None, x, y, width, height, SWP_NOZORDER | SWP_NOOWNERZORDER
+                );
+            }
+        }
+
+        fn style(&self) -> u32 {
+            unsafe { GetWindowLongPtrW(*self, GWL_STYLE) as u32 }
+        }
+
+        fn set_style(&self, style: u32) {
+            unsafe {
+                SetWindowLongPtrW(*self, GWL_STYLE, style as isize);
+            }
+        }
+
+        fn replace_selection(&self, text: &str) {
+            let text = HSTRING::from(text);
+            // wparam 1: the replacement can be undone
+            self.send(EM_REPLACESEL, 1, text.as_ptr() as isize);
+        }
+
+        fn selection(&self) -> (usize, usize) {
+            let (mut start, mut end) = (0u32, 0u32);
+            self.send(
+                EM_GETSEL,
+                &mut start as *mut _ as usize,
+                &mut end as *mut _ as isize
+            );
+            (start as usize, end as usize)
+        }
+
+        fn window_rect(&self) -> (i32, i32, i32, i32) {
+            let mut rect = RECT::default();
+            unsafe {
+                let _ = GetWindowRect(*self, &mut rect);
+            }
+            (rect.left, rect.top, rect.right, rect.bottom)
+        }
+    }
+
+    impl TooltipHandle<HWND> {
+        /// Shows the tip over `control`, `owner` is asked for its text.
+        pub fn add_tool(&self, owner: HWND, control: HWND) {
+            let info = TTTOOLINFOW {
+                cbSize: std::mem::size_of::<TTTOOLINFOW>() as u32,
+                uFlags: TTF_IDISHWND | TTF_SUBCLASS,
+                hwnd: owner,
+                uId: control.0 as usize,
+                lpszText: TEXT_CALLBACK,
+                ..Default::default()
+            };
+            self.0.send(TTM_ADDTOOLW, 0, &info as *const _ as isize);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn button_styles() {
+        let spec = Button::new(ControlId::new(2)).text("執行").default().spec().unwrap();
+        assert_eq!(spec.class.class_name(), "BUTTON");
+        assert_eq!(spec.text, "執行");
+        assert_eq!(spec.style, WS_CHILD | WS_VISIBLE | WS_TABSTOP | BS_DEFPUSHBUTTON);
+        let spec = Button::new(ControlId::new(2)).disabled().hidden().spec().unwrap();
+        assert_eq!(spec.style, WS_CHILD | WS_DISABLED | WS_TABSTOP);
+    }
+
+    #[test]
+    fn edit_styles() {
+        let path = Edit::new(ControlId::new(4)).border().spec().unwrap();
+        assert_eq!(
+            path.style,
+            WS_CHILD | WS_VISIBLE | WS_TABSTOP | WS_BORDER | ES_AUTOHSCROLL
+        );
+        let log = Edit::new(ControlId::new(3)).multiline().read_only().border().vscroll().spec().unwrap();
+        assert_eq!(
+            log.style,
+            WS_CHILD | WS_VISIBLE | WS_BORDER | WS_VSCROLL |
+                ES_MULTILINE | ES_AUTOVSCROLL | ES_READONLY
+        );
+        let nowrap = Edit::new(ControlId::new(3)).multiline().vscroll().hscroll().spec().unwrap();
+        assert_eq!(nowrap.style & (WS_HSCROLL | ES_AUTOHSCROLL), WS_HSCROLL | ES_AUTOHSCROLL);
+        let label = Edit::new(ControlId::new(6)).read_only().align(Align::Center).spec().unwrap();
+        assert_eq!(label.style & (ES_CENTER | ES_READONLY), ES_CENTER | ES_READONLY);
+        assert_eq!(label.style & WS_TABSTOP, 0);
+    }
+
+    #[test]
+    fn invalid_combinations_are_rejected() {
+        assert_eq!(Button::new(ControlId::new(0)).spec().unwrap_err(), StyleError::ZeroId);
+        assert_eq!(
+            Edit::new(ControlId::new(1)).password().multiline().spec().unwrap_err(),
+            StyleError::PasswordMultiline
+        );
+        assert_eq!(
+            Edit::new(ControlId::new(1)).password().number().spec().unwrap_err(),
+            StyleError::PasswordNumber
+        );
+        assert_eq!(
+            Edit::new(ControlId::new(1)).vscroll().spec().unwrap_err(),
+            StyleError::ScrollWithoutMultiline
+        );
+        assert_eq!(
+            ProgressBar::new(ControlId::new(5)).range(10, 10).spec().unwrap_err(),
+            StyleError::EmptyRange(10, 10)
+        );
+    }
+
+    #[test]
+    fn progress_bar_styles() {
+        let spec = ProgressBar::new(ControlId::new(5)).range(0, 1000).smooth().spec().unwrap();
+        assert_eq!(spec.class.class_name(), "msctls_progress32");
+        assert_eq!(spec.style, WS_CHILD | WS_VISIBLE | PBS_SMOOTH);
+        assert_eq!(spec.range, Some((0, 1000)));
+        let spec = ProgressBar::new(ControlId::new(5)).marquee().vertical().spec().unwrap();
+        assert_eq!(spec.style & (PBS_MARQUEE | PBS_VERTICAL), PBS_MARQUEE | PBS_VERTICAL);
+    }
+
+    use std::cell::RefCell;
+
+    thread_local! {
+        // (handle, msg, wparam, lparam) of every message sent to a fake
+        static SENT: RefCell<Vec<(u32, u32, usize, isize)>> = const { RefCell::new(Vec::new()) };
+    }
+
+    // stands in for HWND, the state lives in SENT so the handle stays Copy
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    struct Fake(u32);
+
+    impl RawControl for Fake {
+        fn send(&self, msg: u32, wparam: usize, lparam: isize) -> isize {
+            SENT.with(|s| s.borrow_mut().push((self.0, msg, wparam, lparam)));
+            match msg {
+                WM_GETTEXTLENGTH => self.text().len() as isize,
+                _ => 0,
+            }
+        }
+        fn text(&self) -> String {
+            "abc".to_string()
+        }
+        fn set_text(&self, _text: &str) {}
+        fn enable(&self, _enable: bool) {}
+        fn set_rect(&self, _x: i32, _y: i32, _width: i32, _height: i32) {}
+        fn style(&self) -> u32 {
+            WS_CHILD
+        }
+        fn set_style(&self, style: u32) {
+            self.send(0, style as usize, 0);
+        }
+        fn replace_selection(&self, _text: &str) {}
+        fn selection(&self) -> (usize, usize) {
+            (0, 0)
+        }
+        fn window_rect(&self) -> (i32, i32, i32, i32) {
+            (0, 0, 0, 0)
+        }
+    }
+
+    fn sent() -> Vec<(u32, u32, usize, isize)> {
+        SENT.with(|s| s.borrow_mut().drain(..).collect())
+    }
+
+    const RUN: ControlId<Button> = ControlId::new(2);
+    const LOG: ControlId<Edit> = ControlId::new(3);
+    const BAR: ControlId<ProgressBar> = ControlId::new(5);
+
+    #[test]
+    fn registry_hands_out_typed_handles() {
+        let mut registry = ControlRegistry::new();
+        registry.insert(RUN.value(), ControlClass::Button, Fake(1)).unwrap();
+        registry.insert(LOG.value(), ControlClass::Edit, Fake(2)).unwrap();
+        assert_eq!(registry.get(RUN), Some(ButtonHandle(Fake(1))));
+        assert_eq!(registry.get(LOG), Some(EditHandle(Fake(2))));
+        // same number, other kind
+        assert_eq!(registry.get(ControlId::<Edit>::new(2)), None);
+        assert_eq!(registry.get(BAR), None);
+        assert_eq!(registry.handle(BAR), ProgressBarHandle(Fake(0)));
+        assert_eq!(registry.id_of(Fake(2)), Some(3));
+        assert_eq!(registry.raw(2), Some(Fake(1)));
+        assert_eq!(registry.len(), 2);
+    }
+
+    #[test]
+    fn duplicate_ids_are_refused() {
+        let mut registry = ControlRegistry::new();
+        registry.insert(3, ControlClass::Edit, Fake(1)).unwrap();
+        assert_eq!(
+            registry.insert(3, ControlClass::Button, Fake(2)),
+            Err(RegistryError::DuplicateId(3))
+        );
+    }
+
+    #[test]
+    fn recreated_controls_are_replaced() {
+        let mut registry = ControlRegistry::new();
+        registry.insert(LOG.value(), ControlClass::Edit, Fake(1)).unwrap();
+        assert_eq!(registry.replace(LOG.value(), Fake(9)), Some(Fake(1)));
+        assert_eq!(registry.get(LOG), Some(EditHandle(Fake(9))));
+        assert_eq!(registry.replace(42, Fake(3)), None);
+        assert_eq!(registry.remove(LOG.value()), Some(Fake(9)));
+        assert!(registry.is_empty());
+    }
+
+    #[test]
+    fn handles_send_the_control_messages() {
+        sent();
+        let bar = ProgressBarHandle(Fake(5));
+        bar.set_pos(40);
+        bar.set_marquee(true);
+        assert_eq!(sent(), vec![
+            (5, PBM_SETPOS, 40, 0),
+            (5, 0, (WS_CHILD | PBS_MARQUEE) as usize, 0),
+            (5, PBM_SETMARQUEE, 1, 30),
+        ]);
+        let log = EditHandle(Fake(3));
+        log.append_line("x");
+        assert_eq!(sent(), vec![
+            (3, WM_GETTEXTLENGTH, 0, 0),
+            (3, EM_SETSEL, 3, 3),
+            (3, EM_GETLINECOUNT, 0, 0),
+            (3, EM_LINESCROLL, 0, 0),
+        ]);
+        log.set_font(0x2a);
+        assert_eq!(sent(), vec![(3, WM_SETFONT, 0x2a, 1)]);
+    }
+
+    #[test]
+    fn status_bar_and_tooltip_messages() {
+        sent();
+        let bar = StatusBarHandle(Fake(7));
+        bar.reposition();
+        bar.set_parts(&[100, -1]);
+        bar.set_part_text(1, "執行中");
+        let tip = TooltipHandle(Fake(8));
+        tip.set_max_width(400);
+        // the pointers differ from run to run
+        let sent: Vec<_> = sent().into_iter().map(|(h, msg, wparam, _)| (h, msg, wparam)).collect();
+        assert_eq!(sent, vec![
+            (7, WM_SIZE, 0),
+            (7, SB_SETPARTS, 2),
+            (7, SB_SETTEXTW, 1),
+            (8, TTM_SETMAXTIPWIDTH, 0),
+        ]);
+    }
+}
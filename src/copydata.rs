@@ -0,0 +1,176 @@
+use std::fmt;
+
+/// `dwData` of the WM_COPYDATA a second instance sends with its command
+/// line, "WAPA".
+pub const FORWARD_ARGS: usize = 0x5741_5041;
+
+const MAGIC: &[u8; 4] = b"WAPA";
+pub const VERSION: u16 = 1;
+/// Command lines are short, anything bigger isn't from us.
+pub const MAX_SIZE: usize = 64 * 1024;
+
+/// The command line of a second launch and the folder it was started in,
+/// so relative paths mean the same to the first instance.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Forwarded {
+    pub cwd: String,
+    pub args: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PayloadError {
+    TooLarge(usize),
+    BadMagic,
+    UnsupportedVersion(u16),
+    Truncated,
+    InvalidUtf8,
+    TrailingBytes(usize),
+}
+
+impl fmt::Display for PayloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PayloadError::TooLarge(size) => write!(f, "payload of {} bytes, at most {}", size, MAX_SIZE),
+            PayloadError::BadMagic => write!(f, "not a command line payload"),
+            PayloadError::UnsupportedVersion(v) => write!(f, "payload version {}, expected {}", v, VERSION),
+            PayloadError::Truncated => write!(f, "payload cut short"),
+            PayloadError::InvalidUtf8 => write!(f, "payload text isn't utf-8"),
+            PayloadError::TrailingBytes(n) => write!(f, "{} bytes after the payload", n),
+        }
+    }
+}
+
+impl std::error::Error for PayloadError {}
+
+// magic, version, then the cwd and the args as u32 count, each string
+// a little endian u32 length and its utf-8 bytes
+pub fn encode(forwarded: &Forwarded) -> Result<Vec<u8>, PayloadError> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    push_str(&mut bytes, &forwarded.cwd);
+    bytes.extend_from_slice(&(forwarded.args.len() as u32).to_le_bytes());
+    for arg in &forwarded.args {
+        push_str(&mut bytes, arg);
+    }
+    match bytes.len() > MAX_SIZE {
+        true => Err(PayloadError::TooLarge(bytes.len())),
+        false => Ok(bytes),
+    }
+}
+
+fn push_str(bytes: &mut Vec<u8>, s: &str) {
+    bytes.extend_from_slice(&(s.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(s.as_bytes());
+}
+
+/// Reads a payload from another process, nothing in it is trusted.
+pub fn decode(bytes: &[u8]) -> Result<Forwarded, PayloadError> {
+    if bytes.len() > MAX_SIZE {
+        return Err(PayloadError::TooLarge(bytes.len()));
+    }
+    let mut reader = Reader { bytes };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(PayloadError::BadMagic);
+    }
+    let version = u16::from_le_bytes(reader.array()?);
+    if version != VERSION {
+        return Err(PayloadError::UnsupportedVersion(version));
+    }
+    let cwd = reader.string()?;
+    let count = u32::from_le_bytes(reader.array()?) as usize;
+    // every arg takes at least its length, a bigger count is a lie
+    if count > reader.bytes.len() / 4 {
+        return Err(PayloadError::Truncated);
+    }
+    let mut args = Vec::with_capacity(count);
+    for _ in 0..count {
+        args.push(reader.string()?);
+    }
+    match reader.bytes.len() {
+        0 => Ok(Forwarded { cwd, args }),
+        n => Err(PayloadError::TrailingBytes(n)),
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], PayloadError> {
+        if n > self.bytes.len() {
+            return Err(PayloadError::Truncated);
+        }
+        let (head, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], PayloadError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn string(&mut self) -> Result<String, PayloadError> {
+        let len = u32::from_le_bytes(self.array()?) as usize;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| PayloadError::InvalidUtf8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Forwarded {
+        Forwarded {
+            cwd: "C:\\Users\\a".into(),
+            args: vec!["資料".into(), "--autostart".into(), String::new()],
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let bytes = encode(&sample()).unwrap();
+        assert_eq!(&bytes[..6], b"WAPA\x01\x00");
+        assert_eq!(decode(&bytes).unwrap(), sample());
+        let empty = Forwarded::default();
+        assert_eq!(decode(&encode(&empty).unwrap()).unwrap(), empty);
+    }
+
+    #[test]
+    fn rejects_what_is_not_ours() {
+        let bytes = encode(&sample()).unwrap();
+        assert_eq!(decode(b"MZ\x90\x00\x03\x00"), Err(PayloadError::BadMagic));
+        let mut newer = bytes.clone();
+        newer[4] = 2;
+        assert_eq!(decode(&newer), Err(PayloadError::UnsupportedVersion(2)));
+        assert_eq!(decode(&bytes[..bytes.len() - 1]), Err(PayloadError::Truncated));
+        let mut longer = bytes.clone();
+        longer.push(0);
+        assert_eq!(decode(&longer), Err(PayloadError::TrailingBytes(1)));
+        assert_eq!(decode(&[]), Err(PayloadError::Truncated));
+    }
+
+    #[test]
+    fn rejects_bad_text_and_lengths() {
+        let mut bytes = encode(&Forwarded { cwd: "ab".into(), args: Vec::new() }).unwrap();
+        // the cwd bytes follow magic, version and its length
+        bytes[10] = 0xff;
+        assert_eq!(decode(&bytes), Err(PayloadError::InvalidUtf8));
+        // a count far beyond the bytes there are
+        let mut bytes = encode(&Forwarded::default()).unwrap();
+        bytes[10..14].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(decode(&bytes), Err(PayloadError::Truncated));
+    }
+
+    #[test]
+    fn size_is_limited() {
+        let big = Forwarded { cwd: String::new(), args: vec!["x".repeat(MAX_SIZE)] };
+        assert!(matches!(encode(&big), Err(PayloadError::TooLarge(_))));
+        assert!(matches!(decode(&vec![0; MAX_SIZE + 1]), Err(PayloadError::TooLarge(_))));
+        assert!(PayloadError::TooLarge(70000).to_string().contains("70000"));
+    }
+}
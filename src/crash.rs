@@ -0,0 +1,269 @@
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::civil::civil_from_days;
+
+// lines of the log that go into a report
+const RECENT_LINES: usize = 50;
+
+/// The last lines of the log, bounded, oldest first.
+#[derive(Clone, Debug)]
+pub struct RecentLines {
+    lines: VecDeque<String>,
+    capacity: usize,
+}
+
+impl RecentLines {
+    pub const fn new(capacity: usize) -> Self {
+        Self { lines: VecDeque::new(), capacity }
+    }
+
+    pub fn push(&mut self, line: &str) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.lines.len() == self.capacity {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line.to_string());
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        self.lines.iter().cloned().collect()
+    }
+}
+
+static RECENT: Mutex<RecentLines> = Mutex::new(RecentLines::new(RECENT_LINES));
+
+/// Keeps `line` for the next crash report.
+pub fn remember(line: &str) {
+    RECENT.lock().unwrap_or_else(PoisonError::into_inner).push(line);
+}
+
+// where and how the panic of this thread happened, the hook runs before
+// unwinding so it's the only place the backtrace is still there
+struct PanicSite {
+    location: Option<String>,
+    backtrace: String,
+}
+
+thread_local! {
+    static LAST_PANIC: RefCell<Option<PanicSite>> = const { RefCell::new(None) };
+}
+
+/// Records the location and backtrace of panics for `catch`, then runs
+/// the hook that was there before.
+pub fn install_hook() {
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let site = PanicSite {
+            location: info.location().map(|l| format!("{}:{}:{}", l.file(), l.line(), l.column())),
+            backtrace: Backtrace::force_capture().to_string(),
+        };
+        LAST_PANIC.with(|last| *last.borrow_mut() = Some(site));
+        previous(info);
+    }));
+}
+
+/// The text a panic was raised with.
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    match (payload.downcast_ref::<&str>(), payload.downcast_ref::<String>()) {
+        (Some(message), _) => message.to_string(),
+        (_, Some(message)) => message.clone(),
+        _ => "unknown panic".to_string(),
+    }
+}
+
+/// Runs `f`, a panic comes back as the report of it instead of unwinding
+/// further, across a wndproc or out of a worker.
+pub fn catch<R>(context: &str, f: impl FnOnce() -> R) -> Result<R, Box<CrashReport>> {
+    panic::catch_unwind(AssertUnwindSafe(f))
+        .map_err(|payload| Box::new(CrashReport::from_panic(context, payload.as_ref())))
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CrashReport {
+    /// What was running: "window", "worker".
+    pub context: String,
+    pub message: String,
+    pub location: Option<String>,
+    pub thread: String,
+    pub backtrace: String,
+    pub version: String,
+    /// Seconds since the unix epoch.
+    pub time: u64,
+    pub recent: Vec<String>,
+}
+
+impl CrashReport {
+    pub fn from_panic(context: &str, payload: &(dyn Any + Send)) -> Self {
+        let site = LAST_PANIC.with(|last| last.borrow_mut().take());
+        let (location, backtrace) = match site {
+            Some(site) => (site.location, site.backtrace),
+            None => (None, String::new()),
+        };
+        Self {
+            context: context.to_string(),
+            message: panic_message(payload),
+            location,
+            thread: std::thread::current().name().unwrap_or("unnamed").to_string(),
+            backtrace,
+            version: version(),
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            recent: RECENT.lock().unwrap_or_else(PoisonError::into_inner).lines(),
+        }
+    }
+
+    /// One line for the error dialog.
+    pub fn summary(&self) -> String {
+        match &self.location {
+            Some(location) => format!("{} ({})", self.message, location),
+            None => self.message.clone(),
+        }
+    }
+
+    pub fn file_name(&self) -> String {
+        format!("crash-{}-{}.txt", self.time, std::process::id())
+    }
+
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        text.push_str(&format!("windows-app {}\n", self.version));
+        text.push_str(&format!("time: {}\n", format_utc(self.time)));
+        text.push_str(&format!("context: {}\n", self.context));
+        text.push_str(&format!("thread: {}\n", self.thread));
+        text.push_str(&format!("panic: {}\n", self.message));
+        if let Some(location) = &self.location {
+            text.push_str(&format!("at: {}\n", location));
+        }
+        text.push_str("\nbacktrace:\n");
+        match self.backtrace.is_empty() {
+            true => text.push_str("(not captured)\n"),
+            false => text.push_str(&self.backtrace),
+        }
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+        text.push_str(&format!("\nlast {} log lines:\n", self.recent.len()));
+        for line in &self.recent {
+            text.push_str(line);
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Writes the report to the crash folder of the application.
+    pub fn save(&self) -> io::Result<PathBuf> {
+        self.write(&crate::paths::crash_dir())
+    }
+
+    /// What the user is told, with where the report went.
+    pub fn describe(&self, saved: &io::Result<PathBuf>) -> String {
+        match saved {
+            Ok(path) => format!("{}\n\n報告: {}", self.summary(), path.display()),
+            Err(e) => format!("{}\n\n報告無法寫入: {}", self.summary(), e),
+        }
+    }
+
+    /// Writes the report into `dir`, creating it, and returns the file.
+    pub fn write(&self, dir: &Path) -> io::Result<PathBuf> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(self.file_name());
+        fs::write(&path, self.render())?;
+        Ok(path)
+    }
+}
+
+fn version() -> String {
+    match option_env!("GIT_COMMIT") {
+        Some(commit) => format!("{} ({})", env!("CARGO_PKG_VERSION"), commit),
+        None => env!("CARGO_PKG_VERSION").to_string(),
+    }
+}
+
+/// `secs` since the epoch as "YYYY-MM-DD hh:mm:ss UTC".
+pub fn format_utc(secs: u64) -> String {
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let rem = secs % 86400;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year, month, day, rem / 3600, rem / 60 % 60, rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recent_lines_keep_the_last() {
+        let mut recent = RecentLines::new(2);
+        recent.push("a");
+        recent.push("b");
+        recent.push("c");
+        assert_eq!(recent.lines(), ["b", "c"]);
+        let mut none = RecentLines::new(0);
+        none.push("a");
+        assert!(none.lines().is_empty());
+    }
+
+    #[test]
+    fn catches_panics_with_their_message() {
+        install_hook();
+        assert_eq!(catch("test", || 7), Ok(7));
+        let report = catch("worker", || -> u32 { panic!("index {} out of range", 3) }).unwrap_err();
+        assert_eq!(report.context, "worker");
+        assert_eq!(report.message, "index 3 out of range");
+        assert!(report.location.as_deref().unwrap().contains("crash.rs"));
+        assert!(!report.backtrace.is_empty());
+        let report = catch("window", || std::panic::panic_any(5u8)).unwrap_err();
+        assert_eq!(report.message, "unknown panic");
+    }
+
+    #[test]
+    fn renders_and_writes_a_report() {
+        let report = CrashReport {
+            context: "window".into(),
+            message: "called `Option::unwrap()` on a `None` value".into(),
+            location: Some("src/window.rs:10:5".into()),
+            thread: "main".into(),
+            backtrace: String::new(),
+            version: "0.1.0 (abc123)".into(),
+            time: 1_760_745_600,
+            recent: vec!["append line 1 to results".into()],
+        };
+        let text = report.render();
+        assert!(text.starts_with("windows-app 0.1.0 (abc123)\ntime: 2025-10-18 00:00:00 UTC\n"));
+        assert!(text.contains("panic: called `Option::unwrap()` on a `None` value\nat: src/window.rs:10:5\n"));
+        assert!(text.contains("(not captured)"));
+        assert!(text.ends_with("last 1 log lines:\nappend line 1 to results\n"));
+        assert_eq!(report.summary(), "called `Option::unwrap()` on a `None` value (src/window.rs:10:5)");
+        let denied = Err(io::Error::new(io::ErrorKind::PermissionDenied, "denied"));
+        assert!(report.describe(&denied).ends_with("\n\n報告無法寫入: denied"));
+
+        let dir = std::env::temp_dir().join(format!("windows-app-crash-{}", std::process::id()));
+        let saved = report.write(&dir);
+        assert!(report.describe(&saved).contains("報告: "));
+        let path = saved.unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), text);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn utc_dates() {
+        assert_eq!(format_utc(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_utc(951_782_400), "2000-02-29 00:00:00 UTC");
+        assert_eq!(format_utc(1_709_251_199), "2024-02-29 23:59:59 UTC");
+    }
+}
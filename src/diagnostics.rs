@@ -0,0 +1,376 @@
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::crash::format_utc;
+
+const LOG_NAME: &str = "windows-app";
+// rotate at 1 MiB, keep the current file and 4 older ones
+const MAX_BYTES: u64 = 1024 * 1024;
+const KEEP_FILES: usize = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error = 1,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    pub const ALL: [Level; 5] = [Level::Error, Level::Warn, Level::Info, Level::Debug, Level::Trace];
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|level| level.as_str().eq_ignore_ascii_case(s))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        }
+    }
+
+    fn from_u8(v: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|level| *level as u8 == v)
+    }
+}
+
+/// One line of the log: when, how bad, which part of the app, what
+/// happened and `key=value` fields.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Record {
+    pub time: u64,
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+    pub fields: Vec<(String, String)>,
+}
+
+impl Record {
+    pub fn new(level: Level, target: &str, message: &str) -> Self {
+        Self {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            level,
+            target: target.to_string(),
+            message: message.to_string(),
+            fields: Vec::new(),
+        }
+    }
+
+    pub fn field(mut self, key: &str, value: impl fmt::Display) -> Self {
+        self.fields.push((key.to_string(), value.to_string()));
+        self
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {:<5} {}: {}",
+            format_utc(self.time),
+            self.level.as_str().to_uppercase(),
+            self.target,
+            self.message.replace(['\r', '\n'], " ")
+        )?;
+        for (key, value) in &self.fields {
+            // quoted when it wouldn't read back as one word
+            match value.is_empty() || value.contains([' ', '"', '=', '\r', '\n']) {
+                true => write!(f, " {}={:?}", key, value)?,
+                false => write!(f, " {}={}", key, value)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `windows-app.log` in `dir`, moved to `windows-app.1.log` once it would
+/// grow past `max_bytes`, the older ones shift up and the oldest beyond
+/// `keep` files is removed.
+#[derive(Debug)]
+pub struct RotatingFile {
+    dir: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+    file: Option<File>,
+    size: u64,
+}
+
+impl RotatingFile {
+    pub fn new(dir: &Path, max_bytes: u64, keep: usize) -> Self {
+        Self { dir: dir.to_path_buf(), max_bytes, keep: keep.max(1), file: None, size: 0 }
+    }
+
+    /// The file of `index`, 0 is the one being written.
+    pub fn path(&self, index: usize) -> PathBuf {
+        match index {
+            0 => self.dir.join(format!("{}.log", LOG_NAME)),
+            i => self.dir.join(format!("{}.{}.log", LOG_NAME, i)),
+        }
+    }
+
+    /// The log files there are, newest first.
+    pub fn files(&self) -> Vec<PathBuf> {
+        (0..self.keep).map(|i| self.path(i)).filter(|p| p.exists()).collect()
+    }
+
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 2;
+        if self.file.is_none() {
+            self.open()?;
+        }
+        // a line longer than the limit still goes into a file of its own
+        if self.size > 0 && self.size + len > self.max_bytes {
+            self.rotate()?;
+        }
+        let file = match self.file.as_mut() {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+        write!(file, "{}\r\n", line)?;
+        self.size += len;
+        Ok(())
+    }
+
+    fn open(&mut self) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let file = OpenOptions::new().create(true).append(true).open(self.path(0))?;
+        self.size = file.metadata()?.len();
+        self.file = Some(file);
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file = None;
+        let oldest = self.path(self.keep - 1);
+        if oldest.exists() {
+            fs::remove_file(&oldest)?;
+        }
+        for i in (0..self.keep - 1).rev() {
+            let from = self.path(i);
+            if from.exists() {
+                fs::rename(&from, self.path(i + 1))?;
+            }
+        }
+        self.open()
+    }
+}
+
+/// How the logger is set up, from the command line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogConfig {
+    pub level: Level,
+    /// Task output lines go to the log as well.
+    pub task_output: bool,
+    pub dir: PathBuf,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self { level: Level::Info, task_output: false, dir: crate::paths::log_dir() }
+    }
+}
+
+impl LogConfig {
+    /// Takes `--log-level <level>`, `--log-level=<level>` and
+    /// `--log-task-output` out of `args`, the rest is left alone.
+    pub fn from_args(args: &[String]) -> Self {
+        let mut config = Self::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let value = match arg.strip_prefix("--log-level") {
+                Some("") => args.next().map(String::as_str),
+                Some(rest) => rest.strip_prefix('='),
+                None => {
+                    if arg == "--log-task-output" {
+                        config.task_output = true;
+                    }
+                    continue;
+                },
+            };
+            if let Some(level) = value.and_then(Level::parse) {
+                config.level = level;
+            }
+        }
+        config
+    }
+}
+
+struct Logger {
+    file: RotatingFile,
+    task_output: bool,
+}
+
+// 0 until `init`, nothing is written before
+static LEVEL: AtomicU8 = AtomicU8::new(0);
+static LOGGER: Mutex<Option<Logger>> = Mutex::new(None);
+
+pub fn init(config: &LogConfig) {
+    let logger = Logger {
+        file: RotatingFile::new(&config.dir, MAX_BYTES, KEEP_FILES),
+        task_output: config.task_output,
+    };
+    *LOGGER.lock().unwrap_or_else(PoisonError::into_inner) = Some(logger);
+    set_level(config.level);
+}
+
+/// Changes what's written from now on.
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn level() -> Option<Level> {
+    Level::from_u8(LEVEL.load(Ordering::Relaxed))
+}
+
+pub fn enabled(level: Level) -> bool {
+    level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+/// The file being written, for the user to find it.
+pub fn current_file() -> Option<PathBuf> {
+    let logger = LOGGER.lock().unwrap_or_else(PoisonError::into_inner);
+    logger.as_ref().map(|logger| logger.file.path(0))
+}
+
+pub fn write(record: &Record) {
+    if !enabled(record.level) {
+        return;
+    }
+    let mut logger = LOGGER.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(logger) = logger.as_mut() {
+        // nowhere to report it, the log is where failures go
+        let _ = logger.file.write_line(&record.to_string());
+    }
+}
+
+pub fn log(level: Level, target: &str, message: &str) {
+    if enabled(level) {
+        write(&Record::new(level, target, message));
+    }
+}
+
+pub fn error(target: &str, message: &str) {
+    log(Level::Error, target, message);
+}
+
+pub fn warn(target: &str, message: &str) {
+    log(Level::Warn, target, message);
+}
+
+pub fn info(target: &str, message: &str) {
+    log(Level::Info, target, message);
+}
+
+pub fn debug(target: &str, message: &str) {
+    log(Level::Debug, target, message);
+}
+
+/// A line of task output, logged when mirroring is on.
+pub fn task_output(line: &str) {
+    let mirror = LOGGER
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .as_ref()
+        .is_some_and(|logger| logger.task_output);
+    if mirror {
+        log(Level::Info, "task", line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("windows-app-log-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn levels() {
+        assert_eq!(Level::parse("DEBUG"), Some(Level::Debug));
+        assert_eq!(Level::parse("verbose"), None);
+        assert!(Level::Error < Level::Trace);
+        assert_eq!(Level::from_u8(0), None);
+        assert_eq!(Level::from_u8(Level::Warn as u8), Some(Level::Warn));
+    }
+
+    #[test]
+    fn record_format() {
+        let record = Record {
+            time: 0,
+            level: Level::Warn,
+            target: "worker".into(),
+            message: "stage\nfailed".into(),
+            fields: Vec::new(),
+        }
+        .field("stage", "處理")
+        .field("path", "C:\\My Files")
+        .field("empty", "");
+        assert_eq!(
+            record.to_string(),
+            "1970-01-01 00:00:00 UTC WARN  worker: stage failed stage=處理 path=\"C:\\\\My Files\" empty=\"\""
+        );
+    }
+
+    #[test]
+    fn rotates_and_keeps_the_newest() {
+        let dir = temp_dir("rotate");
+        // each line is 8 bytes with its crlf, two fit in a file
+        let mut file = RotatingFile::new(&dir, 16, 3);
+        for i in 0..7 {
+            file.write_line(&format!("line{:02}", i)).unwrap();
+        }
+        let files = file.files();
+        assert_eq!(files, [file.path(0), file.path(1), file.path(2)]);
+        assert_eq!(fs::read_to_string(file.path(0)).unwrap(), "line06\r\n");
+        assert_eq!(fs::read_to_string(file.path(1)).unwrap(), "line04\r\nline05\r\n");
+        assert_eq!(fs::read_to_string(file.path(2)).unwrap(), "line02\r\nline03\r\n");
+        assert!(!dir.join("windows-app.3.log").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn appends_to_the_file_of_the_last_run() {
+        let dir = temp_dir("append");
+        RotatingFile::new(&dir, 16, 2).write_line("line00").unwrap();
+        // the size of what's there counts toward the limit
+        let mut file = RotatingFile::new(&dir, 16, 2);
+        file.write_line("line01").unwrap();
+        file.write_line("line02").unwrap();
+        assert_eq!(fs::read_to_string(file.path(1)).unwrap(), "line00\r\nline01\r\n");
+        assert_eq!(fs::read_to_string(file.path(0)).unwrap(), "line02\r\n");
+        // an oversized line gets a file of its own
+        file.write_line(&"x".repeat(40)).unwrap();
+        assert_eq!(fs::read_to_string(file.path(0)).unwrap().len(), 42);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn config_from_args() {
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let config = LogConfig::from_args(&args(&["C:\\data", "--log-level", "debug"]));
+        assert_eq!(config.level, Level::Debug);
+        assert!(!config.task_output);
+        let config = LogConfig::from_args(&args(&["--log-level=trace", "--log-task-output"]));
+        assert_eq!((config.level, config.task_output), (Level::Trace, true));
+        // unknown levels keep the default
+        assert_eq!(LogConfig::from_args(&args(&["--log-level", "loud"])).level, Level::Info);
+        assert_eq!(LogConfig::from_args(&args(&["--log-level"])).level, Level::Info);
+    }
+}
@@ -2,14 +2,18 @@ use windows::core::*;
 use windows::Win32::{
     Foundation::*,
     UI::{
+        Controls::*,
         WindowsAndMessaging::*,
         Shell::*,
         Shell::Common::*,
     },
     System::Com::*,
+    System::Diagnostics::Debug::OutputDebugStringW,
 };
 
 use crate::*;
+use crash::CrashReport;
+use error::AppError;
 use win_str::*;
 
 pub fn pop_yesno<T>(hwnd: T, msg: &HSTRING) -> MESSAGEBOX_RESULT
@@ -48,6 +52,32 @@ where T: Param<HWND> {
     }
 }
 
+/// The one place failures reach the user: logged, written to the debugger
+/// output and shown with what was being done and why it failed.
+pub fn report_error<T>(hwnd: T, error: &AppError) -> MESSAGEBOX_RESULT
+where T: Param<HWND> {
+    diagnostics::error("app", &error.to_string());
+    unsafe {
+        OutputDebugStringW(&HSTRING::from(format!("windows-app: {}\n", error)));
+    }
+    pop_error(hwnd, &HSTRING::from(error.details()))
+}
+
+/// A panic that was caught: the report is saved and the user told where.
+pub fn report_crash<T>(hwnd: T, report: &CrashReport) -> MESSAGEBOX_RESULT
+where T: Param<HWND> {
+    let saved = report.save();
+    let record = diagnostics::Record::new(diagnostics::Level::Error, &report.context, "panic")
+        .field("message", &report.message)
+        .field("at", report.location.as_deref().unwrap_or("?"));
+    diagnostics::write(&record);
+    unsafe {
+        OutputDebugStringW(&HSTRING::from(report.render()));
+    }
+    let text = format!("程式發生未預期的錯誤，必須關閉。\n\n{}", report.describe(&saved));
+    pop_error(hwnd, &HSTRING::from(text))
+}
+
 pub fn file_open() -> Result<()> {
     unsafe {
         CoIncrementMTAUsage()?;
@@ -78,11 +108,36 @@ pub fn file_open() -> Result<()> {
     }
 }
 
-pub fn select_folder() -> Result<String> {
+// sets up COM for a file dialog on this thread and undoes it however the
+// dialog returns
+fn with_com<T>(dialog: impl FnOnce() -> Result<T>) -> Result<T> {
     unsafe {
-        // Initialize COM
-        let _ = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
-        
+        // S_FALSE when COM was set up already, which still has to be undone
+        let initialized = CoInitializeEx(None, COINIT_APARTMENTTHREADED).is_ok();
+        let result = dialog();
+        if initialized {
+            CoUninitialize();
+        }
+        result
+    }
+}
+
+// shows `dialog` modal to `owner`, an empty path when it was cancelled
+fn picked_path(dialog: &IFileDialog, owner: HWND) -> Result<String> {
+    unsafe {
+        if dialog.Show(owner).is_err() {
+            return Ok(String::new());
+        }
+        let result: IShellItem = dialog.GetResult()?;
+        let path: PWSTR = result.GetDisplayName(SIGDN_FILESYSPATH)?;
+        let s = path.to_string().unwrap_or_default();
+        CoTaskMemFree(Some(path.0 as _));
+        Ok(s)
+    }
+}
+
+pub fn select_folder(owner: HWND) -> Result<String> {
+    with_com(|| unsafe {
         let file_dialog: IFileDialog = CoCreateInstance(
             &FileOpenDialog,
             None,
@@ -96,18 +151,84 @@ pub fn select_folder() -> Result<String> {
             FOS_DONTADDTORECENT
         )?;
 
-        let result = match file_dialog.Show(None) {
-            Ok(_) => {
-                let result: IShellItem = file_dialog.GetResult()?;
-                let path: PWSTR = result.GetDisplayName(SIGDN_FILESYSPATH)?;
-                path.to_string().unwrap_or_default()
+        picked_path(&file_dialog, owner)
+    })
+}
+
+pub fn save_file(owner: HWND, default_name: &str) -> Result<String> {
+    with_com(|| unsafe {
+        let file_dialog: IFileDialog = CoCreateInstance(
+            &FileSaveDialog,
+            None,
+            CLSCTX_ALL,
+        )?;
+
+        file_dialog.SetFileTypes(&[
+            COMDLG_FILTERSPEC {
+                pszName: w!("Text files"),
+                pszSpec: w!("*.txt"),
             },
-            Err(_) => {
-                String::new()
-            }
-        };
+            COMDLG_FILTERSPEC {
+                pszName: w!("All files"),
+                pszSpec: w!("*.*"),
+            },
+        ])?;
+        file_dialog.SetDefaultExtension(w!("txt"))?;
+        file_dialog.SetFileName(&str_to_hstring(default_name))?;
 
-        CoUninitialize();
-        Ok(result)
+        picked_path(&file_dialog, owner)
+    })
+}
+
+const ID_ABOUT_COPY: i32 = 100;
+
+unsafe extern "system" fn about_callback(
+    hwnd: HWND,
+    msg: TASKDIALOG_NOTIFICATIONS,
+    wparam: WPARAM,
+    _lparam: LPARAM,
+    data: isize,
+) -> HRESULT {
+    if msg == TDN_BUTTON_CLICKED && wparam.0 as i32 == ID_ABOUT_COPY {
+        // data is the &str passed to show_about, alive while the dialog is
+        let text = &*(data as *const &str);
+        let mut clipboard = clipboard::Clipboard::new(
+            clipboard::Win32Clipboard::new(hwnd)
+        );
+        if let Err(e) = clipboard.set_text(text) {
+            pop_error(hwnd, &str_to_hstring(&format!("clipboard: {}", e)));
+        }
+        // keep the dialog open
+        return S_FALSE;
     }
-}
\ No newline at end of file
+    S_OK
+}
+
+/// Shows `text` with a button copying it to the clipboard.
+pub fn show_about<T>(hwnd: T, title: &str, text: &str) -> Result<()>
+where T: Param<HWND> {
+    unsafe {
+        let title = str_to_hstring(title);
+        let content = str_to_hstring(text);
+        let copy = str_to_hstring("複製到剪貼簿");
+        let buttons = [TASKDIALOG_BUTTON {
+            nButtonID: ID_ABOUT_COPY,
+            pszButtonText: hstr_to_pcwstr(&copy),
+        }];
+        let config = TASKDIALOGCONFIG {
+            cbSize: std::mem::size_of::<TASKDIALOGCONFIG>() as u32,
+            hwndParent: hwnd.param().abi(),
+            dwFlags: TDF_ALLOW_DIALOG_CANCELLATION,
+            dwCommonButtons: TDCBF_OK_BUTTON,
+            pszWindowTitle: hstr_to_pcwstr(&title),
+            pszContent: hstr_to_pcwstr(&content),
+            cButtons: buttons.len() as u32,
+            pButtons: buttons.as_ptr(),
+            nDefaultButton: IDOK.0,
+            pfCallback: Some(about_callback),
+            lpCallbackData: &text as *const &str as isize,
+            ..Default::default()
+        };
+        TaskDialogIndirect(&config, None, None, None)
+    }
+}
@@ -0,0 +1,313 @@
+use std::fmt::Write;
+
+/// The hashes the checksum task knows, with the names sha256sum and its
+/// siblings use.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Algorithm {
+    #[default]
+    Sha256,
+    Sha1,
+    Md5,
+}
+
+impl Algorithm {
+    pub const ALL: [Algorithm; 3] = [Algorithm::Sha256, Algorithm::Sha1, Algorithm::Md5];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Sha1 => "sha1",
+            Algorithm::Md5 => "md5",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|a| a.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Length of the digest in hex digits.
+    pub fn hex_len(&self) -> usize {
+        match self {
+            Algorithm::Sha256 => 64,
+            Algorithm::Sha1 => 40,
+            Algorithm::Md5 => 32,
+        }
+    }
+
+    /// What sha256sum and friends call their manifest by habit.
+    pub fn manifest_name(&self) -> &'static str {
+        match self {
+            Algorithm::Sha256 => "SHA256SUMS",
+            Algorithm::Sha1 => "SHA1SUMS",
+            Algorithm::Md5 => "MD5SUMS",
+        }
+    }
+
+    pub fn hasher(&self) -> Hasher {
+        let state = match self {
+            Algorithm::Sha256 => State::Sha256(SHA256_INIT),
+            Algorithm::Sha1 => State::Sha1(SHA1_INIT),
+            Algorithm::Md5 => State::Md5(MD5_INIT),
+        };
+        Hasher { state, block: [0; 64], buffered: 0, length: 0 }
+    }
+}
+
+enum State {
+    Sha256([u32; 8]),
+    Sha1([u32; 5]),
+    Md5([u32; 4]),
+}
+
+/// Hashes data given in pieces of any size.
+pub struct Hasher {
+    state: State,
+    block: [u8; 64],
+    buffered: usize,
+    // bytes so far
+    length: u64,
+}
+
+impl Hasher {
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.length += data.len() as u64;
+        while !data.is_empty() {
+            let n = (64 - self.buffered).min(data.len());
+            self.block[self.buffered..self.buffered + n].copy_from_slice(&data[..n]);
+            self.buffered += n;
+            data = &data[n..];
+            if self.buffered == 64 {
+                let block = self.block;
+                self.compress(&block);
+                self.buffered = 0;
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        let bits = self.length.wrapping_mul(8);
+        // a 1 bit, zeros up to 56 bytes into a block, then the length
+        let mut padding = vec![0x80];
+        padding.resize(1 + (55 - self.buffered as isize).rem_euclid(64) as usize, 0);
+        padding.extend_from_slice(&match self.state {
+            State::Md5(_) => bits.to_le_bytes(),
+            _ => bits.to_be_bytes(),
+        });
+        let length = self.length;
+        self.update(&padding);
+        self.length = length;
+        match self.state {
+            State::Sha256(h) => h.iter().flat_map(|w| w.to_be_bytes()).collect(),
+            State::Sha1(h) => h.iter().flat_map(|w| w.to_be_bytes()).collect(),
+            State::Md5(h) => h.iter().flat_map(|w| w.to_le_bytes()).collect(),
+        }
+    }
+
+    fn compress(&mut self, block: &[u8; 64]) {
+        match &mut self.state {
+            State::Sha256(h) => sha256_block(h, block),
+            State::Sha1(h) => sha1_block(h, block),
+            State::Md5(h) => md5_block(h, block),
+        }
+    }
+}
+
+/// Lowercase hex, as the manifests have it.
+pub fn hex(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        let _ = write!(text, "{:02x}", b);
+    }
+    text
+}
+
+const SHA256_INIT: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+fn sha256_block(h: &mut [u32; 8], block: &[u8; 64]) {
+    let mut w = [0u32; 64];
+    for (i, word) in block.chunks_exact(4).enumerate() {
+        w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+    }
+    for i in 16..64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+    }
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut hh] = *h;
+    for i in 0..64 {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let ch = (e & f) ^ (!e & g);
+        let t1 = hh.wrapping_add(s1).wrapping_add(ch).wrapping_add(SHA256_K[i]).wrapping_add(w[i]);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0.wrapping_add(maj);
+        hh = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(t1);
+        d = c;
+        c = b;
+        b = a;
+        a = t1.wrapping_add(t2);
+    }
+    for (state, v) in h.iter_mut().zip([a, b, c, d, e, f, g, hh]) {
+        *state = state.wrapping_add(v);
+    }
+}
+
+const SHA1_INIT: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+
+fn sha1_block(h: &mut [u32; 5], block: &[u8; 64]) {
+    let mut w = [0u32; 80];
+    for (i, word) in block.chunks_exact(4).enumerate() {
+        w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+    }
+    for i in 16..80 {
+        w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+    }
+    let [mut a, mut b, mut c, mut d, mut e] = *h;
+    for (i, word) in w.iter().enumerate() {
+        let (f, k) = match i {
+            0..=19 => ((b & c) | (!b & d), 0x5a827999),
+            20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+            40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+            _ => (b ^ c ^ d, 0xca62c1d6),
+        };
+        let t = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*word);
+        e = d;
+        d = c;
+        c = b.rotate_left(30);
+        b = a;
+        a = t;
+    }
+    for (state, v) in h.iter_mut().zip([a, b, c, d, e]) {
+        *state = state.wrapping_add(v);
+    }
+}
+
+const MD5_INIT: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+
+const MD5_SHIFT: [u32; 64] = [
+    7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22,
+    5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20,
+    4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23,
+    6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+];
+
+const MD5_K: [u32; 64] = [
+    0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613, 0xfd469501,
+    0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193, 0xa679438e, 0x49b40821,
+    0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa, 0xd62f105d, 0x02441453, 0xd8a1e681, 0xe7d3fbc8,
+    0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed, 0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a,
+    0xfffa3942, 0x8771f681, 0x6d9d6122, 0xfde5380c, 0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70,
+    0x289b7ec6, 0xeaa127fa, 0xd4ef3085, 0x04881d05, 0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665,
+    0xf4292244, 0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+    0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb, 0xeb86d391,
+];
+
+fn md5_block(h: &mut [u32; 4], block: &[u8; 64]) {
+    let mut m = [0u32; 16];
+    for (i, word) in block.chunks_exact(4).enumerate() {
+        m[i] = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+    }
+    let [mut a, mut b, mut c, mut d] = *h;
+    for i in 0..64 {
+        let (f, g) = match i {
+            0..=15 => ((b & c) | (!b & d), i),
+            16..=31 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+            32..=47 => (b ^ c ^ d, (3 * i + 5) % 16),
+            _ => (c ^ (b | !d), (7 * i) % 16),
+        };
+        let f = f.wrapping_add(a).wrapping_add(MD5_K[i]).wrapping_add(m[g]);
+        a = d;
+        d = c;
+        c = b;
+        b = b.wrapping_add(f.rotate_left(MD5_SHIFT[i]));
+    }
+    for (state, v) in h.iter_mut().zip([a, b, c, d]) {
+        *state = state.wrapping_add(v);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digest(algorithm: Algorithm, data: &[u8]) -> String {
+        let mut hasher = algorithm.hasher();
+        hasher.update(data);
+        hex(&hasher.finish())
+    }
+
+    #[test]
+    fn known_digests() {
+        let cases = [
+            (Algorithm::Sha256, "", "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"),
+            (Algorithm::Sha256, "abc", "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"),
+            (
+                Algorithm::Sha256,
+                "abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq",
+                "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+            ),
+            (Algorithm::Sha1, "", "da39a3ee5e6b4b0d3255bfef95601890afd80709"),
+            (Algorithm::Sha1, "abc", "a9993e364706816aba3e25717850c26c9cd0d89d"),
+            (
+                Algorithm::Sha1,
+                "abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq",
+                "84983e441c3bd26ebaae4aa1f95129e5e54670f1",
+            ),
+            (Algorithm::Md5, "", "d41d8cd98f00b204e9800998ecf8427e"),
+            (Algorithm::Md5, "abc", "900150983cd24fb0d6963f7d28e17f72"),
+            (
+                Algorithm::Md5,
+                "12345678901234567890123456789012345678901234567890123456789012345678901234567890",
+                "57edf4a22be3c955ac49da2e2107b67a",
+            ),
+        ];
+        for (algorithm, data, expected) in cases {
+            assert_eq!(digest(algorithm, data.as_bytes()), expected, "{} {:?}", algorithm.name(), data);
+            assert_eq!(expected.len(), algorithm.hex_len());
+        }
+    }
+
+    #[test]
+    fn pieces_hash_like_the_whole() {
+        let data: Vec<u8> = (0..1000u32).map(|i| (i * 7) as u8).collect();
+        for algorithm in Algorithm::ALL {
+            let whole = digest(algorithm, &data);
+            // every block boundary and padding length gets crossed
+            for piece in [1, 55, 56, 63, 64, 65, 999] {
+                let mut hasher = algorithm.hasher();
+                for chunk in data.chunks(piece) {
+                    hasher.update(chunk);
+                }
+                assert_eq!(hex(&hasher.finish()), whole, "{} in {}", algorithm.name(), piece);
+            }
+        }
+        let million = vec![b'a'; 1_000_000];
+        assert_eq!(
+            digest(Algorithm::Sha256, &million),
+            "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"
+        );
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(Algorithm::parse("SHA1"), Some(Algorithm::Sha1));
+        assert_eq!(Algorithm::parse("crc32"), None);
+        assert_eq!(Algorithm::default().manifest_name(), "SHA256SUMS");
+    }
+}
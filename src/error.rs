@@ -0,0 +1,192 @@
+use std::fmt;
+use std::io;
+
+// FACILITY_WIN32, HRESULT_FROM_WIN32 wraps a GetLastError code in it
+const FACILITY_WIN32: i32 = 7;
+
+/// Everything that can go wrong in the application. `Context` wraps an
+/// error with what was being done when it happened, the outermost
+/// context comes first when it's shown.
+#[derive(Debug)]
+pub enum AppError {
+    /// A GetLastError code.
+    Win32 { code: u32, message: String },
+    /// Any other HRESULT, from COM or the shell.
+    Com { hresult: i32, message: String },
+    Io(io::Error),
+    /// A task that ran but didn't finish its work.
+    Task(String),
+    /// Bad settings or command line.
+    Config(String),
+    Context { context: String, source: Box<AppError> },
+}
+
+impl AppError {
+    /// Sorts an HRESULT into a Win32 code or a COM failure.
+    pub fn from_hresult(hresult: i32, message: impl Into<String>) -> Self {
+        let message = message.into();
+        match (hresult >> 16) & 0x1fff == FACILITY_WIN32 && hresult < 0 {
+            true => AppError::Win32 { code: (hresult & 0xffff) as u32, message },
+            false => AppError::Com { hresult, message },
+        }
+    }
+
+    pub fn task(message: impl Into<String>) -> Self {
+        AppError::Task(message.into())
+    }
+
+    pub fn config(message: impl Into<String>) -> Self {
+        AppError::Config(message.into())
+    }
+
+    pub fn context(self, context: impl Into<String>) -> Self {
+        AppError::Context { context: context.into(), source: Box::new(self) }
+    }
+
+    /// The error itself, without the contexts around it.
+    pub fn root(&self) -> &AppError {
+        match self {
+            AppError::Context { source, .. } => source.root(),
+            error => error,
+        }
+    }
+
+    /// The contexts, outermost first.
+    pub fn contexts(&self) -> Vec<&str> {
+        let mut contexts = Vec::new();
+        let mut error = self;
+        while let AppError::Context { context, source } = error {
+            contexts.push(context.as_str());
+            error = source;
+        }
+        contexts
+    }
+
+    /// Multi-line text for the error dialog: what failed, then the cause.
+    pub fn details(&self) -> String {
+        let contexts = self.contexts();
+        let cause = self.root().to_string();
+        match contexts.split_first() {
+            None => cause,
+            Some((first, rest)) => {
+                let mut text = format!("{}失敗", first);
+                for context in rest {
+                    text.push_str(&format!("\n  {}", context));
+                }
+                text.push_str(&format!("\n\n原因: {}", cause));
+                text
+            },
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::Win32 { code, message } => {
+                write!(f, "win32 error {}", code)?;
+                match message.is_empty() {
+                    true => Ok(()),
+                    false => write!(f, ": {}", message),
+                }
+            },
+            AppError::Com { hresult, message } => {
+                write!(f, "hresult 0x{:08X}", *hresult as u32)?;
+                match message.is_empty() {
+                    true => Ok(()),
+                    false => write!(f, ": {}", message),
+                }
+            },
+            AppError::Io(e) => write!(f, "{}", e),
+            AppError::Task(message) => write!(f, "task failed: {}", message),
+            AppError::Config(message) => write!(f, "bad configuration: {}", message),
+            AppError::Context { context, source } => write!(f, "{}: {}", context, source),
+        }
+    }
+}
+
+impl std::error::Error for AppError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AppError::Io(e) => Some(e),
+            AppError::Context { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for AppError {
+    fn from(e: io::Error) -> Self {
+        AppError::Io(e)
+    }
+}
+
+#[cfg(windows)]
+impl From<windows::core::Error> for AppError {
+    fn from(e: windows::core::Error) -> Self {
+        AppError::from_hresult(e.code().0, e.message())
+    }
+}
+
+/// `.context()` on results whose error converts into an `AppError`.
+pub trait Context<T> {
+    fn context(self, context: impl Into<String>) -> Result<T, AppError>;
+    fn with_context<S: Into<String>>(self, context: impl FnOnce() -> S) -> Result<T, AppError>;
+}
+
+impl<T, E: Into<AppError>> Context<T> for Result<T, E> {
+    fn context(self, context: impl Into<String>) -> Result<T, AppError> {
+        self.map_err(|e| e.into().context(context))
+    }
+
+    fn with_context<S: Into<String>>(self, context: impl FnOnce() -> S) -> Result<T, AppError> {
+        self.map_err(|e| e.into().context(context()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hresults_are_sorted() {
+        // HRESULT_FROM_WIN32(ERROR_ACCESS_DENIED)
+        let e = AppError::from_hresult(0x8007_0005_u32 as i32, "Access is denied.");
+        assert!(matches!(e, AppError::Win32 { code: 5, .. }));
+        assert_eq!(e.to_string(), "win32 error 5: Access is denied.");
+        // REGDB_E_CLASSNOTREG
+        let e = AppError::from_hresult(0x8004_0154_u32 as i32, "");
+        assert!(matches!(e, AppError::Com { .. }));
+        assert_eq!(e.to_string(), "hresult 0x80040154");
+    }
+
+    #[test]
+    fn contexts_chain_outermost_first() {
+        let result: Result<(), io::Error> =
+            Err(io::Error::new(io::ErrorKind::NotFound, "no such file"));
+        let e = result
+            .context("讀取設定")
+            .with_context(|| format!("開啟 {}", "result.txt"))
+            .unwrap_err();
+        assert_eq!(e.contexts(), ["開啟 result.txt", "讀取設定"]);
+        assert!(matches!(e.root(), AppError::Io(_)));
+        assert_eq!(e.to_string(), "開啟 result.txt: 讀取設定: no such file");
+    }
+
+    #[test]
+    fn details_for_the_dialog() {
+        let e = AppError::task("3 files unreadable").context("處理").context("執行");
+        assert_eq!(e.details(), "執行失敗\n  處理\n\n原因: task failed: 3 files unreadable");
+        assert_eq!(AppError::config("--output").details(), "bad configuration: --output");
+    }
+
+    #[test]
+    fn source_walks_the_chain() {
+        use std::error::Error;
+        let e = AppError::from(io::Error::other("disk full")).context("儲存");
+        let source = e.source().unwrap();
+        assert_eq!(source.to_string(), "disk full");
+        assert_eq!(source.source().unwrap().to_string(), "disk full");
+        assert!(AppError::config("x").source().is_none());
+    }
+}
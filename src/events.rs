@@ -0,0 +1,186 @@
+use crate::messages::{hiword, loword};
+
+// notification codes in the high word of a WM_COMMAND wparam
+pub const BN_CLICKED: u32 = 0;
+pub const EN_CHANGE: u32 = 0x0300;
+// menu items report 0, accelerators 1, both without a control
+pub const CODE_MENU: u32 = 0;
+pub const CODE_ACCELERATOR: u32 = 1;
+
+/// WM_COMMAND split into its parts, `control` is `None` for menus and
+/// accelerators.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Command<H> {
+    pub id: usize,
+    pub code: u32,
+    pub control: Option<H>,
+}
+
+impl<H> Command<H> {
+    /// `control` is the lparam of the message, null for menus.
+    pub fn decode(wparam: usize, control: Option<H>) -> Self {
+        Self {
+            id: loword(wparam) as usize,
+            code: hiword(wparam) as u32,
+            control,
+        }
+    }
+
+    pub fn is_menu(&self) -> bool {
+        self.control.is_none()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event<H> {
+    Command(Command<H>),
+    Resize { width: u32, height: u32 },
+    Close,
+}
+
+type Handler<C> = Box<dyn FnMut(&mut C)>;
+type ResizeHandler<C> = Box<dyn FnMut(&mut C, u32, u32)>;
+
+/// Handlers of the events of a window with state `C`. Every handler that
+/// matches an event runs, in the order they were added.
+pub struct Router<C> {
+    clicks: Vec<(usize, Handler<C>)>,
+    menu_commands: Vec<(usize, Handler<C>)>,
+    text_changes: Vec<(usize, Handler<C>)>,
+    resizes: Vec<ResizeHandler<C>>,
+    closes: Vec<Handler<C>>,
+}
+
+impl<C> Default for Router<C> {
+    fn default() -> Self {
+        Self {
+            clicks: Vec::new(),
+            menu_commands: Vec::new(),
+            text_changes: Vec::new(),
+            resizes: Vec::new(),
+            closes: Vec::new(),
+        }
+    }
+}
+
+impl<C> Router<C> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A button `id` was clicked.
+    pub fn on_click(&mut self, id: usize, handler: impl FnMut(&mut C) + 'static) {
+        self.clicks.push((id, Box::new(handler)));
+    }
+
+    /// A menu item or accelerator `id` was chosen.
+    pub fn on_menu(&mut self, id: usize, handler: impl FnMut(&mut C) + 'static) {
+        self.menu_commands.push((id, Box::new(handler)));
+    }
+
+    /// The text of edit `id` changed.
+    pub fn on_text_changed(&mut self, id: usize, handler: impl FnMut(&mut C) + 'static) {
+        self.text_changes.push((id, Box::new(handler)));
+    }
+
+    /// The client area was resized to `width` x `height`.
+    pub fn on_resize(&mut self, handler: impl FnMut(&mut C, u32, u32) + 'static) {
+        self.resizes.push(Box::new(handler));
+    }
+
+    pub fn on_close(&mut self, handler: impl FnMut(&mut C) + 'static) {
+        self.closes.push(Box::new(handler));
+    }
+
+    /// Runs the handlers of `event`, returns false when none matched so
+    /// the caller can fall back to the default handling.
+    pub fn dispatch<H>(&mut self, ctx: &mut C, event: &Event<H>) -> bool {
+        match event {
+            Event::Command(command) => {
+                let handlers = match (command.is_menu(), command.code) {
+                    (true, CODE_MENU | CODE_ACCELERATOR) => &mut self.menu_commands,
+                    (false, BN_CLICKED) => &mut self.clicks,
+                    (false, EN_CHANGE) => &mut self.text_changes,
+                    _ => return false,
+                };
+                let mut handled = false;
+                for (id, handler) in handlers.iter_mut() {
+                    if *id == command.id {
+                        handler(ctx);
+                        handled = true;
+                    }
+                }
+                handled
+            },
+            Event::Resize { width, height } => {
+                for handler in self.resizes.iter_mut() {
+                    handler(ctx, *width, *height);
+                }
+                !self.resizes.is_empty()
+            },
+            Event::Close => {
+                for handler in self.closes.iter_mut() {
+                    handler(ctx);
+                }
+                !self.closes.is_empty()
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(id: usize, code: u32, control: Option<u8>) -> Event<u8> {
+        Event::Command(Command::decode(((code as usize) << 16) | id, control))
+    }
+
+    #[test]
+    fn decodes_wm_command() {
+        let c = Command::decode((EN_CHANGE as usize) << 16 | 4, Some(7u8));
+        assert_eq!(c, Command { id: 4, code: EN_CHANGE, control: Some(7) });
+        let menu = Command::<u8>::decode(301, None);
+        assert_eq!(menu.code, CODE_MENU);
+        assert!(menu.is_menu());
+    }
+
+    #[test]
+    fn routes_by_id_and_code() {
+        let mut router: Router<Vec<&str>> = Router::new();
+        router.on_click(1, |log| log.push("path"));
+        router.on_click(2, |log| log.push("run"));
+        router.on_text_changed(4, |log| log.push("text"));
+        router.on_menu(301, |log| log.push("about"));
+        let mut log = Vec::new();
+        assert!(router.dispatch(&mut log, &command(2, BN_CLICKED, Some(1))));
+        assert!(router.dispatch(&mut log, &command(4, EN_CHANGE, Some(1))));
+        assert!(router.dispatch(&mut log, &command(301, CODE_MENU, None)));
+        assert!(router.dispatch(&mut log, &command(301, CODE_ACCELERATOR, None)));
+        // a menu item with the id of a button isn't a click
+        assert!(!router.dispatch(&mut log, &command(1, CODE_MENU, None)));
+        // focus notifications of the edit
+        assert!(!router.dispatch(&mut log, &command(4, 0x0100, Some(1))));
+        assert_eq!(log, ["run", "text", "about", "about"]);
+    }
+
+    #[test]
+    fn handlers_run_in_registration_order() {
+        let mut router: Router<Vec<String>> = Router::new();
+        router.on_resize(|log, w, h| log.push(format!("layout {}x{}", w, h)));
+        router.on_resize(|log, _, _| log.push("position".to_string()));
+        router.on_close(|log| log.push("tray".to_string()));
+        router.on_close(|log| log.push("quit".to_string()));
+        let mut log = Vec::new();
+        router.dispatch(&mut log, &Event::<u8>::Resize { width: 800, height: 600 });
+        router.dispatch(&mut log, &Event::<u8>::Close);
+        assert_eq!(log, ["layout 800x600", "position", "tray", "quit"]);
+    }
+
+    #[test]
+    fn unhandled_events_fall_through() {
+        let mut router: Router<()> = Router::new();
+        assert!(!router.dispatch(&mut (), &Event::<u8>::Close));
+        assert!(!router.dispatch(&mut (), &Event::<u8>::Resize { width: 1, height: 1 }));
+    }
+}
@@ -0,0 +1,242 @@
+use std::cell::{Cell, RefCell};
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::cli::{self, Options, Output};
+use crate::crash;
+use crate::diagnostics;
+use crate::error::AppError;
+use crate::json::Value;
+use crate::progress::ProgressUpdate;
+use crate::status::format_elapsed;
+use crate::tasks::{self, TaskSink};
+
+/// How a headless run ended.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Finish {
+    Done,
+    Failed(String),
+    Panicked(String),
+}
+
+impl Finish {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Finish::Done => cli::EXIT_OK,
+            Finish::Failed(_) => cli::EXIT_FAILED,
+            Finish::Panicked(_) => cli::EXIT_PANIC,
+        }
+    }
+
+    fn status(&self) -> &'static str {
+        match self {
+            Finish::Done => "done",
+            Finish::Failed(_) => "failed",
+            Finish::Panicked(_) => "panicked",
+        }
+    }
+}
+
+pub fn percent(update: &ProgressUpdate) -> u32 {
+    let fraction = match (update.fraction, update.total) {
+        (Some(fraction), _) => fraction,
+        (None, 0) => 0.0,
+        (None, total) => update.done as f64 / total as f64,
+    };
+    (fraction.clamp(0.0, 1.0) * 100.0).floor() as u32
+}
+
+/// Prints what a task reports, as text for people or a JSON object per
+/// line for scripts. Progress is printed when the percentage or the
+/// stage changes, not on every step.
+pub struct ConsoleRenderer<W: Write> {
+    out: RefCell<W>,
+    output: Output,
+    last: RefCell<Option<(u32, String)>>,
+    lines: Cell<usize>,
+}
+
+impl<W: Write> ConsoleRenderer<W> {
+    pub fn new(out: W, output: Output) -> Self {
+        Self { out: RefCell::new(out), output, last: RefCell::new(None), lines: Cell::new(0) }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out.into_inner()
+    }
+
+    fn emit(&self, text: &str, json: Value) {
+        let mut out = self.out.borrow_mut();
+        // a closed stdout, like `| head`, doesn't stop the task
+        let _ = match self.output {
+            Output::Text => writeln!(out, "{}", text),
+            Output::Json => writeln!(out, "{}", json),
+        };
+        let _ = out.flush();
+    }
+
+    pub fn start(&self, task: &str, path: &Path) {
+        self.emit(
+            &format!("{} {}", task, path.display()),
+            Value::object([
+                ("event", "start".into()),
+                ("task", task.into()),
+                ("path", path.display().to_string().into()),
+            ]),
+        );
+    }
+
+    pub fn finish(&self, finish: &Finish, elapsed: Duration) {
+        let text = match finish {
+            Finish::Done => format!("done, {} lines in {}", self.lines.get(), format_elapsed(elapsed)),
+            Finish::Failed(e) => format!("failed: {}", e),
+            Finish::Panicked(e) => format!("crashed: {}", e),
+        };
+        let error = match finish {
+            Finish::Done => None,
+            Finish::Failed(e) | Finish::Panicked(e) => Some(e.as_str()),
+        };
+        self.emit(
+            &text,
+            Value::object([
+                ("event", "finish".into()),
+                ("status", finish.status().into()),
+                ("lines", self.lines.get().into()),
+                ("elapsed_ms", (elapsed.as_millis() as u64).into()),
+                ("error", error.into()),
+                ("exit_code", (finish.exit_code() as i64).into()),
+            ]),
+        );
+    }
+}
+
+impl<W: Write> TaskSink for ConsoleRenderer<W> {
+    fn progress(&self, update: &ProgressUpdate) {
+        let current = (percent(update), update.stage.clone());
+        if self.last.borrow().as_ref() == Some(&current) {
+            return;
+        }
+        let text = match update.stage.is_empty() {
+            true => format!("[{:>3}%] {}/{}", current.0, update.done, update.total),
+            false => format!("[{:>3}%] {} {}/{}", current.0, update.stage, update.done, update.total),
+        };
+        self.emit(
+            &text,
+            Value::object([
+                ("event", "progress".into()),
+                ("percent", (current.0 as u64).into()),
+                ("stage", update.stage.as_str().into()),
+                ("done", update.done.into()),
+                ("total", update.total.into()),
+            ]),
+        );
+        *self.last.borrow_mut() = Some(current);
+    }
+
+    fn line(&self, line: &str) {
+        self.lines.set(self.lines.get() + 1);
+        diagnostics::task_output(line);
+        self.emit(line, Value::object([("event", "line".into()), ("text", line.into())]));
+    }
+
+    fn error_line(&self, line: &str) {
+        self.lines.set(self.lines.get() + 1);
+        diagnostics::task_output(line);
+        self.emit(
+            &format!("error: {}", line),
+            Value::object([("event", "line".into()), ("text", line.into()), ("error", true.into())]),
+        );
+    }
+
+    fn keep_going(&self, _progress: &ProgressUpdate) -> bool {
+        true
+    }
+}
+
+/// Runs the task of `options` on this thread, printing to `out`, and
+/// returns the exit code.
+pub fn run<W: Write>(options: &Options, out: W) -> (i32, W) {
+    let renderer = ConsoleRenderer::new(out, options.output);
+    let name = options.task.as_deref().unwrap_or(tasks::DEFAULT_TASK);
+    let path = options.path.clone().unwrap_or_default();
+    let started = Instant::now();
+    renderer.start(name, &path);
+    diagnostics::info("headless", &format!("{} {}", name, path.display()));
+    let finish = match tasks::find(name, &options.task_options) {
+        None => Finish::Failed(AppError::config(format!("unknown task {}", name)).to_string()),
+        Some(task) => match crash::catch("headless", || task.run(&path, &renderer)) {
+            Ok(Ok(())) => Finish::Done,
+            Ok(Err(e)) => Finish::Failed(e.to_string()),
+            Err(report) => {
+                let saved = report.save();
+                Finish::Panicked(report.describe(&saved))
+            },
+        },
+    };
+    if finish != Finish::Done {
+        diagnostics::error("headless", &format!("{:?}", finish));
+    }
+    renderer.finish(&finish, started.elapsed());
+    (finish.exit_code(), renderer.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(stage: &str, done: usize, total: usize, fraction: f64) -> ProgressUpdate {
+        ProgressUpdate { stage: stage.into(), fraction: Some(fraction), ..ProgressUpdate::new(done, total) }
+    }
+
+    fn printed(renderer: ConsoleRenderer<Vec<u8>>) -> String {
+        String::from_utf8(renderer.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn percent_of_updates() {
+        assert_eq!(percent(&ProgressUpdate::new(1, 4)), 25);
+        assert_eq!(percent(&ProgressUpdate::new(3, 0)), 0);
+        assert_eq!(percent(&update("a", 0, 1, 0.999)), 99);
+        assert_eq!(percent(&update("a", 0, 1, 1.5)), 100);
+    }
+
+    #[test]
+    fn text_output_skips_repeats() {
+        let renderer = ConsoleRenderer::new(Vec::new(), Output::Text);
+        renderer.progress(&update("掃描", 1, 5, 0.034));
+        renderer.progress(&update("掃描", 2, 5, 0.038));
+        renderer.progress(&update("處理", 0, 20, 0.038));
+        renderer.line("append line 1 to results");
+        renderer.finish(&Finish::Done, Duration::from_secs(9));
+        assert_eq!(
+            printed(renderer),
+            "[  3%] 掃描 1/5\n[  3%] 處理 0/20\nappend line 1 to results\ndone, 1 lines in 00:09\n"
+        );
+    }
+
+    #[test]
+    fn json_output_is_one_object_per_line() {
+        let renderer = ConsoleRenderer::new(Vec::new(), Output::Json);
+        renderer.progress(&update("處理", 9, 20, 0.45));
+        renderer.line("a \"quoted\" line");
+        renderer.error_line("oops");
+        renderer.finish(&Finish::Failed("disk full".into()), Duration::from_millis(1500));
+        let out = printed(renderer);
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(lines[0], r#"{"event":"progress","percent":45,"stage":"處理","done":9,"total":20}"#);
+        assert_eq!(lines[1], r#"{"event":"line","text":"a \"quoted\" line"}"#);
+        assert_eq!(lines[2], r#"{"event":"line","text":"oops","error":true}"#);
+        assert_eq!(
+            lines[3],
+            r#"{"event":"finish","status":"failed","lines":2,"elapsed_ms":1500,"error":"disk full","exit_code":1}"#
+        );
+    }
+
+    #[test]
+    fn exit_codes() {
+        assert_eq!(Finish::Done.exit_code(), 0);
+        assert_eq!(Finish::Failed(String::new()).exit_code(), 1);
+        assert_eq!(Finish::Panicked(String::new()).exit_code(), 101);
+    }
+}
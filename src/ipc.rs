@@ -0,0 +1,232 @@
+use std::io::{self, BufRead, Read, Write};
+
+use crate::json::Value;
+use crate::rpc::{self, Request};
+
+/// A request line longer than this ends the connection.
+pub const MAX_LINE: usize = 64 * 1024;
+
+/// Answers a request, None for a notification. Called on the thread of the
+/// connection.
+pub type Handler = dyn Fn(&Request) -> Option<Value> + Send + Sync;
+
+/// Talks to one client until it hangs up: a JSON-RPC request per line in,
+/// a response per line out.
+pub fn serve<R: BufRead, W: Write>(mut reader: R, mut writer: W, handler: &Handler) -> io::Result<()> {
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if (&mut reader).take(MAX_LINE as u64 + 1).read_until(b'\n', &mut line)? == 0 {
+            return Ok(());
+        }
+        if line.len() > MAX_LINE {
+            let response = rpc::error_response(None, rpc::INVALID_REQUEST, "request too long");
+            return writeln!(writer, "{}", response);
+        }
+        let response = match std::str::from_utf8(&line) {
+            Ok(text) if text.trim().is_empty() => continue,
+            Ok(text) => match rpc::parse_request(text.trim()) {
+                Ok(request) => handler(&request),
+                Err(response) => Some(response),
+            },
+            Err(_) => Some(rpc::error_response(None, rpc::PARSE_ERROR, "request isn't utf-8")),
+        };
+        if let Some(response) = response {
+            writeln!(writer, "{}", response)?;
+            writer.flush()?;
+        }
+    }
+}
+
+/// The control api on a unix socket, what the named pipe is on windows.
+#[cfg(unix)]
+pub mod unix {
+    use std::io::{self, BufReader};
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use std::{fs, thread};
+
+    use super::Handler;
+    use crate::diagnostics;
+    use crate::paths;
+
+    pub fn socket_path() -> PathBuf {
+        paths::app_data_dir().join("control.sock")
+    }
+
+    pub struct Server {
+        listener: UnixListener,
+        path: PathBuf,
+    }
+
+    impl Server {
+        /// Fails when another instance is listening on `path`, a socket
+        /// left behind by a crash is replaced.
+        pub fn bind(path: &Path) -> io::Result<Self> {
+            if path.exists() {
+                if UnixStream::connect(path).is_ok() {
+                    return Err(io::Error::new(io::ErrorKind::AddrInUse, "another instance is listening"));
+                }
+                fs::remove_file(path)?;
+            }
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let listener = UnixListener::bind(path)?;
+            // only this user may drive the app
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+            Ok(Self { listener, path: path.to_path_buf() })
+        }
+
+        pub fn path(&self) -> &Path {
+            &self.path
+        }
+
+        /// Accepts clients until the listener fails, each on its own thread.
+        pub fn run(&self, handler: Arc<Handler>) -> io::Result<()> {
+            for stream in self.listener.incoming() {
+                let stream = stream?;
+                let handler = handler.clone();
+                thread::spawn(move || {
+                    if let Err(e) = super::serve(BufReader::new(&stream), &stream, &*handler) {
+                        diagnostics::debug("ipc", &format!("client gone: {}", e));
+                    }
+                });
+            }
+            Ok(())
+        }
+    }
+
+    impl Drop for Server {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// The control api on a named pipe, one pipe instance per client.
+#[cfg(windows)]
+pub mod pipe {
+    use std::fs::File;
+    use std::io::BufReader;
+    use std::os::windows::io::FromRawHandle;
+    use std::sync::Arc;
+    use std::thread;
+
+    use windows::core::HSTRING;
+    use windows::Win32::{
+        Foundation::*,
+        Storage::FileSystem::{FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_DUPLEX},
+        System::Pipes::*,
+    };
+
+    use super::Handler;
+    use crate::diagnostics;
+    use crate::error::AppError;
+
+    pub const PIPE_NAME: &str = r"\\.\pipe\windows-app";
+    const BUFFER_SIZE: u32 = 4096;
+
+    /// Accepts clients until creating a pipe instance fails, which it does
+    /// at once when another instance is listening already.
+    pub fn run(handler: Arc<Handler>) -> Result<(), AppError> {
+        let name = HSTRING::from(PIPE_NAME);
+        let mut mode = PIPE_ACCESS_DUPLEX | FILE_FLAG_FIRST_PIPE_INSTANCE;
+        loop {
+            let pipe = unsafe {
+                CreateNamedPipeW(
+                    &name,
+                    mode,
+                    PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                    PIPE_UNLIMITED_INSTANCES,
+                    BUFFER_SIZE,
+                    BUFFER_SIZE,
+                    0,
+                    None,
+                )
+            };
+            if pipe == INVALID_HANDLE_VALUE {
+                return Err(windows::core::Error::from_win32().into());
+            }
+            mode = PIPE_ACCESS_DUPLEX;
+            // the file closes the pipe
+            let file = unsafe { File::from_raw_handle(pipe.0) };
+            if let Err(e) = unsafe { ConnectNamedPipe(pipe, None) } {
+                // a client that connected before the call is fine
+                if e.code() != ERROR_PIPE_CONNECTED.to_hresult() {
+                    diagnostics::warn("ipc", &format!("connect: {}", e));
+                    continue;
+                }
+            }
+            let handler = handler.clone();
+            thread::spawn(move || {
+                if let Err(e) = super::serve(BufReader::new(&file), &file, &*handler) {
+                    diagnostics::debug("ipc", &format!("client gone: {}", e));
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn echo_method(request: &Request) -> Option<Value> {
+        request.id.as_ref().map(|_| Value::from(request.method.as_str()))
+    }
+
+    fn served(input: &[u8]) -> String {
+        let mut out = Vec::new();
+        serve(input, &mut out, &echo_method).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn a_response_per_request_line() {
+        let input = b"{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"status\"}\r\n\n\
+            {\"jsonrpc\":\"2.0\",\"method\":\"cancel\"}\n\
+            {\"jsonrpc\":\"2.0\",\"id\":2,\"method\":\"start\"}";
+        assert_eq!(served(input), "\"status\"\n\"start\"\n");
+        let out = served(b"nope\n\xff\n");
+        let codes: Vec<_> = out
+            .lines()
+            .map(|line| Value::parse(line).unwrap().get("error").unwrap().get("code").cloned())
+            .collect();
+        assert_eq!(codes, [Some(Value::from(rpc::PARSE_ERROR)), Some(Value::from(rpc::PARSE_ERROR))]);
+    }
+
+    #[test]
+    fn long_lines_end_the_connection() {
+        let mut input = vec![b' '; MAX_LINE + 10];
+        input.extend_from_slice(b"\n{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"status\"}\n");
+        let out = served(&input);
+        assert_eq!(out.lines().count(), 1);
+        assert!(out.contains("request too long"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_socket_round_trip() {
+        use std::io::BufReader;
+        use std::os::unix::net::UnixStream;
+        use std::sync::Arc;
+
+        let dir = std::env::temp_dir().join(format!("windows-app-ipc-{}", std::process::id()));
+        let path = dir.join("control.sock");
+        let server = Arc::new(unix::Server::bind(&path).unwrap());
+        // a second server on the same socket is refused
+        assert_eq!(unix::Server::bind(&path).err().map(|e| e.kind()), Some(io::ErrorKind::AddrInUse));
+        let running = server.clone();
+        std::thread::spawn(move || running.run(Arc::new(echo_method)));
+
+        let stream = UnixStream::connect(server.path()).unwrap();
+        (&stream).write_all(b"{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"tail_log\"}\n").unwrap();
+        let mut response = String::new();
+        BufReader::new(&stream).read_line(&mut response).unwrap();
+        assert_eq!(response, "\"tail_log\"\n");
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,423 @@
+use std::fmt;
+
+/// A JSON value, enough for the machine readable output of the app and
+/// the requests of the control api. Object keys keep the order they were
+/// added in.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn object<K: Into<String>>(fields: impl IntoIterator<Item = (K, Value)>) -> Self {
+        Value::Object(fields.into_iter().map(|(k, v)| (k.into(), v)).collect())
+    }
+
+    /// The value of `key` of an object.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Whole, non negative numbers only.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Number(v) if *v >= 0.0 && v.fract() == 0.0 && *v < u64::MAX as f64 => Some(*v as u64),
+            _ => None,
+        }
+    }
+
+    /// Parses one JSON document, anything but whitespace after it is an
+    /// error.
+    pub fn parse(text: &str) -> Result<Value, ParseError> {
+        let mut parser = Parser { bytes: text.as_bytes(), at: 0, depth: 0 };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        match parser.at == parser.bytes.len() {
+            true => Ok(value),
+            false => Err(parser.error("text after the value")),
+        }
+    }
+}
+
+/// Where and why a text isn't JSON.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// Byte offset into the text.
+    pub offset: usize,
+    pub message: &'static str,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.offset)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+// deeper nesting than any request needs is someone trying the stack
+const MAX_DEPTH: usize = 64;
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    at: usize,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &'static str) -> ParseError {
+        ParseError { offset: self.at, message }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.at).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.at += 1;
+        }
+    }
+
+    fn expect(&mut self, word: &str) -> Result<(), ParseError> {
+        match self.bytes[self.at..].starts_with(word.as_bytes()) {
+            true => {
+                self.at += word.len();
+                Ok(())
+            },
+            false => Err(self.error("unknown literal")),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, ParseError> {
+        self.skip_whitespace();
+        match self.peek() {
+            None => Err(self.error("unexpected end")),
+            Some(b'n') => self.expect("null").map(|_| Value::Null),
+            Some(b't') => self.expect("true").map(|_| Value::Bool(true)),
+            Some(b'f') => self.expect("false").map(|_| Value::Bool(false)),
+            Some(b'"') => self.string().map(Value::String),
+            Some(b'[') => self.nested(Self::array),
+            Some(b'{') => self.nested(Self::object),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.error("unexpected character")),
+        }
+    }
+
+    fn nested(&mut self, f: fn(&mut Self) -> Result<Value, ParseError>) -> Result<Value, ParseError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error("nested too deep"));
+        }
+        self.depth += 1;
+        let value = f(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn array(&mut self) -> Result<Value, ParseError> {
+        self.at += 1;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.at += 1;
+            return Ok(Value::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.at += 1,
+                Some(b']') => {
+                    self.at += 1;
+                    return Ok(Value::Array(items));
+                },
+                _ => return Err(self.error("expected , or ]")),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, ParseError> {
+        self.at += 1;
+        let mut fields = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.at += 1;
+            return Ok(Value::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected a key"));
+            }
+            let key = self.string()?;
+            self.skip_whitespace();
+            if self.peek() != Some(b':') {
+                return Err(self.error("expected :"));
+            }
+            self.at += 1;
+            fields.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.at += 1,
+                Some(b'}') => {
+                    self.at += 1;
+                    return Ok(Value::Object(fields));
+                },
+                _ => return Err(self.error("expected , or }")),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, ParseError> {
+        let start = self.at;
+        while let Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') = self.peek() {
+            self.at += 1;
+        }
+        // the bytes are ascii, rust's float syntax is a superset of JSON's
+        let text = std::str::from_utf8(&self.bytes[start..self.at]).unwrap_or_default();
+        let leading_zero = text.trim_start_matches('-').starts_with('0')
+            && text.trim_start_matches('-').as_bytes().get(1).is_some_and(u8::is_ascii_digit);
+        match text.parse::<f64>() {
+            Ok(v) if !leading_zero && !text.ends_with('.') && !text.starts_with("-.") => Ok(Value::Number(v)),
+            _ => Err(ParseError { offset: start, message: "bad number" }),
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, ParseError> {
+        let digits = self.bytes.get(self.at..self.at + 4).ok_or_else(|| self.error("unexpected end"))?;
+        let code = std::str::from_utf8(digits)
+            .ok()
+            .and_then(|d| u32::from_str_radix(d, 16).ok())
+            .ok_or_else(|| self.error("bad \\u escape"))?;
+        self.at += 4;
+        Ok(code)
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        self.at += 1;
+        let mut out = Vec::new();
+        loop {
+            let Some(b) = self.peek() else {
+                return Err(self.error("unterminated string"));
+            };
+            self.at += 1;
+            match b {
+                b'"' => break,
+                b'\\' => {
+                    let Some(escape) = self.peek() else {
+                        return Err(self.error("unterminated string"));
+                    };
+                    self.at += 1;
+                    let c = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => return Err(self.error("bad escape")),
+                    };
+                    out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                },
+                b if b < 0x20 => return Err(self.error("control character in string")),
+                b => out.push(b),
+            }
+        }
+        // the text was a &str and escapes are whole chars
+        Ok(String::from_utf8(out).unwrap_or_default())
+    }
+
+    fn unicode_escape(&mut self) -> Result<char, ParseError> {
+        let high = self.hex4()?;
+        let code = match high {
+            0xd800..=0xdbff => {
+                if !self.bytes[self.at..].starts_with(b"\\u") {
+                    return Err(self.error("lone surrogate"));
+                }
+                self.at += 2;
+                let low = self.hex4()?;
+                if !(0xdc00..=0xdfff).contains(&low) {
+                    return Err(self.error("lone surrogate"));
+                }
+                0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+            },
+            _ => high,
+        };
+        char::from_u32(code).ok_or_else(|| self.error("lone surrogate"))
+    }
+}
+
+impl From<bool> for Value {
+    fn from(v: bool) -> Self {
+        Value::Bool(v)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(v: f64) -> Self {
+        Value::Number(v)
+    }
+}
+
+impl From<u64> for Value {
+    fn from(v: u64) -> Self {
+        Value::Number(v as f64)
+    }
+}
+
+impl From<usize> for Value {
+    fn from(v: usize) -> Self {
+        Value::Number(v as f64)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(v: i64) -> Self {
+        Value::Number(v as f64)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(v: &str) -> Self {
+        Value::String(v.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(v: String) -> Self {
+        Value::String(v)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(v: Option<T>) -> Self {
+        v.map(Into::into).unwrap_or(Value::Null)
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+/// Compact, on one line, so every value can be a line of output.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => f.write_str("null"),
+            Value::Bool(v) => write!(f, "{}", v),
+            // integers without a fraction, no NaN or infinity in JSON
+            Value::Number(v) if !v.is_finite() => f.write_str("null"),
+            Value::Number(v) if v.fract() == 0.0 && v.abs() < 1e15 => write!(f, "{}", *v as i64),
+            Value::Number(v) => write!(f, "{}", v),
+            Value::String(s) => write_string(f, s),
+            Value::Array(items) => {
+                f.write_str("[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                f.write_str("]")
+            },
+            Value::Object(fields) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_str("}")
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_compact_json() {
+        let v = Value::object([
+            ("event", Value::from("progress")),
+            ("percent", Value::from(45u64)),
+            ("fraction", Value::from(0.25)),
+            ("error", Value::from(None::<String>)),
+            ("items", Value::Array(vec![true.into(), Value::Null])),
+        ]);
+        assert_eq!(
+            v.to_string(),
+            r#"{"event":"progress","percent":45,"fraction":0.25,"error":null,"items":[true,null]}"#
+        );
+        assert_eq!(v.get("percent"), Some(&Value::Number(45.0)));
+        assert_eq!(Value::from(f64::NAN).to_string(), "null");
+        assert_eq!(Value::from(-3i64).to_string(), "-3");
+    }
+
+    #[test]
+    fn parses_what_it_writes() {
+        let text = r#"{"id":7,"method":"start","params":{"path":"C:\\資料","deep":[true,false,null,-1.5e2]}}"#;
+        let v = Value::parse(text).unwrap();
+        assert_eq!(v.to_string(), r#"{"id":7,"method":"start","params":{"path":"C:\\資料","deep":[true,false,null,-150]}}"#);
+        assert_eq!(v.get("id").and_then(Value::as_u64), Some(7));
+        assert_eq!(v.get("method").and_then(Value::as_str), Some("start"));
+        let v = Value::parse(" [ \"\\u00e9\\ud83d\\ude00\\n\" , {} ] \r\n").unwrap();
+        assert_eq!(v, Value::Array(vec!["é😀\n".into(), Value::Object(Vec::new())]));
+        assert_eq!(Value::from(-2i64).as_u64(), None);
+        assert_eq!(Value::from(2.5).as_u64(), None);
+    }
+
+    #[test]
+    fn rejects_what_is_not_json() {
+        let error = |text: &str| Value::parse(text).unwrap_err().to_string();
+        assert_eq!(error(""), "unexpected end at byte 0");
+        assert_eq!(error("{\"a\":1,}"), "expected a key at byte 7");
+        assert_eq!(error("[1 2]"), "expected , or ] at byte 3");
+        assert_eq!(error("tru"), "unknown literal at byte 0");
+        assert_eq!(error("01"), "bad number at byte 0");
+        assert_eq!(error("1."), "bad number at byte 0");
+        assert_eq!(error("\"a\nb\""), "control character in string at byte 3");
+        assert_eq!(error("\"\\ud800\""), "lone surrogate at byte 7");
+        assert_eq!(error("{} x"), "text after the value at byte 3");
+        assert_eq!(error(&"[".repeat(100)), "nested too deep at byte 64");
+    }
+
+    #[test]
+    fn escapes_strings() {
+        let v = Value::from("C:\\資料\t\"a\"\n\u{1}");
+        assert_eq!(v.to_string(), r#""C:\\資料\t\"a\"\n\u0001""#);
+    }
+}
@@ -0,0 +1,162 @@
+use windows::core::*;
+use windows::Win32::{
+    Foundation::*,
+    Graphics::Gdi::HFONT,
+    System::LibraryLoader::GetModuleHandleW,
+    UI::WindowsAndMessaging::*,
+};
+
+use crate::application::{Application, Creation};
+use crate::error::{AppError, Context};
+use crate::controls::{ControlBuilder, ControlHandle, ControlId, Edit, EditHandle};
+use crate::messages::Message;
+use crate::reentrancy::StateCell;
+use crate::window_registry::WindowRole;
+
+type State = StateCell<LogViewer, Message>;
+
+/// Tool window with a snapshot of the result log that can stay open next
+/// to the main window.
+pub struct LogViewer {
+    hwnd: HWND,
+    edit: EditHandle<HWND>,
+    font: HFONT,
+    text: String,
+}
+
+impl LogViewer {
+    const CLASS: &'static str = "log_viewer";
+    const ID_EDIT: ControlId<Edit> = ControlId::new(1);
+
+    /// Shows `text` in the viewer, opening it if it isn't yet.
+    pub fn open(text: &str, font: HFONT) -> std::result::Result<(), AppError> {
+        if let Some(hwnd) = Application::find_window(Self::CLASS) {
+            unsafe {
+                if let Some(this) = State::of(hwnd).as_deref().and_then(State::try_enter) {
+                    this.edit.set_text(text);
+                }
+                let _ = ShowWindow(hwnd, SW_RESTORE);
+                let _ = SetForegroundWindow(hwnd);
+            }
+            return Ok(());
+        }
+        Application::register_class(Self::CLASS, Some(Self::wndproc))
+            .context("註冊視窗類別")?;
+        let mut creation = Creation::new(Self {
+            hwnd: HWND::default(),
+            edit: EditHandle(HWND::default()),
+            font,
+            text: text.to_string(),
+        });
+        Application::create_window(
+            Self::CLASS,
+            "結果",
+            WS_OVERLAPPEDWINDOW | WS_VISIBLE,
+            640,
+            480,
+            WindowRole::Tool,
+            &mut creation,
+        )?;
+        Ok(())
+    }
+
+    extern "system" fn wndproc(
+        window: HWND,
+        message: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        Application::guard(window, message, wparam, lparam, || unsafe {
+            Self::window_proc(window, message, wparam, lparam)
+        })
+    }
+
+    unsafe fn window_proc(
+        window: HWND,
+        message: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        if message == WM_NCCREATE {
+            // the state moves from `open` into the window
+            if let Some(mut this) = Creation::<Self>::from_lparam(lparam).take() {
+                this.hwnd = window;
+                State::attach(window, this);
+            }
+        } else if message == WM_NCDESTROY {
+            State::detach(window);
+            Application::window_destroyed(window);
+        } else if let Some(state) = State::of(window) {
+            // only the layout can come while the viewer is busy
+            let Some(mut this) = state.try_enter() else {
+                if message == WM_SIZE {
+                    state.defer(Message::crack(message, wparam.0, lparam.0));
+                    return LRESULT(0);
+                }
+                return DefWindowProcW(window, message, wparam, lparam);
+            };
+            let result = this.message_handler(message, wparam, lparam);
+            drop(this);
+            state.drain(|this, message| {
+                let (msg, wparam, lparam) = message.to_raw();
+                this.message_handler(msg, WPARAM(wparam), LPARAM(lparam));
+            });
+            return result;
+        }
+        DefWindowProcW(window, message, wparam, lparam)
+    }
+
+    fn message_handler(&mut self, message: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+        unsafe {
+            match message {
+                WM_CREATE => match self.create_edit().context("建立文字框") {
+                    Ok(()) => LRESULT(0),
+                    Err(e) => {
+                        Creation::<Self>::from_lparam(lparam).fail(e);
+                        LRESULT(-1)
+                    },
+                },
+                WM_SIZE => {
+                    let mut client = RECT::default();
+                    let _ = GetClientRect(self.hwnd, &mut client);
+                    self.edit.move_to(0, 0, client.right, client.bottom);
+                    LRESULT(0)
+                },
+                _ => DefWindowProcW(self.hwnd, message, wparam, lparam),
+            }
+        }
+    }
+
+    fn create_edit(&mut self) -> Result<()> {
+        let spec = Edit::new(Self::ID_EDIT)
+            .multiline()
+            .read_only()
+            .vscroll()
+            .hscroll()
+            .spec()
+            .map_err(|e| Error::new(E_INVALIDARG, e.to_string()))?;
+        unsafe {
+            let instance = GetModuleHandleW(None)?;
+            let hwnd = CreateWindowExW(
+                WINDOW_EX_STYLE::default(),
+                &HSTRING::from(spec.class.class_name()),
+                &HSTRING::from(self.text.as_str()),
+                WINDOW_STYLE(spec.style),
+                0,
+                0,
+                0,
+                0,
+                self.hwnd,
+                HMENU(spec.id as _),
+                instance,
+                None,
+            )?;
+            self.edit = EditHandle(hwnd);
+            if !self.font.is_invalid() {
+                self.edit.set_font(self.font.0 as usize);
+            }
+            self.edit.limit_text(0);
+        }
+        Ok(())
+    }
+}
@@ -1,15 +1,126 @@
 #![windows_subsystem = "windows"]
+// the platform-neutral modules are only driven by the win32 ui
+#![cfg_attr(not(windows), allow(dead_code))]
 
+#[cfg(windows)]
 pub mod app;
+#[cfg(windows)]
+pub mod application;
+pub mod checksum;
+pub mod civil;
+pub mod cli;
+pub mod clipboard;
+pub mod controls;
+pub mod copydata;
+pub mod crash;
+pub mod diagnostics;
+pub mod digest;
+#[cfg(windows)]
 pub mod dialog;
+pub mod error;
+pub mod events;
+pub mod headless;
+pub mod ipc;
+pub mod json;
+#[cfg(windows)]
+pub mod log_viewer;
+pub mod messages;
+pub mod paths;
+pub mod progress;
+pub mod progress_tree;
+pub mod reentrancy;
+pub mod result_log;
+pub mod rpc;
+pub mod run_command;
+pub mod scan;
+#[cfg(windows)]
+pub mod single_instance;
+pub mod startup;
+pub mod status;
+pub mod taskbar;
+pub mod tasks;
+#[cfg(windows)]
 pub mod thread_safe;
+pub mod tooltip;
+pub mod tray;
+pub mod version_info;
+#[cfg(windows)]
 pub mod win_str;
+#[cfg(windows)]
 pub mod window;
+pub mod window_registry;
 
-use std::io::Error;
-use app::App;
+use cli::Command;
 
-fn main() -> Result<(), Error> {
-    let app = App::new();
-    App::run(app)
-}
\ No newline at end of file
+fn main() {
+    crash::install_hook();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    diagnostics::init(&diagnostics::LogConfig::from_args(&args));
+    diagnostics::info("app", &format!("started {}", env!("CARGO_PKG_VERSION")));
+    let code = match cli::parse(&args) {
+        Ok(Command::Gui(options)) => run_gui(options, &args),
+        Ok(Command::Headless(options)) => {
+            attach_console();
+            let (code, _) = headless::run(&options, std::io::stdout());
+            code
+        },
+        Ok(Command::Help) => {
+            attach_console();
+            print!("{}", cli::USAGE);
+            cli::EXIT_OK
+        },
+        Err(e) => {
+            attach_console();
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            cli::EXIT_USAGE
+        },
+    };
+    diagnostics::info("app", &format!("exited with {}", code));
+    std::process::exit(code);
+}
+
+#[cfg(windows)]
+fn run_gui(options: cli::Options, args: &[String]) -> i32 {
+    let cwd = std::env::current_dir().unwrap_or_default();
+    // held until the window closes
+    let mut _guard = None;
+    if options.single_instance {
+        match single_instance::acquire() {
+            Ok(single_instance::Instance::First(guard)) => _guard = Some(guard),
+            Ok(single_instance::Instance::Second) => match single_instance::forward(args, &cwd) {
+                Ok(()) => return cli::EXIT_OK,
+                Err(e) => diagnostics::warn("single_instance", &format!("opening a window: {}", e)),
+            },
+            Err(e) => diagnostics::warn("single_instance", &e.to_string()),
+        }
+    }
+    let plan = startup::StartupPlan::new(&options, &cwd, |path| path.exists());
+    let app = app::App::new();
+    match app::App::run(app, plan) {
+        Ok(()) => cli::EXIT_OK,
+        Err(e) => {
+            dialog::report_error(None, &e);
+            cli::EXIT_FAILED
+        },
+    }
+}
+
+#[cfg(not(windows))]
+fn run_gui(_options: cli::Options, _args: &[String]) -> i32 {
+    eprintln!("windows-app requires windows to run the gui, use --no-gui");
+    cli::EXIT_FAILED
+}
+
+/// A gui subsystem program has no console of its own, the output goes to
+/// the one of the shell that started it, if any. The shell doesn't wait
+/// for us, so the prompt may show up before the output.
+#[cfg(windows)]
+fn attach_console() {
+    use windows::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+    unsafe {
+        let _ = AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(windows))]
+fn attach_console() {}
@@ -0,0 +1,291 @@
+use crate::reentrancy::Coalesce;
+
+// message numbers from winuser.h
+pub const WM_DESTROY: u32 = 0x0002;
+pub const WM_SIZE: u32 = 0x0005;
+pub const WM_CLOSE: u32 = 0x0010;
+pub const WM_CONTEXTMENU: u32 = 0x007B;
+pub const WM_NCDESTROY: u32 = 0x0082;
+pub const WM_COMMAND: u32 = 0x0111;
+pub const WM_TIMER: u32 = 0x0113;
+pub const WM_MOUSEMOVE: u32 = 0x0200;
+pub const WM_LBUTTONDOWN: u32 = 0x0201;
+pub const WM_MOUSEWHEEL: u32 = 0x020A;
+pub const WM_DPICHANGED: u32 = 0x02E0;
+
+// one notch of the wheel
+pub const WHEEL_DELTA: i16 = 120;
+
+pub fn loword(v: usize) -> u16 {
+    v as u16
+}
+
+pub fn hiword(v: usize) -> u16 {
+    (v >> 16) as u16
+}
+
+/// GET_X_LPARAM, coordinates are signed: left of or above the primary
+/// monitor they are negative.
+pub fn x_lparam(lparam: isize) -> i32 {
+    lparam as u16 as i16 as i32
+}
+
+/// GET_Y_LPARAM
+pub fn y_lparam(lparam: isize) -> i32 {
+    (lparam >> 16) as u16 as i16 as i32
+}
+
+pub fn make_wparam(lo: u16, hi: u16) -> usize {
+    ((hi as usize) << 16) | lo as usize
+}
+
+/// MAKELPARAM, the high word is sign extended like the system does on
+/// 64-bit.
+pub fn make_lparam(lo: u16, hi: u16) -> isize {
+    (((hi as i16 as i32) << 16) | lo as i32) as isize
+}
+
+fn point_lparam(x: i32, y: i32) -> isize {
+    make_lparam(x as i16 as u16, y as i16 as u16)
+}
+
+/// The SIZE_* value of WM_SIZE.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SizeKind {
+    Restored,
+    Minimized,
+    Maximized,
+    MaxShow,
+    MaxHide,
+    Other(usize),
+}
+
+impl SizeKind {
+    pub fn from_raw(raw: usize) -> Self {
+        match raw {
+            0 => SizeKind::Restored,
+            1 => SizeKind::Minimized,
+            2 => SizeKind::Maximized,
+            3 => SizeKind::MaxShow,
+            4 => SizeKind::MaxHide,
+            raw => SizeKind::Other(raw),
+        }
+    }
+
+    pub fn raw(&self) -> usize {
+        match self {
+            SizeKind::Restored => 0,
+            SizeKind::Minimized => 1,
+            SizeKind::Maximized => 2,
+            SizeKind::MaxShow => 3,
+            SizeKind::MaxHide => 4,
+            SizeKind::Other(raw) => *raw,
+        }
+    }
+}
+
+/// A window message with its parameters decoded. Handles are kept as the
+/// raw isize of the HWND, `suggested` of WM_DPICHANGED is the address of
+/// the RECT the system proposes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Message {
+    Size { kind: SizeKind, width: u16, height: u16 },
+    Command { id: u16, code: u16, hwnd: isize },
+    Timer { id: usize },
+    /// `position` is `None` when the menu was opened from the keyboard.
+    ContextMenu { hwnd: isize, position: Option<(i32, i32)> },
+    MouseMove { keys: u16, x: i32, y: i32 },
+    LButtonDown { keys: u16, x: i32, y: i32 },
+    /// `x`, `y` are screen coordinates, `delta` is a multiple of
+    /// WHEEL_DELTA, positive away from the user.
+    MouseWheel { delta: i16, keys: u16, x: i32, y: i32 },
+    DpiChanged { dpi: u16, suggested: isize },
+    Close,
+    Destroy,
+    NcDestroy,
+    Other { msg: u32, wparam: usize, lparam: isize },
+}
+
+impl Message {
+    pub fn crack(msg: u32, wparam: usize, lparam: isize) -> Self {
+        match msg {
+            WM_SIZE => Message::Size {
+                kind: SizeKind::from_raw(wparam),
+                width: loword(lparam as usize),
+                height: hiword(lparam as usize),
+            },
+            WM_COMMAND => Message::Command {
+                id: loword(wparam),
+                code: hiword(wparam),
+                hwnd: lparam,
+            },
+            WM_TIMER => Message::Timer { id: wparam },
+            WM_CONTEXTMENU => Message::ContextMenu {
+                hwnd: wparam as isize,
+                position: match (x_lparam(lparam), y_lparam(lparam)) {
+                    (-1, -1) => None,
+                    point => Some(point),
+                },
+            },
+            WM_MOUSEMOVE => Message::MouseMove {
+                keys: wparam as u16,
+                x: x_lparam(lparam),
+                y: y_lparam(lparam),
+            },
+            WM_LBUTTONDOWN => Message::LButtonDown {
+                keys: wparam as u16,
+                x: x_lparam(lparam),
+                y: y_lparam(lparam),
+            },
+            WM_MOUSEWHEEL => Message::MouseWheel {
+                delta: hiword(wparam) as i16,
+                keys: loword(wparam),
+                x: x_lparam(lparam),
+                y: y_lparam(lparam),
+            },
+            // x and y dpi are always the same
+            WM_DPICHANGED => Message::DpiChanged { dpi: loword(wparam), suggested: lparam },
+            WM_CLOSE => Message::Close,
+            WM_DESTROY => Message::Destroy,
+            WM_NCDESTROY => Message::NcDestroy,
+            msg => Message::Other { msg, wparam, lparam },
+        }
+    }
+
+    /// The raw `(msg, wparam, lparam)` to send this message.
+    pub fn to_raw(&self) -> (u32, usize, isize) {
+        match *self {
+            Message::Size { kind, width, height } => {
+                (WM_SIZE, kind.raw(), make_lparam(width, height))
+            },
+            Message::Command { id, code, hwnd } => (WM_COMMAND, make_wparam(id, code), hwnd),
+            Message::Timer { id } => (WM_TIMER, id, 0),
+            Message::ContextMenu { hwnd, position } => {
+                let (x, y) = position.unwrap_or((-1, -1));
+                (WM_CONTEXTMENU, hwnd as usize, point_lparam(x, y))
+            },
+            Message::MouseMove { keys, x, y } => {
+                (WM_MOUSEMOVE, keys as usize, point_lparam(x, y))
+            },
+            Message::LButtonDown { keys, x, y } => {
+                (WM_LBUTTONDOWN, keys as usize, point_lparam(x, y))
+            },
+            Message::MouseWheel { delta, keys, x, y } => {
+                (WM_MOUSEWHEEL, make_wparam(keys, delta as u16), point_lparam(x, y))
+            },
+            Message::DpiChanged { dpi, suggested } => {
+                (WM_DPICHANGED, make_wparam(dpi, dpi), suggested)
+            },
+            Message::Close => (WM_CLOSE, 0, 0),
+            Message::Destroy => (WM_DESTROY, 0, 0),
+            Message::NcDestroy => (WM_NCDESTROY, 0, 0),
+            Message::Other { msg, wparam, lparam } => (msg, wparam, lparam),
+        }
+    }
+}
+
+// only the last layout, tick or mouse position is worth handling late
+impl Coalesce for Message {
+    fn replaces(&self, older: &Self) -> bool {
+        match (self, older) {
+            (Message::Size { .. }, Message::Size { .. }) => true,
+            (Message::MouseMove { .. }, Message::MouseMove { .. }) => true,
+            (Message::Timer { id }, Message::Timer { id: older }) => id == older,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(message: Message) {
+        let (msg, wparam, lparam) = message.to_raw();
+        assert_eq!(Message::crack(msg, wparam, lparam), message);
+    }
+
+    #[test]
+    fn size() {
+        assert_eq!(
+            Message::crack(WM_SIZE, 2, (600 << 16) | 800),
+            Message::Size { kind: SizeKind::Maximized, width: 800, height: 600 }
+        );
+        // sizes above 32767 stay positive
+        round_trip(Message::Size { kind: SizeKind::Restored, width: 40000, height: 65535 });
+        round_trip(Message::Size { kind: SizeKind::Other(9), width: 1, height: 2 });
+    }
+
+    #[test]
+    fn command() {
+        let wparam = (0x0300 << 16) | 4;
+        assert_eq!(
+            Message::crack(WM_COMMAND, wparam, 0x1234),
+            Message::Command { id: 4, code: 0x0300, hwnd: 0x1234 }
+        );
+        // a 64-bit wparam with garbage above the low dword
+        assert_eq!(
+            Message::crack(WM_COMMAND, 0xffff_ffff_0000_012d_usize, 0),
+            Message::Command { id: 301, code: 0, hwnd: 0 }
+        );
+        round_trip(Message::Command { id: 0xffff, code: 0xffff, hwnd: -1 });
+    }
+
+    #[test]
+    fn negative_coordinates() {
+        // a second monitor left of and above the primary one
+        let lparam = make_lparam(-300i16 as u16, -20i16 as u16);
+        assert!(lparam < 0);
+        assert_eq!(x_lparam(lparam), -300);
+        assert_eq!(y_lparam(lparam), -20);
+        assert_eq!(
+            Message::crack(WM_MOUSEMOVE, 1, lparam),
+            Message::MouseMove { keys: 1, x: -300, y: -20 }
+        );
+        round_trip(Message::LButtonDown { keys: 0, x: -32768, y: 32767 });
+        round_trip(Message::MouseMove { keys: 8, x: 5, y: -1 });
+    }
+
+    #[test]
+    fn context_menu_from_the_keyboard() {
+        assert_eq!(
+            Message::crack(WM_CONTEXTMENU, 7, -1),
+            Message::ContextMenu { hwnd: 7, position: None }
+        );
+        round_trip(Message::ContextMenu { hwnd: 7, position: Some((-10, 20)) });
+        round_trip(Message::ContextMenu { hwnd: 7, position: None });
+    }
+
+    #[test]
+    fn mouse_wheel() {
+        let wparam = make_wparam(0x0008, (-WHEEL_DELTA) as u16);
+        assert_eq!(
+            Message::crack(WM_MOUSEWHEEL, wparam, make_lparam(100, -5i16 as u16)),
+            Message::MouseWheel { delta: -120, keys: 8, x: 100, y: -5 }
+        );
+        round_trip(Message::MouseWheel { delta: 240, keys: 0, x: -1, y: -1 });
+    }
+
+    #[test]
+    fn dpi_changed_and_others() {
+        assert_eq!(
+            Message::crack(WM_DPICHANGED, (144 << 16) | 144, 0x5000),
+            Message::DpiChanged { dpi: 144, suggested: 0x5000 }
+        );
+        round_trip(Message::Timer { id: 1 });
+        round_trip(Message::Close);
+        round_trip(Message::NcDestroy);
+        round_trip(Message::Other { msg: 0x0400 + 1, wparam: usize::MAX, lparam: isize::MIN });
+    }
+
+    #[test]
+    fn late_messages_coalesce() {
+        let size = |width| Message::Size { kind: SizeKind::Restored, width, height: 1 };
+        assert!(size(2).replaces(&size(1)));
+        assert!(Message::Timer { id: 1 }.replaces(&Message::Timer { id: 1 }));
+        assert!(!Message::Timer { id: 1 }.replaces(&Message::Timer { id: 2 }));
+        // every command counts
+        let click = Message::Command { id: 2, code: 0, hwnd: 9 };
+        assert!(!click.replaces(&click));
+    }
+}
@@ -0,0 +1,41 @@
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+pub const APP_DIR: &str = "windows-app";
+
+/// Per-user folder of the application, `%LOCALAPPDATA%\windows-app`. The
+/// temp folder stands in when the variable is missing, so there's always
+/// somewhere to write.
+pub fn app_data_dir() -> PathBuf {
+    app_data_dir_from(std::env::var_os("LOCALAPPDATA"), std::env::temp_dir())
+}
+
+pub fn app_data_dir_from(local_app_data: Option<OsString>, temp: PathBuf) -> PathBuf {
+    match local_app_data.filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join(APP_DIR),
+        None => temp.join(APP_DIR),
+    }
+}
+
+/// Where crash reports go.
+pub fn crash_dir() -> PathBuf {
+    app_data_dir().join("crashes")
+}
+
+/// Where the diagnostics log rotates.
+pub fn log_dir() -> PathBuf {
+    app_data_dir().join("logs")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_app_data_first() {
+        let dir = app_data_dir_from(Some("C:/Users/a/AppData/Local".into()), "/tmp".into());
+        assert_eq!(dir, PathBuf::from("C:/Users/a/AppData/Local").join(APP_DIR));
+        assert_eq!(app_data_dir_from(None, "/tmp".into()), PathBuf::from("/tmp").join(APP_DIR));
+        assert_eq!(app_data_dir_from(Some("".into()), "/tmp".into()), PathBuf::from("/tmp").join(APP_DIR));
+    }
+}
@@ -0,0 +1,368 @@
+use std::time::{Duration, Instant};
+
+use crate::progress_tree::ProgressTree;
+use crate::status::format_elapsed;
+
+// weight of the newest throughput sample in the moving average
+const RATE_SMOOTHING: f64 = 0.3;
+// resolution of the overall fraction of staged tasks fed to the estimator
+const FRACTION_UNITS: f64 = 10_000.0;
+
+/// Mirrors the states of the progress bar (PBST_* and PBS_MARQUEE).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ProgressState {
+    #[default]
+    Normal,
+    Indeterminate,
+    Paused,
+    Error,
+}
+
+/// What a worker reports, sent to the window with APP_UPDATE_PROGRESS.
+/// A `total` of 0 means the amount of work isn't known (yet). Staged
+/// tasks count `done/total` within the current stage and carry the
+/// rolled-up `fraction` of the whole task.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProgressUpdate {
+    pub done: usize,
+    pub total: usize,
+    pub state: ProgressState,
+    pub stage: String,
+    pub fraction: Option<f64>,
+}
+
+impl ProgressUpdate {
+    pub fn new(done: usize, total: usize) -> Self {
+        Self { done, total, ..Default::default() }
+    }
+
+    /// Snapshot of the stage the task of `tree` is working on.
+    pub fn from_tree(tree: &ProgressTree) -> Self {
+        let current = tree.current();
+        let (done, total) = tree.counts(current);
+        Self {
+            done: done as usize,
+            total: total as usize,
+            stage: tree.label(current),
+            fraction: Some(tree.overall()),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_state(mut self, state: ProgressState) -> Self {
+        self.state = state;
+        self
+    }
+}
+
+/// Smoothed items per second from (time, done) samples.
+#[derive(Clone, Debug, Default)]
+pub struct RateEstimator {
+    last: Option<(Instant, usize)>,
+    rate: Option<f64>,
+}
+
+impl RateEstimator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn reset(&mut self) {
+        self.last = None;
+        self.rate = None;
+    }
+
+    /// Forgets the last sample but keeps the rate, so time spent paused
+    /// doesn't count as a slow interval.
+    pub fn suspend(&mut self) {
+        self.last = None;
+    }
+
+    pub fn sample(&mut self, now: Instant, done: usize) {
+        let Some((at, prev)) = self.last else {
+            self.last = Some((now, done));
+            return;
+        };
+        if done < prev {
+            // the task restarted its count
+            self.reset();
+            self.last = Some((now, done));
+            return;
+        }
+        let dt = now.saturating_duration_since(at).as_secs_f64();
+        if dt <= 0.0 {
+            // wait for time to pass, the items are counted next sample
+            return;
+        }
+        let current = (done - prev) as f64 / dt;
+        self.rate = Some(match self.rate {
+            Some(rate) => RATE_SMOOTHING * current + (1.0 - RATE_SMOOTHING) * rate,
+            None => current,
+        });
+        self.last = Some((now, done));
+    }
+
+    pub fn rate(&self) -> Option<f64> {
+        self.rate
+    }
+
+    pub fn eta(&self, remaining: usize) -> Option<Duration> {
+        match self.rate {
+            Some(rate) if rate > 0.0 => Some(Duration::from_secs_f64(remaining as f64 / rate)),
+            _ => None,
+        }
+    }
+}
+
+/// Progress of the running task as shown by `progress_bar` and
+/// `progress_txt`.
+#[derive(Clone, Debug, Default)]
+pub struct ProgressModel {
+    state: ProgressState,
+    done: usize,
+    total: usize,
+    stage: String,
+    fraction: Option<f64>,
+    estimator: RateEstimator,
+}
+
+impl ProgressModel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn state(&self) -> ProgressState {
+        self.state
+    }
+
+    pub fn done(&self) -> usize {
+        self.done
+    }
+
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    pub fn stage(&self) -> &str {
+        &self.stage
+    }
+
+    /// Rolled-up progress of a staged task.
+    pub fn fraction(&self) -> Option<f64> {
+        self.fraction
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn apply(&mut self, now: Instant, update: &ProgressUpdate) {
+        let fraction = update.fraction.map(|f| f.clamp(0.0, 1.0));
+        let state = match update.state {
+            ProgressState::Normal if update.total == 0 && fraction.is_none() => {
+                ProgressState::Indeterminate
+            },
+            state => state,
+        };
+        // stages restart their count, the eta of a staged task follows
+        // the overall fraction instead
+        let sampled = match fraction {
+            Some(f) => (f * FRACTION_UNITS) as usize,
+            None => update.done,
+        };
+        match state {
+            ProgressState::Paused => self.estimator.suspend(),
+            ProgressState::Error => {},
+            _ => self.estimator.sample(now, sampled),
+        }
+        self.state = state;
+        self.done = update.done;
+        self.total = update.total;
+        self.stage = update.stage.clone();
+        self.fraction = fraction;
+    }
+
+    pub fn set_state(&mut self, state: ProgressState) {
+        if state == ProgressState::Paused {
+            self.estimator.suspend();
+        }
+        self.state = state;
+    }
+
+    /// Position of the bar in 0..=100.
+    pub fn percent(&self) -> usize {
+        if let Some(fraction) = self.fraction {
+            return (fraction * 100.0) as usize;
+        }
+        match self.total {
+            0 => 0,
+            total => self.done.min(total) * 100 / total,
+        }
+    }
+
+    pub fn rate(&self) -> Option<f64> {
+        self.estimator.rate()
+    }
+
+    pub fn eta(&self) -> Option<Duration> {
+        if let Some(fraction) = self.fraction {
+            return match self.state {
+                ProgressState::Normal => {
+                    self.estimator.eta(((1.0 - fraction) * FRACTION_UNITS) as usize)
+                },
+                _ => None,
+            };
+        }
+        match (self.state, self.total) {
+            (ProgressState::Normal, total) if total > 0 => {
+                self.estimator.eta(total.saturating_sub(self.done))
+            },
+            _ => None,
+        }
+    }
+
+    /// e.g. "12/30 · 3.4 items/s · 00:05 left", staged tasks lead with
+    /// the stage and leave out the rate: "hash · 12/30 · 00:05 left"
+    pub fn text(&self) -> String {
+        let mut parts = Vec::new();
+        if !self.stage.is_empty() {
+            parts.push(self.stage.clone());
+        }
+        parts.push(match self.total {
+            0 => format!("{}", self.done),
+            total => format!("{}/{}", self.done, total),
+        });
+        match self.state {
+            ProgressState::Paused => parts.push("paused".to_string()),
+            ProgressState::Error => parts.push("error".to_string()),
+            _ => {
+                if let Some(rate) = self.rate().filter(|_| self.fraction.is_none()) {
+                    parts.push(format_rate(rate));
+                }
+                if let Some(eta) = self.eta() {
+                    parts.push(format!("{} left", format_elapsed(eta)));
+                }
+            },
+        }
+        parts.join(" · ")
+    }
+}
+
+pub fn format_rate(rate: f64) -> String {
+    match rate < 10.0 {
+        true => format!("{:.1} items/s", rate),
+        false => format!("{:.0} items/s", rate),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(s: f64) -> Duration {
+        Duration::from_secs_f64(s)
+    }
+
+    #[test]
+    fn estimator_smooths_rate() {
+        let t0 = Instant::now();
+        let mut est = RateEstimator::new();
+        est.sample(t0, 0);
+        assert_eq!(est.rate(), None);
+        est.sample(t0 + secs(1.0), 4);
+        assert_eq!(est.rate(), Some(4.0));
+        est.sample(t0 + secs(2.0), 6);
+        // 0.3 * 2 + 0.7 * 4
+        assert!((est.rate().unwrap() - 3.4).abs() < 1e-9);
+        assert_eq!(est.eta(17), Some(secs(5.0)));
+    }
+
+    #[test]
+    fn estimator_restarts_when_count_goes_back() {
+        let t0 = Instant::now();
+        let mut est = RateEstimator::new();
+        est.sample(t0, 10);
+        est.sample(t0 + secs(1.0), 20);
+        est.sample(t0 + secs(2.0), 0);
+        assert_eq!(est.rate(), None);
+        // same instant: wait for time to pass
+        est.sample(t0 + secs(2.0), 5);
+        est.sample(t0 + secs(3.0), 5);
+        assert_eq!(est.rate(), Some(5.0));
+    }
+
+    #[test]
+    fn text_shows_rate_and_eta() {
+        let t0 = Instant::now();
+        let mut model = ProgressModel::new();
+        model.apply(t0, &ProgressUpdate::new(0, 30));
+        assert_eq!(model.text(), "0/30");
+        model.apply(t0 + secs(1.0), &ProgressUpdate::new(4, 30));
+        model.apply(t0 + secs(2.0), &ProgressUpdate::new(12, 30));
+        // 0.3 * 8 + 0.7 * 4 = 5.2 items/s, 18 left
+        assert_eq!(model.text(), "12/30 · 5.2 items/s · 00:03 left");
+        assert_eq!(model.percent(), 40);
+    }
+
+    #[test]
+    fn unknown_total_is_indeterminate() {
+        let t0 = Instant::now();
+        let mut model = ProgressModel::new();
+        model.apply(t0, &ProgressUpdate::new(3, 0));
+        model.apply(t0 + secs(0.5), &ProgressUpdate::new(30, 0));
+        assert_eq!(model.state(), ProgressState::Indeterminate);
+        assert_eq!(model.percent(), 0);
+        assert_eq!(model.text(), "30 · 54 items/s");
+    }
+
+    #[test]
+    fn pause_does_not_slow_the_rate() {
+        let t0 = Instant::now();
+        let mut model = ProgressModel::new();
+        model.apply(t0, &ProgressUpdate::new(0, 30));
+        model.apply(t0 + secs(1.0), &ProgressUpdate::new(2, 30));
+        model.apply(
+            t0 + secs(1.0),
+            &ProgressUpdate::new(2, 30).with_state(ProgressState::Paused)
+        );
+        assert_eq!(model.text(), "2/30 · paused");
+        assert_eq!(model.eta(), None);
+        // resumed a minute later
+        model.apply(t0 + secs(61.0), &ProgressUpdate::new(2, 30));
+        model.apply(t0 + secs(62.0), &ProgressUpdate::new(4, 30));
+        assert!((model.rate().unwrap() - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn staged_updates_show_stage_and_overall_position() {
+        let t0 = Instant::now();
+        let mut tree = ProgressTree::new("task");
+        let scan = tree.add_stage(tree.root(), "scan", 1.0);
+        let hash = tree.add_stage(tree.root(), "hash", 3.0);
+        let mut model = ProgressModel::new();
+        tree.report(scan, 0, 0);
+        model.apply(t0, &ProgressUpdate::from_tree(&tree));
+        // an unknown stage total isn't a marquee, the overall position is known
+        assert_eq!(model.state(), ProgressState::Normal);
+        tree.finish(scan);
+        tree.report(hash, 0, 10);
+        model.apply(t0 + secs(1.0), &ProgressUpdate::from_tree(&tree));
+        assert_eq!(model.percent(), 25);
+        tree.report(hash, 2, 10);
+        model.apply(t0 + secs(2.0), &ProgressUpdate::from_tree(&tree));
+        assert_eq!(model.percent(), 40);
+        // 2500 then 1500 units per second: 0.3 * 1500 + 0.7 * 2500 = 2200
+        assert_eq!(model.text(), "hash · 2/10 · 00:02 left");
+    }
+
+    #[test]
+    fn error_keeps_position() {
+        let mut model = ProgressModel::new();
+        model.apply(Instant::now(), &ProgressUpdate::new(7, 10));
+        model.set_state(ProgressState::Error);
+        assert_eq!(model.percent(), 70);
+        assert_eq!(model.text(), "7/10 · error");
+        model.apply(Instant::now(), &ProgressUpdate::new(50, 10));
+        assert_eq!(model.percent(), 100);
+    }
+}
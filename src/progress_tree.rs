@@ -0,0 +1,259 @@
+/// Handle to a scope of a [`ProgressTree`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScopeId(usize);
+
+#[derive(Clone, Debug)]
+struct Scope {
+    name: String,
+    weight: f64,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    done: u64,
+    total: u64,
+    started: bool,
+    finished: bool,
+}
+
+/// Nested progress of a multi-stage task. A scope either reports its own
+/// `done/total` or is split into weighted child scopes, its fraction is
+/// then the weighted mean of theirs.
+#[derive(Clone, Debug)]
+pub struct ProgressTree {
+    scopes: Vec<Scope>,
+}
+
+impl ProgressTree {
+    pub fn new(name: &str) -> Self {
+        Self {
+            scopes: vec![Scope {
+                name: name.to_string(),
+                weight: 1.0,
+                parent: None,
+                children: Vec::new(),
+                done: 0,
+                total: 0,
+                started: true,
+                finished: false,
+            }],
+        }
+    }
+
+    pub fn root(&self) -> ScopeId {
+        ScopeId(0)
+    }
+
+    /// Allocates a sub-range of `parent` worth `weight` relative to its
+    /// siblings. Negative or non finite weights count as 0.
+    pub fn add_stage(&mut self, parent: ScopeId, name: &str, weight: f64) -> ScopeId {
+        let weight = match weight.is_finite() && weight > 0.0 {
+            true => weight,
+            false => 0.0,
+        };
+        let id = self.scopes.len();
+        self.scopes.push(Scope {
+            name: name.to_string(),
+            weight,
+            parent: Some(parent.0),
+            children: Vec::new(),
+            done: 0,
+            total: 0,
+            started: false,
+            finished: false,
+        });
+        self.scopes[parent.0].children.push(id);
+        ScopeId(id)
+    }
+
+    /// Reports progress inside `scope`. `done` is clamped to `total` and
+    /// never goes back for the same total, so late updates are harmless.
+    /// Reports to finished scopes are ignored.
+    pub fn report(&mut self, scope: ScopeId, done: u64, total: u64) {
+        if self.is_finished(scope) {
+            return;
+        }
+        let s = &mut self.scopes[scope.0];
+        let done = done.min(total);
+        if total == s.total && done < s.done {
+            return;
+        }
+        s.done = done;
+        s.total = total;
+        self.mark_started(scope.0);
+    }
+
+    /// Completes `scope` and everything below it.
+    pub fn finish(&mut self, scope: ScopeId) {
+        self.mark_started(scope.0);
+        let mut stack = vec![scope.0];
+        while let Some(i) = stack.pop() {
+            let s = &mut self.scopes[i];
+            s.finished = true;
+            stack.extend(s.children.iter().copied());
+        }
+    }
+
+    /// True when the scope or one of its parents is finished.
+    pub fn is_finished(&self, scope: ScopeId) -> bool {
+        let mut i = Some(scope.0);
+        while let Some(j) = i {
+            if self.scopes[j].finished {
+                return true;
+            }
+            i = self.scopes[j].parent;
+        }
+        false
+    }
+
+    /// Progress of `scope` in 0.0..=1.0.
+    pub fn fraction(&self, scope: ScopeId) -> f64 {
+        let s = &self.scopes[scope.0];
+        if s.finished {
+            return 1.0;
+        }
+        if s.children.is_empty() {
+            return match s.total {
+                0 => 0.0,
+                total => s.done as f64 / total as f64,
+            };
+        }
+        let weight: f64 = s.children.iter().map(|c| self.scopes[*c].weight).sum();
+        if weight <= 0.0 {
+            // only zero-weight stages: count them equally
+            let n = s.children.len() as f64;
+            return s.children.iter().map(|c| self.fraction(ScopeId(*c))).sum::<f64>() / n;
+        }
+        let sum: f64 = s.children
+            .iter()
+            .map(|c| self.scopes[*c].weight * self.fraction(ScopeId(*c)))
+            .sum();
+        (sum / weight).clamp(0.0, 1.0)
+    }
+
+    pub fn overall(&self) -> f64 {
+        self.fraction(self.root())
+    }
+
+    /// The deepest started, unfinished scope, the one the task works on.
+    pub fn current(&self) -> ScopeId {
+        let mut current = 0;
+        loop {
+            let next = self.scopes[current]
+                .children
+                .iter()
+                .rev()
+                .find(|c| self.scopes[**c].started && !self.scopes[**c].finished);
+            match next {
+                Some(c) => current = *c,
+                None => return ScopeId(current),
+            }
+        }
+    }
+
+    /// Names from the first stage down to `scope`, e.g. "scan › hash".
+    pub fn label(&self, scope: ScopeId) -> String {
+        let mut names = Vec::new();
+        let mut i = Some(scope.0);
+        while let Some(j) = i {
+            // the root is the task itself
+            if self.scopes[j].parent.is_some() {
+                names.push(self.scopes[j].name.as_str());
+            }
+            i = self.scopes[j].parent;
+        }
+        names.reverse();
+        names.join(" › ")
+    }
+
+    pub fn counts(&self, scope: ScopeId) -> (u64, u64) {
+        let s = &self.scopes[scope.0];
+        (s.done, s.total)
+    }
+
+    fn mark_started(&mut self, mut i: usize) {
+        loop {
+            self.scopes[i].started = true;
+            match self.scopes[i].parent {
+                Some(p) => i = p,
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn weights_split_the_range() {
+        let mut tree = ProgressTree::new("task");
+        let scan = tree.add_stage(tree.root(), "scan", 1.0);
+        let hash = tree.add_stage(tree.root(), "hash", 3.0);
+        tree.report(scan, 5, 10);
+        assert!(close(tree.overall(), 0.125));
+        tree.finish(scan);
+        tree.report(hash, 1, 3);
+        assert!(close(tree.overall(), 0.5));
+        assert_eq!(tree.current(), hash);
+        assert_eq!(tree.label(hash), "hash");
+    }
+
+    #[test]
+    fn nested_scopes_roll_up() {
+        let mut tree = ProgressTree::new("task");
+        let a = tree.add_stage(tree.root(), "a", 1.0);
+        let b = tree.add_stage(tree.root(), "b", 1.0);
+        let b1 = tree.add_stage(b, "b1", 1.0);
+        let b2 = tree.add_stage(b, "b2", 1.0);
+        tree.finish(a);
+        tree.report(b2, 1, 2);
+        assert!(close(tree.fraction(b), 0.25));
+        assert!(close(tree.overall(), 0.625));
+        assert_eq!(tree.current(), b2);
+        assert_eq!(tree.label(b2), "b › b2");
+        tree.finish(b);
+        assert!(tree.is_finished(b1));
+        assert!(close(tree.overall(), 1.0));
+        assert_eq!(tree.current(), tree.root());
+    }
+
+    #[test]
+    fn overflow_is_clamped() {
+        let mut tree = ProgressTree::new("task");
+        let s = tree.add_stage(tree.root(), "s", 1.0);
+        tree.report(s, 40, 30);
+        assert_eq!(tree.counts(s), (30, 30));
+        assert!(close(tree.overall(), 1.0));
+        let z = tree.add_stage(tree.root(), "z", f64::NAN);
+        tree.report(z, 0, 1);
+        assert!(close(tree.overall(), 1.0));
+    }
+
+    #[test]
+    fn late_updates_are_ignored() {
+        let mut tree = ProgressTree::new("task");
+        let s = tree.add_stage(tree.root(), "s", 1.0);
+        tree.report(s, 8, 10);
+        tree.report(s, 6, 10);
+        assert_eq!(tree.counts(s), (8, 10));
+        // a new total restarts the count
+        tree.report(s, 2, 20);
+        assert_eq!(tree.counts(s), (2, 20));
+        tree.finish(s);
+        tree.report(s, 3, 20);
+        assert!(close(tree.fraction(s), 1.0));
+    }
+
+    #[test]
+    fn zero_weight_stages_count_equally() {
+        let mut tree = ProgressTree::new("task");
+        let a = tree.add_stage(tree.root(), "a", 0.0);
+        tree.add_stage(tree.root(), "b", -1.0);
+        tree.finish(a);
+        assert!(close(tree.overall(), 0.5));
+    }
+}
@@ -0,0 +1,265 @@
+use std::cell::{RefCell, RefMut};
+use std::collections::VecDeque;
+
+/// Deferred messages that only matter in their latest version, like a
+/// resize or a progress update, replace the older one still queued.
+pub trait Coalesce {
+    fn replaces(&self, _older: &Self) -> bool {
+        false
+    }
+}
+
+/// Messages that arrived while the state was busy, in arrival order.
+#[derive(Clone, Debug)]
+pub struct DeferQueue<M> {
+    queue: VecDeque<M>,
+}
+
+impl<M> Default for DeferQueue<M> {
+    fn default() -> Self {
+        Self { queue: VecDeque::new() }
+    }
+}
+
+impl<M: Coalesce> DeferQueue<M> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, message: M) {
+        self.queue.retain(|older| !message.replaces(older));
+        self.queue.push_back(message);
+    }
+
+    pub fn pop(&mut self) -> Option<M> {
+        self.queue.pop_front()
+    }
+
+    /// Puts back a message that couldn't be handled yet.
+    pub fn unpop(&mut self, message: M) {
+        self.queue.push_front(message);
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+/// State of a window shared with its wndproc. A message that arrives while
+/// a handler holds the state (SendMessageW to ourselves, a modal loop run
+/// from a handler) can't borrow it again, it's queued and handled once the
+/// outer handler is done.
+///
+/// It's the only way to the state: subclass procs and other callbacks get
+/// no pointer to it, they send the window a message or use `try_enter`. A
+/// raw pointer would alias the `&mut` of whatever handler they run under.
+pub struct StateCell<T, M> {
+    value: RefCell<T>,
+    queue: RefCell<DeferQueue<M>>,
+}
+
+impl<T, M: Coalesce> StateCell<T, M> {
+    pub fn new(value: T) -> Self {
+        Self { value: RefCell::new(value), queue: RefCell::new(DeferQueue::new()) }
+    }
+
+    /// The state, `None` while a handler up the stack has it.
+    pub fn try_enter(&self) -> Option<RefMut<'_, T>> {
+        self.value.try_borrow_mut().ok()
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.value.try_borrow_mut().is_err()
+    }
+
+    pub fn defer(&self, message: M) {
+        self.queue.borrow_mut().push(message);
+    }
+
+    pub fn pending(&self) -> usize {
+        self.queue.borrow().len()
+    }
+
+    /// Handles the queued messages, including the ones queued by the
+    /// handlers themselves. Stops early when called from inside a handler,
+    /// the outer call gets to them.
+    pub fn drain(&self, mut handle: impl FnMut(&mut T, M)) {
+        loop {
+            let Some(message) = self.queue.borrow_mut().pop() else {
+                return;
+            };
+            let Ok(mut value) = self.value.try_borrow_mut() else {
+                self.queue.borrow_mut().unpop(message);
+                return;
+            };
+            handle(&mut value, message);
+        }
+    }
+}
+
+#[cfg(windows)]
+mod win32 {
+    use std::rc::Rc;
+
+    use windows::Win32::{Foundation::HWND, UI::WindowsAndMessaging::*};
+
+    use super::*;
+
+    // the window holds one reference in GWLP_USERDATA from WM_NCCREATE to
+    // WM_NCDESTROY, every message being handled holds another one, so a
+    // handler that destroys its own window keeps using a live state
+    impl<T, M: Coalesce> StateCell<T, M> {
+        /// Moves `value` into `window`.
+        ///
+        /// # Safety
+        /// `window` must not have a state yet, `detach` has to be called in
+        /// its WM_NCDESTROY with the same `T` and `M`.
+        pub unsafe fn attach(window: HWND, value: T) {
+            let state = Rc::into_raw(Rc::new(Self::new(value)));
+            SetWindowLongPtrW(window, GWLP_USERDATA, state as _);
+        }
+
+        /// A reference to the state of `window` for the message at hand,
+        /// `None` before WM_NCCREATE and after WM_NCDESTROY.
+        ///
+        /// # Safety
+        /// The state of `window` has to be attached with the same `T`, `M`.
+        pub unsafe fn of(window: HWND) -> Option<Rc<Self>> {
+            let state = GetWindowLongPtrW(window, GWLP_USERDATA) as *const Self;
+            if state.is_null() {
+                return None;
+            }
+            Rc::increment_strong_count(state);
+            Some(Rc::from_raw(state))
+        }
+
+        /// Drops the reference of `window`, the state goes once the
+        /// handlers still running are done with it.
+        ///
+        /// # Safety
+        /// The state of `window` has to be attached with the same `T`, `M`.
+        pub unsafe fn detach(window: HWND) {
+            let state = SetWindowLongPtrW(window, GWLP_USERDATA, 0) as *const Self;
+            if !state.is_null() {
+                drop(Rc::from_raw(state));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    #[derive(Clone, Debug, PartialEq)]
+    enum Msg {
+        Size(u32),
+        Command(u32),
+    }
+
+    impl Coalesce for Msg {
+        fn replaces(&self, older: &Self) -> bool {
+            matches!((self, older), (Msg::Size(_), Msg::Size(_)))
+        }
+    }
+
+    type Cell = StateCell<Vec<Msg>, Msg>;
+
+    // what a wndproc does with a message
+    fn dispatch(cell: &Cell, msg: Msg, reenter: &dyn Fn(&Cell)) {
+        match cell.try_enter() {
+            Some(mut log) => {
+                log.push(msg);
+                drop(log);
+                reenter(cell);
+                cell.drain(|log, m| log.push(m));
+            },
+            None => cell.defer(msg),
+        }
+    }
+
+    #[test]
+    fn reentrant_messages_run_after_the_handler() {
+        let cell = StateCell::new(Vec::new());
+        {
+            let mut log = cell.try_enter().unwrap();
+            log.push(Msg::Command(1));
+            // a SendMessageW to ourselves from inside the handler
+            assert!(cell.try_enter().is_none());
+            assert!(cell.is_busy());
+            cell.defer(Msg::Command(2));
+            cell.defer(Msg::Command(3));
+        }
+        cell.drain(|log, m| log.push(m));
+        assert_eq!(
+            *cell.try_enter().unwrap(),
+            [Msg::Command(1), Msg::Command(2), Msg::Command(3)]
+        );
+        assert_eq!(cell.pending(), 0);
+    }
+
+    #[test]
+    fn drain_inside_a_handler_leaves_the_queue_alone() {
+        let cell = StateCell::new(Vec::new());
+        let log = cell.try_enter().unwrap();
+        cell.defer(Msg::Command(1));
+        cell.drain(|log, m| log.push(m));
+        assert_eq!(cell.pending(), 1);
+        drop(log);
+        cell.drain(|log, m| log.push(m));
+        assert_eq!(*cell.try_enter().unwrap(), [Msg::Command(1)]);
+    }
+
+    #[test]
+    fn latest_resize_wins() {
+        let mut queue = DeferQueue::new();
+        queue.push(Msg::Size(1));
+        queue.push(Msg::Command(7));
+        queue.push(Msg::Size(2));
+        assert_eq!(queue.pop(), Some(Msg::Command(7)));
+        assert_eq!(queue.pop(), Some(Msg::Size(2)));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn messages_sent_while_draining_are_handled_too() {
+        let cell = StateCell::new(Vec::new());
+        let log = cell.try_enter().unwrap();
+        cell.defer(Msg::Command(1));
+        drop(log);
+        // handling 1 sends 2 to the window again
+        cell.drain(|log, m| {
+            log.push(m.clone());
+            if m == Msg::Command(1) {
+                assert!(cell.is_busy());
+                cell.defer(Msg::Command(2));
+            }
+        });
+        assert_eq!(*cell.try_enter().unwrap(), [Msg::Command(1), Msg::Command(2)]);
+    }
+
+    #[test]
+    fn nested_dispatch_keeps_order() {
+        let cell = StateCell::new(Vec::new());
+        dispatch(&cell, Msg::Command(1), &|cell| {
+            dispatch(cell, Msg::Command(2), &|_| {});
+        });
+        assert_eq!(*cell.try_enter().unwrap(), [Msg::Command(1), Msg::Command(2)]);
+    }
+
+    #[test]
+    fn state_outlives_its_owner_while_a_handler_runs() {
+        // the window drops its reference in WM_NCDESTROY, the handler
+        // that called DestroyWindow still holds one
+        let owner = Rc::new(Cell::new(Vec::new()));
+        let running = Rc::clone(&owner);
+        drop(owner);
+        running.try_enter().unwrap().push(Msg::Command(1));
+        assert_eq!(Rc::strong_count(&running), 1);
+    }
+}
@@ -0,0 +1,284 @@
+use std::io::{self, Write};
+
+// the edit control separates lines with crlf, offsets below are counted
+// in utf-16 units of that text so they can go straight to EM_SETSEL
+const LINE_END: &str = "\r\n";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogCommand {
+    Copy,
+    CopyAll,
+    SelectAll,
+    Clear,
+    SaveAs,
+    Find,
+    WrapLines,
+}
+
+impl LogCommand {
+    pub const ALL: [LogCommand; 7] = [
+        LogCommand::Copy,
+        LogCommand::CopyAll,
+        LogCommand::SelectAll,
+        LogCommand::Clear,
+        LogCommand::SaveAs,
+        LogCommand::Find,
+        LogCommand::WrapLines,
+    ];
+
+    // menu item ids, kept clear of the control and tray ids
+    pub fn id(&self) -> usize {
+        match self {
+            LogCommand::Copy => 201,
+            LogCommand::CopyAll => 202,
+            LogCommand::SelectAll => 203,
+            LogCommand::Clear => 204,
+            LogCommand::SaveAs => 205,
+            LogCommand::Find => 206,
+            LogCommand::WrapLines => 207,
+        }
+    }
+
+    pub fn from_id(id: usize) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.id() == id)
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            LogCommand::Copy => "複製",
+            LogCommand::CopyAll => "全部複製",
+            LogCommand::SelectAll => "全選",
+            LogCommand::Clear => "清除",
+            LogCommand::SaveAs => "另存新檔...",
+            LogCommand::Find => "尋找...",
+            LogCommand::WrapLines => "自動換行",
+        }
+    }
+
+    /// (ctrl, shift, virtual key) of the keyboard shortcut.
+    pub fn shortcut(&self) -> (bool, bool, u16) {
+        match self {
+            LogCommand::Copy => (true, false, b'C' as u16),
+            LogCommand::CopyAll => (true, true, b'C' as u16),
+            LogCommand::SelectAll => (true, false, b'A' as u16),
+            LogCommand::Clear => (true, false, b'L' as u16),
+            LogCommand::SaveAs => (true, false, b'S' as u16),
+            LogCommand::Find => (true, false, b'F' as u16),
+            LogCommand::WrapLines => (true, false, b'W' as u16),
+        }
+    }
+
+    pub fn from_shortcut(ctrl: bool, shift: bool, key: u16) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.shortcut() == (ctrl, shift, key))
+    }
+
+    /// Menu text with the shortcut after a tab, e.g. "複製\tCtrl+C".
+    pub fn menu_text(&self) -> String {
+        let (ctrl, shift, key) = self.shortcut();
+        let mut text = format!("{}\t", self.label());
+        if ctrl {
+            text.push_str("Ctrl+");
+        }
+        if shift {
+            text.push_str("Shift+");
+        }
+        text.push(key as u8 as char);
+        text
+    }
+}
+
+/// Content of the result log, the edit control only displays it.
+#[derive(Clone, Debug)]
+pub struct ResultLog {
+    lines: Vec<String>,
+    wrap: bool,
+}
+
+impl Default for ResultLog {
+    fn default() -> Self {
+        Self { lines: Vec::new(), wrap: true }
+    }
+}
+
+impl ResultLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    pub fn wrap(&self) -> bool {
+        self.wrap
+    }
+
+    pub fn toggle_wrap(&mut self) -> bool {
+        self.wrap = !self.wrap;
+        self.wrap
+    }
+
+    pub fn append(&mut self, line: &str) {
+        // keep one entry per displayed line
+        self.lines.extend(line.lines().map(str::to_string));
+        if line.is_empty() {
+            self.lines.push(String::new());
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+
+    /// The whole log as shown by the control, every line ended by crlf.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for line in &self.lines {
+            text.push_str(line);
+            text.push_str(LINE_END);
+        }
+        text
+    }
+
+    pub fn len_utf16(&self) -> usize {
+        self.lines.iter().map(|l| l.encode_utf16().count() + 2).sum()
+    }
+
+    /// Text between two utf-16 offsets, as returned by EM_GETSEL.
+    pub fn selected_text(&self, start: usize, end: usize) -> String {
+        let text: Vec<u16> = self.text().encode_utf16().collect();
+        let end = end.min(text.len());
+        let start = start.min(end);
+        String::from_utf16_lossy(&text[start..end])
+    }
+
+    /// Finds `query` starting at the utf-16 offset `from`, searching
+    /// towards the end when `down`, wrapping around once. Returns the
+    /// utf-16 range of the match.
+    pub fn find(
+        &self, query: &str, from: usize, match_case: bool, down: bool
+    ) -> Option<(usize, usize)> {
+        if query.is_empty() {
+            return None;
+        }
+        let fold = |s: &str| match match_case {
+            true => s.encode_utf16().collect::<Vec<u16>>(),
+            false => fold_case(s),
+        };
+        let text = fold(&self.text());
+        let query = fold(query);
+        if query.len() > text.len() {
+            return None;
+        }
+        let starts: Vec<usize> = (0..=text.len() - query.len())
+            .filter(|i| text[*i..*i + query.len()] == query[..])
+            .collect();
+        let found = match down {
+            true => starts.iter().find(|i| **i >= from).or(starts.first()),
+            false => starts.iter().rev().find(|i| **i < from).or(starts.last()),
+        };
+        found.map(|i| (*i, *i + query.len()))
+    }
+
+    pub fn save<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(self.text().as_bytes())?;
+        writer.flush()
+    }
+}
+
+// lowercase one char for one, so the offsets stay those of the text; a
+// char whose lowercase is longer (İ is i and a dot) stays as it is
+fn fold_case(s: &str) -> Vec<u16> {
+    let mut folded = Vec::with_capacity(s.len());
+    let mut buf = [0u16; 2];
+    for c in s.chars() {
+        let mut lower = c.to_lowercase();
+        let c = match (lower.next(), lower.next()) {
+            (Some(l), None) if l.len_utf16() == c.len_utf16() => l,
+            _ => c,
+        };
+        folded.extend_from_slice(c.encode_utf16(&mut buf));
+    }
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log() -> ResultLog {
+        let mut log = ResultLog::new();
+        log.append("line 1");
+        log.append("Line 2\nline 3");
+        log
+    }
+
+    #[test]
+    fn text_uses_crlf() {
+        let log = log();
+        assert_eq!(log.lines().len(), 3);
+        assert_eq!(log.text(), "line 1\r\nLine 2\r\nline 3\r\n");
+        assert_eq!(log.len_utf16(), log.text().encode_utf16().count());
+    }
+
+    #[test]
+    fn clear_and_wrap() {
+        let mut log = log();
+        assert!(log.wrap());
+        assert!(!log.toggle_wrap());
+        log.clear();
+        assert!(log.is_empty());
+        assert_eq!(log.text(), "");
+    }
+
+    #[test]
+    fn selection_is_in_utf16_units() {
+        let mut log = ResultLog::new();
+        log.append("進度😀ok");
+        assert_eq!(log.selected_text(2, 4), "😀");
+        assert_eq!(log.selected_text(4, 100), "ok\r\n");
+    }
+
+    #[test]
+    fn find_wraps_and_respects_case() {
+        let log = log();
+        assert_eq!(log.find("line", 0, true, true), Some((0, 4)));
+        assert_eq!(log.find("line", 1, true, true), Some((16, 20)));
+        assert_eq!(log.find("line", 17, true, true), Some((0, 4)));
+        assert_eq!(log.find("line", 1, false, true), Some((8, 12)));
+        assert_eq!(log.find("line", 8, false, false), Some((0, 4)));
+        assert_eq!(log.find("missing", 0, false, true), None);
+    }
+
+    #[test]
+    fn find_ignoring_case_keeps_offsets() {
+        // İ lowercases to two chars, the match after it mustn't shift
+        let mut log = ResultLog::new();
+        log.append("İstanbul Line");
+        let (start, end) = log.find("line", 0, false, true).unwrap();
+        assert_eq!((start, end), (9, 13));
+        assert_eq!(log.selected_text(start, end), "Line");
+        assert_eq!(log.find("İST", 0, false, true), Some((0, 3)));
+    }
+
+    #[test]
+    fn save_writes_the_text() {
+        let mut out = Vec::new();
+        log().save(&mut out).unwrap();
+        assert_eq!(out, log().text().into_bytes());
+    }
+
+    #[test]
+    fn shortcuts_map_back_to_commands() {
+        for command in LogCommand::ALL {
+            let (ctrl, shift, key) = command.shortcut();
+            assert_eq!(LogCommand::from_shortcut(ctrl, shift, key), Some(command));
+            assert_eq!(LogCommand::from_id(command.id()), Some(command));
+        }
+        assert_eq!(LogCommand::CopyAll.menu_text(), "全部複製\tCtrl+Shift+C");
+    }
+}
@@ -0,0 +1,327 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::error::AppError;
+use crate::json::Value;
+use crate::run_command::RunCommand;
+use crate::status::RunState;
+use crate::tasks;
+
+// error codes of JSON-RPC 2.0
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+/// The app refused, like a start while a task runs.
+pub const APP_ERROR: i64 = -32000;
+/// The window is in a dialog or gone, the request wasn't looked at.
+pub const UNAVAILABLE: i64 = -32001;
+
+/// Lines `tail_log` returns when the request doesn't say.
+pub const DEFAULT_TAIL: usize = 20;
+const MAX_TAIL: usize = 1000;
+
+/// What `status` reports.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Status {
+    pub state: RunState,
+    pub path: String,
+    pub task: String,
+    pub percent: usize,
+    pub stage: String,
+    pub done: usize,
+    pub total: usize,
+    pub elapsed: Duration,
+}
+
+impl Status {
+    fn to_json(&self) -> Value {
+        let state = match self.state {
+            RunState::Idle => "idle",
+            RunState::Running => "running",
+            RunState::Paused => "paused",
+            RunState::Done => "done",
+            RunState::Cancelled => "cancelled",
+            RunState::Failed => "failed",
+        };
+        Value::object([
+            ("state", state.into()),
+            ("path", self.path.as_str().into()),
+            ("task", self.task.as_str().into()),
+            ("percent", self.percent.into()),
+            ("stage", self.stage.as_str().into()),
+            ("done", self.done.into()),
+            ("total", self.total.into()),
+            ("elapsed_ms", (self.elapsed.as_millis() as u64).into()),
+        ])
+    }
+}
+
+/// What a script may do to the app, the same things the controls of the
+/// window do.
+pub trait Control {
+    fn status(&self) -> Status;
+    fn set_path(&mut self, path: &Path) -> Result<(), AppError>;
+    /// Like GO, with `task` instead of the current one when given, and
+    /// `command` as the line of the command task from now on.
+    fn start(&mut self, task: Option<&str>, command: Option<&str>) -> Result<(), AppError>;
+    /// False when nothing runs.
+    fn cancel(&mut self) -> bool;
+    /// The last `lines` lines of the result log.
+    fn tail_log(&self, lines: usize) -> Vec<String>;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Request {
+    /// None for a notification, it gets no response.
+    pub id: Option<Value>,
+    pub method: String,
+    pub params: Value,
+}
+
+pub fn error_response(id: Option<&Value>, code: i64, message: &str) -> Value {
+    Value::object([
+        ("jsonrpc", "2.0".into()),
+        ("id", id.cloned().unwrap_or(Value::Null)),
+        ("error", Value::object([("code", code.into()), ("message", message.into())])),
+    ])
+}
+
+fn result_response(id: Option<&Value>, result: Value) -> Value {
+    Value::object([
+        ("jsonrpc", "2.0".into()),
+        ("id", id.cloned().unwrap_or(Value::Null)),
+        ("result", result),
+    ])
+}
+
+/// Reads a request line. The error is the response to send back.
+pub fn parse_request(line: &str) -> Result<Request, Value> {
+    let value = Value::parse(line)
+        .map_err(|e| error_response(None, PARSE_ERROR, &e.to_string()))?;
+    let id = value.get("id").cloned();
+    let invalid = |message| error_response(id.as_ref(), INVALID_REQUEST, message);
+    if !matches!(value, Value::Object(_)) {
+        return Err(invalid("not an object"));
+    }
+    if value.get("jsonrpc").and_then(Value::as_str) != Some("2.0") {
+        return Err(invalid("jsonrpc must be \"2.0\""));
+    }
+    if !matches!(id, None | Some(Value::Null | Value::Number(_) | Value::String(_))) {
+        return Err(invalid("id must be a number or a string"));
+    }
+    let Some(method) = value.get("method").and_then(Value::as_str) else {
+        return Err(invalid("method must be a string"));
+    };
+    let params = match value.get("params") {
+        None => Value::Object(Vec::new()),
+        Some(params @ Value::Object(_)) => params.clone(),
+        Some(_) => return Err(invalid("params must be an object")),
+    };
+    Ok(Request { id, method: method.to_string(), params })
+}
+
+/// Runs `request` on `control`, None for a notification.
+pub fn handle(control: &mut dyn Control, request: &Request) -> Option<Value> {
+    let response = match call(control, &request.method, &request.params) {
+        Ok(result) => result_response(request.id.as_ref(), result),
+        Err((code, message)) => error_response(request.id.as_ref(), code, &message),
+    };
+    request.id.as_ref().map(|_| response)
+}
+
+fn call(control: &mut dyn Control, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+    let app_error = |e: AppError| (APP_ERROR, e.to_string());
+    match method {
+        "status" => Ok(control.status().to_json()),
+        "set_path" => {
+            let path = string_param(params, "path")?.ok_or_else(|| missing("path"))?;
+            control.set_path(&PathBuf::from(path)).map_err(app_error)?;
+            Ok(Value::Null)
+        },
+        "start" => {
+            let task = string_param(params, "task")?;
+            if let Some(task) = task {
+                if !tasks::is_known(task) {
+                    return Err((INVALID_PARAMS, format!("unknown task {}", task)));
+                }
+            }
+            // a command line means the command task, like --command
+            let command = string_param(params, "command")?;
+            let task = match (task, command) {
+                (None, Some(_)) => Some(RunCommand::NAME),
+                _ => task,
+            };
+            // the same as set_path and then start
+            if let Some(path) = string_param(params, "path")? {
+                control.set_path(&PathBuf::from(path)).map_err(app_error)?;
+            }
+            control.start(task, command).map_err(app_error)?;
+            Ok(Value::Null)
+        },
+        "cancel" => Ok(Value::object([("cancelled", control.cancel().into())])),
+        "tail_log" => {
+            let lines = match params.get("lines") {
+                None => DEFAULT_TAIL,
+                Some(lines) => match lines.as_u64() {
+                    Some(n) => (n as usize).min(MAX_TAIL),
+                    None => return Err((INVALID_PARAMS, "lines must be a whole number".to_string())),
+                },
+            };
+            let lines = control.tail_log(lines).into_iter().map(Value::from).collect();
+            Ok(Value::object([("lines", Value::Array(lines))]))
+        },
+        other => Err((METHOD_NOT_FOUND, format!("unknown method {}", other))),
+    }
+}
+
+fn string_param<'a>(params: &'a Value, name: &str) -> Result<Option<&'a str>, (i64, String)> {
+    match params.get(name) {
+        None => Ok(None),
+        Some(value) => match value.as_str() {
+            Some(s) => Ok(Some(s)),
+            None => Err((INVALID_PARAMS, format!("{} must be a string", name))),
+        },
+    }
+}
+
+fn missing(name: &str) -> (i64, String) {
+    (INVALID_PARAMS, format!("{} is missing", name))
+}
+
+/// The last `n` of `lines`.
+pub fn tail(lines: &[String], n: usize) -> Vec<String> {
+    lines[lines.len().saturating_sub(n)..].to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Fake {
+        path: PathBuf,
+        running: Option<String>,
+        command: Option<String>,
+        log: Vec<String>,
+    }
+
+    impl Control for Fake {
+        fn status(&self) -> Status {
+            Status {
+                state: match self.running {
+                    Some(_) => RunState::Running,
+                    None => RunState::Idle,
+                },
+                path: self.path.display().to_string(),
+                task: self.running.clone().unwrap_or_default(),
+                ..Default::default()
+            }
+        }
+
+        fn set_path(&mut self, path: &Path) -> Result<(), AppError> {
+            if self.running.is_some() {
+                return Err(AppError::config("a task is running"));
+            }
+            self.path = path.to_path_buf();
+            Ok(())
+        }
+
+        fn start(&mut self, task: Option<&str>, command: Option<&str>) -> Result<(), AppError> {
+            if self.running.is_some() {
+                return Err(AppError::config("a task is running"));
+            }
+            self.command = command.map(str::to_string);
+            self.running = Some(task.unwrap_or(tasks::DEFAULT_TASK).to_string());
+            Ok(())
+        }
+
+        fn cancel(&mut self) -> bool {
+            self.running.take().is_some()
+        }
+
+        fn tail_log(&self, lines: usize) -> Vec<String> {
+            tail(&self.log, lines)
+        }
+    }
+
+    fn exchange(control: &mut Fake, line: &str) -> String {
+        let response = match parse_request(line) {
+            Ok(request) => handle(control, &request),
+            Err(response) => Some(response),
+        };
+        response.map(|r| r.to_string()).unwrap_or_default()
+    }
+
+    #[test]
+    fn start_status_cancel() {
+        let mut fake = Fake::default();
+        assert_eq!(
+            exchange(&mut fake, r#"{"jsonrpc":"2.0","id":1,"method":"start","params":{"path":"/data"}}"#),
+            r#"{"jsonrpc":"2.0","id":1,"result":null}"#
+        );
+        assert_eq!(
+            exchange(&mut fake, r#"{"jsonrpc":"2.0","id":"s","method":"status"}"#),
+            r#"{"jsonrpc":"2.0","id":"s","result":{"state":"running","path":"/data","task":"demo","percent":0,"stage":"","done":0,"total":0,"elapsed_ms":0}}"#
+        );
+        assert_eq!(
+            exchange(&mut fake, r#"{"jsonrpc":"2.0","id":2,"method":"start"}"#),
+            r#"{"jsonrpc":"2.0","id":2,"error":{"code":-32000,"message":"bad configuration: a task is running"}}"#
+        );
+        assert_eq!(
+            exchange(&mut fake, r#"{"jsonrpc":"2.0","id":3,"method":"cancel"}"#),
+            r#"{"jsonrpc":"2.0","id":3,"result":{"cancelled":true}}"#
+        );
+        // a notification is carried out but not answered
+        assert_eq!(exchange(&mut fake, r#"{"jsonrpc":"2.0","method":"set_path","params":{"path":"/b"}}"#), "");
+        assert_eq!(fake.path, PathBuf::from("/b"));
+    }
+
+    #[test]
+    fn start_with_a_command() {
+        let mut fake = Fake::default();
+        assert_eq!(
+            exchange(&mut fake, r#"{"jsonrpc":"2.0","id":1,"method":"start","params":{"command":"make -j4"}}"#),
+            r#"{"jsonrpc":"2.0","id":1,"result":null}"#
+        );
+        assert_eq!(fake.running.as_deref(), Some("command"));
+        assert_eq!(fake.command.as_deref(), Some("make -j4"));
+    }
+
+    #[test]
+    fn tail_log_lines() {
+        let mut fake = Fake { log: vec!["a".into(), "b".into(), "c".into()], ..Default::default() };
+        assert_eq!(
+            exchange(&mut fake, r#"{"jsonrpc":"2.0","id":1,"method":"tail_log","params":{"lines":2}}"#),
+            r#"{"jsonrpc":"2.0","id":1,"result":{"lines":["b","c"]}}"#
+        );
+        assert_eq!(tail(&fake.log, 10), fake.log);
+        assert_eq!(tail(&fake.log, 0), Vec::<String>::new());
+    }
+
+    #[test]
+    fn bad_requests() {
+        let mut fake = Fake::default();
+        let code = |fake: &mut Fake, line: &str| {
+            let response = Value::parse(&exchange(fake, line)).unwrap();
+            response.get("error").and_then(|e| e.get("code")).cloned()
+        };
+        assert_eq!(code(&mut fake, "{"), Some(Value::from(PARSE_ERROR)));
+        assert_eq!(code(&mut fake, "[]"), Some(Value::from(INVALID_REQUEST)));
+        assert_eq!(code(&mut fake, r#"{"id":1,"method":"status"}"#), Some(Value::from(INVALID_REQUEST)));
+        assert_eq!(code(&mut fake, r#"{"jsonrpc":"2.0","id":[1],"method":"status"}"#), Some(Value::from(INVALID_REQUEST)));
+        assert_eq!(code(&mut fake, r#"{"jsonrpc":"2.0","id":1,"method":"status","params":[]}"#), Some(Value::from(INVALID_REQUEST)));
+        assert_eq!(code(&mut fake, r#"{"jsonrpc":"2.0","id":1,"method":"fly"}"#), Some(Value::from(METHOD_NOT_FOUND)));
+        assert_eq!(code(&mut fake, r#"{"jsonrpc":"2.0","id":1,"method":"set_path"}"#), Some(Value::from(INVALID_PARAMS)));
+        assert_eq!(
+            code(&mut fake, r#"{"jsonrpc":"2.0","id":1,"method":"start","params":{"task":"fly"}}"#),
+            Some(Value::from(INVALID_PARAMS))
+        );
+        assert_eq!(
+            code(&mut fake, r#"{"jsonrpc":"2.0","id":1,"method":"tail_log","params":{"lines":-1}}"#),
+            Some(Value::from(INVALID_PARAMS))
+        );
+        // nothing was started by the bad start
+        assert_eq!(fake.running, None);
+    }
+}
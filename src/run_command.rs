@@ -0,0 +1,372 @@
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::{AppError, Context};
+use crate::progress::ProgressUpdate;
+use crate::tasks::{Task, TaskSink};
+
+// how often a quiet command is checked for cancel
+const POLL: Duration = Duration::from_millis(100);
+// output of a background process that outlives the command isn't waited for
+const DRAIN: Duration = Duration::from_secs(1);
+// a longer line is passed on in pieces
+const MAX_LINE: usize = 64 * 1024;
+
+// no console window flashing up for a gui program
+#[cfg(windows)]
+const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+/// Progress a command prints, the last `NN%` of a line or else its last
+/// `x/y`.
+pub fn parse_progress(line: &str) -> Option<ProgressUpdate> {
+    let bytes = line.as_bytes();
+    let mut percent = None;
+    let mut count = None;
+    let mut i = 0;
+    while i < bytes.len() {
+        if !bytes[i].is_ascii_digit() || (i > 0 && is_number_byte(bytes[i - 1])) {
+            i += 1;
+            continue;
+        }
+        let end = number_end(bytes, i);
+        let number = &line[i..end];
+        match bytes.get(end) {
+            Some(b'%') => {
+                if let Some(p) = number.parse::<f64>().ok().filter(|p| *p <= 100.0) {
+                    percent = Some(p);
+                }
+            },
+            // the middle of 2024/10/18 isn't a count either
+            Some(b'/') if !number.contains('.') && (i == 0 || bytes[i - 1] != b'/') => {
+                let start = end + 1;
+                let total_end = number_end(bytes, start);
+                let total = line[start..total_end].parse::<usize>().ok();
+                let done = number.parse::<usize>().ok();
+                // not a date or a fraction like 1.5/2
+                let whole = bytes.get(total_end).is_none_or(|b| !is_number_byte(*b) && *b != b'/');
+                if let (Some(done), Some(total), true) = (done, total, whole) {
+                    if total > 0 && done <= total {
+                        count = Some((done, total));
+                    }
+                }
+            },
+            _ => {},
+        }
+        i = end.max(i + 1);
+    }
+    match (percent, count) {
+        (Some(p), _) => Some(ProgressUpdate {
+            fraction: Some(p / 100.0),
+            ..ProgressUpdate::new(p as usize, 100)
+        }),
+        (None, Some((done, total))) => Some(ProgressUpdate::new(done, total)),
+        (None, None) => None,
+    }
+}
+
+fn is_number_byte(b: u8) -> bool {
+    b.is_ascii_digit() || b == b'.'
+}
+
+fn number_end(bytes: &[u8], start: usize) -> usize {
+    let mut end = start;
+    let mut dot = false;
+    while let Some(&b) = bytes.get(end) {
+        match b {
+            b'0'..=b'9' => {},
+            // one dot followed by a digit, "3." ends a sentence
+            b'.' if !dot && bytes.get(end + 1).is_some_and(u8::is_ascii_digit) => dot = true,
+            _ => break,
+        }
+        end += 1;
+    }
+    end
+}
+
+/// Splits output on \n and on the \r progress bars redraw with, calling
+/// `line` for every line that isn't empty.
+pub fn split_lines<R: Read>(reader: R, mut line: impl FnMut(String)) -> io::Result<()> {
+    let mut reader = BufReader::new(reader);
+    let mut current = Vec::new();
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            break;
+        }
+        let n = buf.len();
+        for &b in buf {
+            match b {
+                b'\n' | b'\r' => {
+                    if !current.is_empty() {
+                        line(String::from_utf8_lossy(&current).into_owned());
+                        current.clear();
+                    }
+                },
+                b => {
+                    current.push(b);
+                    if current.len() == MAX_LINE {
+                        line(String::from_utf8_lossy(&current).into_owned());
+                        current.clear();
+                    }
+                },
+            }
+        }
+        reader.consume(n);
+    }
+    if !current.is_empty() {
+        line(String::from_utf8_lossy(&current).into_owned());
+    }
+    Ok(())
+}
+
+enum Output {
+    Out(String),
+    Err(String),
+}
+
+fn forward<R: Read + Send + 'static>(reader: R, sender: Sender<Output>, wrap: fn(String) -> Output) {
+    thread::spawn(move || {
+        // a read error is the same as the end of the output
+        let _ = split_lines(reader, |line| {
+            let _ = sender.send(wrap(line));
+        });
+    });
+}
+
+/// Runs a command line with the shell in the selected folder. Its output
+/// goes to the result log, stderr as errors, and what looks like progress
+/// moves the bar.
+pub struct RunCommand {
+    line: Option<String>,
+}
+
+impl RunCommand {
+    pub const NAME: &'static str = "command";
+
+    /// Without a line, it fails when run.
+    pub fn new() -> Self {
+        Self { line: None }
+    }
+
+    pub fn with_line(line: &str) -> Self {
+        Self { line: Some(line.to_string()) }
+    }
+
+    fn shell(line: &str) -> Command {
+        #[cfg(windows)]
+        {
+            use std::os::windows::process::CommandExt;
+            let mut command = Command::new("cmd");
+            // cmd has quoting rules of its own, the line goes as it is
+            command.arg("/C").raw_arg(line).creation_flags(CREATE_NO_WINDOW);
+            command
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+            let mut command = Command::new("sh");
+            // a group of its own, so cancel reaches what it started
+            command.arg("-c").arg(line).process_group(0);
+            command
+        }
+    }
+
+    /// Stops the command and everything it started.
+    fn kill_tree(child: &mut Child) {
+        #[cfg(windows)]
+        let killed = {
+            use std::os::windows::process::CommandExt;
+            Command::new("taskkill")
+                .args(["/T", "/F", "/PID", &child.id().to_string()])
+                .creation_flags(CREATE_NO_WINDOW)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+        };
+        #[cfg(unix)]
+        let killed = Command::new("kill")
+            .args(["-KILL", "--", &format!("-{}", child.id())])
+            .stderr(Stdio::null())
+            .status();
+        if !killed.is_ok_and(|status| status.success()) {
+            let _ = child.kill();
+        }
+    }
+
+    // sends the lines on until the command is done, None when cancelled
+    fn pump(
+        child: &mut Child, output: Receiver<Output>, sink: &dyn TaskSink
+    ) -> Result<Option<ExitStatus>, AppError> {
+        let mut progress = ProgressUpdate::new(0, 0);
+        sink.progress(&progress);
+        let mut exited: Option<(ExitStatus, Instant)> = None;
+        loop {
+            if !sink.keep_going(&progress) {
+                Self::kill_tree(child);
+                child.wait()?;
+                return Ok(None);
+            }
+            match output.recv_timeout(POLL) {
+                Ok(Output::Out(line)) => {
+                    if let Some(update) = parse_progress(&line) {
+                        progress = update;
+                        sink.progress(&progress);
+                    }
+                    sink.line(&line);
+                },
+                Ok(Output::Err(line)) => sink.error_line(&line),
+                Err(RecvTimeoutError::Disconnected) => break,
+                // nothing queued, what's still to come is from the background
+                Err(RecvTimeoutError::Timeout) => {
+                    if exited.is_some_and(|(_, at)| at.elapsed() > DRAIN) {
+                        break;
+                    }
+                },
+            }
+            if exited.is_none() {
+                exited = child.try_wait()?.map(|status| (status, Instant::now()));
+            }
+        }
+        match exited {
+            Some((status, _)) => Ok(Some(status)),
+            None => Ok(Some(child.wait()?)),
+        }
+    }
+}
+
+impl Default for RunCommand {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Task for RunCommand {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn run(&self, path: &Path, sink: &dyn TaskSink) -> Result<(), AppError> {
+        let Some(line) = self.line.clone() else {
+            return Err(AppError::config("沒有設定命令，請用 --command"));
+        };
+        sink.line(&format!("> {}", line));
+        let mut command = Self::shell(&line);
+        // an empty path runs where the app was started
+        if !path.as_os_str().is_empty() {
+            command.current_dir(path);
+        }
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("執行 {}", line))?;
+        let (sender, output) = mpsc::channel();
+        if let Some(stdout) = child.stdout.take() {
+            forward(stdout, sender.clone(), Output::Out);
+        }
+        if let Some(stderr) = child.stderr.take() {
+            forward(stderr, sender, Output::Err);
+        }
+        let Some(status) = Self::pump(&mut child, output, sink)? else {
+            sink.line("命令已取消");
+            return Ok(());
+        };
+        match status.code() {
+            Some(0) => {
+                sink.line("結束代碼 0");
+                Ok(())
+            },
+            Some(code) => {
+                sink.line(&format!("結束代碼 {}", code));
+                Err(AppError::task(format!("命令結束代碼 {}", code)))
+            },
+            None => Err(AppError::task(format!("命令被終止 ({})", status))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(unix)]
+    use crate::tasks::testing::Recorder;
+
+    fn parsed(line: &str) -> Option<(usize, usize, Option<f64>)> {
+        parse_progress(line).map(|p| (p.done, p.total, p.fraction))
+    }
+
+    #[test]
+    fn progress_patterns() {
+        assert_eq!(parsed("downloading 45%"), Some((45, 100, Some(0.45))));
+        assert_eq!(parsed("[3/10] compiling, 12.5% done"), Some((12, 100, Some(0.125))));
+        assert_eq!(parsed("file 3/10"), Some((3, 10, None)));
+        assert_eq!(parsed("step 2/3 then 3/3"), Some((3, 3, None)));
+        assert_eq!(parsed("no numbers here"), None);
+        assert_eq!(parsed("2024/10/18 started"), None);
+        assert_eq!(parsed("11/10 is too many, 0/0 too"), None);
+        assert_eq!(parsed("150% over"), None);
+        assert_eq!(parsed("v1.2/3"), None);
+    }
+
+    #[test]
+    fn splits_on_either_line_end() {
+        let mut lines = Vec::new();
+        split_lines(&b"a\r\nb\r 10%\r 20%\n\nlast"[..], |l| lines.push(l)).unwrap();
+        assert_eq!(lines, ["a", "b", " 10%", " 20%", "last"]);
+        let mut lines = Vec::new();
+        split_lines(&b"\xff\xfeok\n"[..], |l| lines.push(l)).unwrap();
+        assert_eq!(lines, ["\u{fffd}\u{fffd}ok"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn streams_output_and_progress() {
+        let sink = Recorder::default();
+        let task = RunCommand::with_line("pwd; echo 1/4; echo oops >&2; printf '50%%\\r100%%\\n'");
+        task.run(Path::new("/"), &sink).unwrap();
+        assert_eq!(*sink.lines.borrow(), ["> pwd; echo 1/4; echo oops >&2; printf '50%%\\r100%%\\n'", "/", "1/4", "50%", "100%", "結束代碼 0"]);
+        assert_eq!(*sink.errors.borrow(), ["oops"]);
+        let progress = sink.progress.borrow();
+        assert_eq!(progress.len(), 4);
+        assert_eq!(progress.last().unwrap().fraction, Some(1.0));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn exit_code_and_missing_command() {
+        let sink = Recorder::default();
+        let error = RunCommand::with_line("exit 3").run(Path::new(""), &sink).unwrap_err();
+        assert_eq!(error.to_string(), "task failed: 命令結束代碼 3");
+        assert_eq!(sink.lines.borrow().last().unwrap(), "結束代碼 3");
+        let error = RunCommand::with_line("true").run(Path::new("/no/such/dir"), &sink).unwrap_err();
+        assert!(error.to_string().starts_with("執行 true: "));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn slow_sink_gets_every_line() {
+        // the command is done long before the lines are
+        let sink = Recorder { delay: Duration::from_millis(10), ..Default::default() };
+        RunCommand::with_line("seq 1 200").run(Path::new(""), &sink).unwrap();
+        let lines = sink.lines.borrow();
+        assert_eq!(lines.len(), 202);
+        assert_eq!(lines[200], "200");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn cancel_kills_the_tree() {
+        let sink = Recorder { stop_after: Some(2), ..Default::default() };
+        let started = Instant::now();
+        // the sleep is a child of the shell, both have to go
+        let task = RunCommand::with_line("echo started; sleep 30 & wait");
+        task.run(Path::new(""), &sink).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert_eq!(sink.lines.borrow().last().unwrap(), "命令已取消");
+    }
+}
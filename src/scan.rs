@@ -0,0 +1,429 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::{AppError, Context};
+use crate::progress::ProgressUpdate;
+use crate::status::format_size;
+use crate::tasks::{Task, TaskSink};
+
+// how many of the largest files and extensions the summary lists
+const LARGEST: usize = 10;
+const EXTENSIONS: usize = 10;
+
+/// What the scan looks at, from the command line.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ScanOptions {
+    /// Files matching none of these are left out, when there are any.
+    pub include: Vec<String>,
+    /// Files and folders matching any of these are left out.
+    pub exclude: Vec<String>,
+    /// Levels of folders below the selected one, None for all.
+    pub max_depth: Option<usize>,
+    pub follow_symlinks: bool,
+}
+
+enum Token {
+    Char(char),
+    // ?
+    Any,
+    // *, not across a /
+    Star,
+    // **, across folders
+    DoubleStar,
+    // **/, none or more whole folders
+    Folders,
+}
+
+/// Matches `text` against a glob with `?`, `*` and `**`, ignoring case. A
+/// pattern without a `/` is matched against the file name only, others
+/// against the whole path below the selected folder, with `/` between
+/// the names on every platform.
+pub fn glob_match(pattern: &str, path: &str) -> bool {
+    let text = match pattern.contains('/') {
+        true => path,
+        false => path.rsplit('/').next().unwrap_or(path),
+    };
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < pattern.len() {
+        let token = match (pattern[i], pattern.get(i + 1), pattern.get(i + 2)) {
+            ('*', Some('*'), Some('/')) => {
+                i += 2;
+                Token::Folders
+            },
+            ('*', Some('*'), _) => {
+                i += 1;
+                Token::DoubleStar
+            },
+            ('*', _, _) => Token::Star,
+            ('?', _, _) => Token::Any,
+            (c, _, _) => Token::Char(c),
+        };
+        tokens.push(token);
+        i += 1;
+    }
+    // matched[j]: the tokens so far match the first j chars
+    let mut matched = vec![false; text.len() + 1];
+    matched[0] = true;
+    for token in &tokens {
+        let mut next = vec![false; text.len() + 1];
+        for j in 0..=text.len() {
+            match token {
+                Token::Char(c) => next[j] = j > 0 && matched[j - 1] && text[j - 1] == *c,
+                Token::Any => next[j] = j > 0 && matched[j - 1] && text[j - 1] != '/',
+                Token::Star => next[j] = matched[j] || (j > 0 && next[j - 1] && text[j - 1] != '/'),
+                Token::DoubleStar => next[j] = matched[j] || (j > 0 && next[j - 1]),
+                Token::Folders => {
+                    next[j] = matched[j] || (j > 0 && text[j - 1] == '/' && (0..j).any(|k| matched[k]));
+                },
+            }
+        }
+        matched = next;
+    }
+    matched[text.len()]
+}
+
+/// What a scan found.
+#[derive(Clone, Debug, Default)]
+pub struct Summary {
+    pub files: usize,
+    pub folders: usize,
+    pub bytes: u64,
+    /// Symbolic links not followed.
+    pub links: usize,
+    /// Folders that couldn't be read.
+    pub errors: usize,
+    largest: BinaryHeap<Reverse<(u64, String)>>,
+    // extension, (files, bytes)
+    extensions: HashMap<String, (usize, u64)>,
+}
+
+impl Summary {
+    pub fn add_file(&mut self, path: &str, size: u64) {
+        self.files += 1;
+        self.bytes += size;
+        self.largest.push(Reverse((size, path.to_string())));
+        if self.largest.len() > LARGEST {
+            self.largest.pop();
+        }
+        let name = path.rsplit('/').next().unwrap_or(path);
+        let extension = match name.rsplit_once('.') {
+            Some((stem, ext)) if !stem.is_empty() => format!(".{}", ext.to_lowercase()),
+            _ => "(無)".to_string(),
+        };
+        let entry = self.extensions.entry(extension).or_default();
+        entry.0 += 1;
+        entry.1 += size;
+    }
+
+    /// Largest first, ties by path.
+    pub fn largest(&self) -> Vec<(u64, String)> {
+        let mut largest: Vec<_> = self.largest.iter().map(|Reverse(f)| f.clone()).collect();
+        largest.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        largest
+    }
+
+    /// The lines for the result log.
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "檔案 {}，資料夾 {}，共 {}",
+            self.files, self.folders, format_size(self.bytes)
+        )];
+        if self.links > 0 {
+            lines.push(format!("略過連結 {}", self.links));
+        }
+        if self.errors > 0 {
+            lines.push(format!("無法讀取 {} 個資料夾", self.errors));
+        }
+        let mut extensions: Vec<_> = self.extensions.iter().collect();
+        extensions.sort_by(|a, b| b.1 .1.cmp(&a.1 .1).then_with(|| a.0.cmp(b.0)));
+        if !extensions.is_empty() {
+            lines.push("副檔名:".to_string());
+        }
+        for (extension, (files, bytes)) in extensions.into_iter().take(EXTENSIONS) {
+            lines.push(format!("  {:<8} {:>6} 個  {}", extension, files, format_size(*bytes)));
+        }
+        let largest = self.largest();
+        if !largest.is_empty() {
+            lines.push("最大的檔案:".to_string());
+        }
+        for (size, path) in largest {
+            lines.push(format!("  {:>10}  {}", format_size(size), path));
+        }
+        lines
+    }
+}
+
+/// Walks the selected folder and sums up what's in it. Progress counts
+/// the folders visited out of those found so far.
+pub struct Scan {
+    options: ScanOptions,
+}
+
+impl Scan {
+    pub const NAME: &'static str = "scan";
+
+    /// Scans everything below the folder.
+    pub fn new() -> Self {
+        Self::with_options(ScanOptions::default())
+    }
+
+    pub fn with_options(options: ScanOptions) -> Self {
+        Self { options }
+    }
+
+    /// Walks `root`, None when cancelled. `file` gets every file counted,
+    /// with its path below `root` and its size.
+    pub fn walk(
+        root: &Path, options: &ScanOptions, sink: &dyn TaskSink, file: &mut dyn FnMut(&str, &Path, u64)
+    ) -> Result<Option<Summary>, AppError> {
+        let metadata = fs::metadata(root).with_context(|| format!("讀取 {}", root.display()))?;
+        if !metadata.is_dir() {
+            return Err(AppError::config(format!("{} 不是資料夾", root.display())));
+        }
+        let mut summary = Summary::default();
+        // folders as (path, path below the root, depth)
+        let mut pending = vec![(root.to_path_buf(), String::new(), 0)];
+        let mut found = 1;
+        // where followed links lead, a link back up would never end
+        let mut seen = HashSet::new();
+        if options.follow_symlinks {
+            seen.insert(fs::canonicalize(root)?);
+        }
+        let mut progress = ProgressUpdate::new(0, found);
+        sink.progress(&progress);
+        while let Some((dir, relative, depth)) = pending.pop() {
+            if !sink.keep_going(&progress) {
+                return Ok(None);
+            }
+            summary.folders += 1;
+            let entries = match fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    summary.errors += 1;
+                    sink.error_line(&format!("{}: {}", dir.display(), e));
+                    continue;
+                },
+            };
+            let mut folders = Vec::new();
+            for entry in entries {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) => {
+                        sink.error_line(&format!("{}: {}", dir.display(), e));
+                        continue;
+                    },
+                };
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let path = match relative.is_empty() {
+                    true => name,
+                    false => format!("{}/{}", relative, name),
+                };
+                if options.exclude.iter().any(|pattern| glob_match(pattern, &path)) {
+                    continue;
+                }
+                let Ok(mut file_type) = entry.file_type() else {
+                    continue;
+                };
+                let mut metadata = entry.metadata();
+                if file_type.is_symlink() {
+                    if !options.follow_symlinks {
+                        summary.links += 1;
+                        continue;
+                    }
+                    // a dangling link has nothing to count
+                    metadata = fs::metadata(entry.path());
+                    match &metadata {
+                        Ok(target) => file_type = target.file_type(),
+                        Err(_) => continue,
+                    }
+                }
+                if file_type.is_dir() {
+                    let within = options.max_depth.is_none_or(|max| depth < max);
+                    let new = !options.follow_symlinks
+                        || fs::canonicalize(entry.path()).is_ok_and(|target| seen.insert(target));
+                    if within && new {
+                        folders.push((entry.path(), path, depth + 1));
+                    }
+                    continue;
+                }
+                let included = options.include.is_empty()
+                    || options.include.iter().any(|pattern| glob_match(pattern, &path));
+                if included {
+                    let size = metadata.map(|m| m.len()).unwrap_or(0);
+                    file(&path, &entry.path(), size);
+                    summary.add_file(&path, size);
+                }
+            }
+            found += folders.len();
+            // sorted and reversed, so the stack visits them by name
+            folders.sort_by(|a, b| b.1.cmp(&a.1));
+            pending.extend(folders);
+            progress = ProgressUpdate::new(summary.folders, found);
+            sink.progress(&progress);
+        }
+        Ok(Some(summary))
+    }
+}
+
+impl Default for Scan {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Task for Scan {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn run(&self, path: &Path, sink: &dyn TaskSink) -> Result<(), AppError> {
+        let options = &self.options;
+        let root = match path.as_os_str().is_empty() {
+            true => PathBuf::from("."),
+            false => path.to_path_buf(),
+        };
+        sink.line(&format!("掃描 {}", root.display()));
+        match Self::walk(&root, options, sink, &mut |_, _, _| {})? {
+            Some(summary) => {
+                for line in summary.lines() {
+                    sink.line(&line);
+                }
+            },
+            None => sink.line("掃描已取消"),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tasks::testing::{temp_folder, Recorder};
+
+    #[test]
+    fn globs() {
+        assert!(glob_match("*.rs", "src/main.rs"));
+        assert!(glob_match("*.RS", "Main.rs"));
+        assert!(!glob_match("*.rs", "main.rsx"));
+        assert!(glob_match("ma?n.*", "a/main.c"));
+        assert!(glob_match("src/*.rs", "src/main.rs"));
+        assert!(!glob_match("src/*.rs", "src/a/main.rs"));
+        assert!(glob_match("src/**.rs", "src/a/b/main.rs"));
+        assert!(glob_match("**/target", "target"));
+        assert!(glob_match("**/target", "a/b/target"));
+        assert!(!glob_match("**/target", "a/btarget"));
+        assert!(glob_match("a/**/b.txt", "a/b.txt"));
+        assert!(glob_match("a/**/b.txt", "a/x/y/b.txt"));
+        assert!(!glob_match("?", ""));
+        assert!(glob_match("*", ""));
+    }
+
+    #[test]
+    fn summary_lines() {
+        let mut summary = Summary { folders: 2, ..Default::default() };
+        summary.add_file("a.txt", 100);
+        summary.add_file("b/c.TXT", 2048);
+        summary.add_file("b/.hidden", 1);
+        summary.add_file("b/Makefile", 3);
+        assert_eq!(summary.largest()[0], (2048, "b/c.TXT".to_string()));
+        assert_eq!(
+            summary.lines(),
+            [
+                "檔案 4，資料夾 2，共 2.1 KB",
+                "副檔名:",
+                "  .txt          2 個  2.1 KB",
+                "  (無)           2 個  4 B",
+                "最大的檔案:",
+                "      2.0 KB  b/c.TXT",
+                "       100 B  a.txt",
+                "         3 B  b/Makefile",
+                "         1 B  b/.hidden",
+            ]
+        );
+        for i in 0..20 {
+            summary.add_file(&format!("f{}", i), i);
+        }
+        assert_eq!(summary.largest().len(), LARGEST);
+        assert_eq!(summary.largest()[LARGEST - 1].0, 12);
+    }
+
+    // a/1.txt (10), a/b/2.rs (20), a/b/c/3.txt (30), top.txt (5), skip/4.txt (40)
+    fn tree(name: &str) -> PathBuf {
+        let root = temp_folder(&format!("scan-{}", name));
+        fs::create_dir_all(root.join("a/b/c")).unwrap();
+        fs::create_dir_all(root.join("skip")).unwrap();
+        for (path, size) in [("a/1.txt", 10), ("a/b/2.rs", 20), ("a/b/c/3.txt", 30), ("top.txt", 5), ("skip/4.txt", 40)] {
+            fs::write(root.join(path), vec![b'x'; size]).unwrap();
+        }
+        root
+    }
+
+    fn walked(root: &Path, options: ScanOptions) -> Summary {
+        Scan::walk(root, &options, &Recorder::default(), &mut |_, _, _| {}).unwrap().unwrap()
+    }
+
+    #[test]
+    fn walks_a_tree() {
+        let root = tree("walk");
+        let sink = Recorder::default();
+        let mut files = Vec::new();
+        let summary = Scan::walk(&root, &ScanOptions::default(), &sink, &mut |path, full, size| {
+            files.push((path.to_string(), size));
+            assert_eq!(full, root.join(path));
+        });
+        let summary = summary.unwrap().unwrap();
+        assert_eq!((summary.files, summary.folders, summary.bytes), (5, 5, 105));
+        // the files of a folder come before those of its folders
+        assert_eq!(files[0], ("top.txt".to_string(), 5));
+        assert_eq!(files.len(), 5);
+        let progress = sink.progress.borrow();
+        assert_eq!(progress.last().map(|p| (p.done, p.total)), Some((5, 5)));
+        assert!(progress.windows(2).all(|w| w[0].done <= w[1].done));
+
+        let options = ScanOptions {
+            include: vec!["*.txt".into()],
+            exclude: vec!["skip".into()],
+            ..Default::default()
+        };
+        let summary = walked(&root, options);
+        assert_eq!((summary.files, summary.folders, summary.bytes), (3, 4, 45));
+
+        let summary = walked(&root, ScanOptions { max_depth: Some(1), ..Default::default() });
+        assert_eq!(summary.largest().iter().map(|f| f.1.as_str()).collect::<Vec<_>>(), ["skip/4.txt", "a/1.txt", "top.txt"]);
+        let summary = walked(&root, ScanOptions { max_depth: Some(0), ..Default::default() });
+        assert_eq!((summary.files, summary.folders), (1, 1));
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_are_followed_once() {
+        let root = tree("links");
+        std::os::unix::fs::symlink(root.join("a"), root.join("a/b/up")).unwrap();
+        let summary = walked(&root, ScanOptions::default());
+        assert_eq!((summary.files, summary.links), (5, 1));
+        // the link leads back to a, which was seen already
+        let summary = walked(&root, ScanOptions { follow_symlinks: true, ..Default::default() });
+        assert_eq!((summary.files, summary.links), (5, 0));
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn run_reports_and_cancels() {
+        let root = tree("run");
+        let sink = Recorder::default();
+        Scan::with_options(ScanOptions::default()).run(&root, &sink).unwrap();
+        assert_eq!(sink.lines.borrow()[1], "檔案 5，資料夾 5，共 105 B");
+        let sink = Recorder { stop_after: Some(0), ..Default::default() };
+        Scan::with_options(ScanOptions::default()).run(&root, &sink).unwrap();
+        assert_eq!(sink.lines.borrow().last().unwrap(), "掃描已取消");
+        let error = Scan::with_options(ScanOptions::default()).run(&root.join("top.txt"), &sink).unwrap_err();
+        assert!(error.to_string().ends_with("不是資料夾"));
+        let _ = fs::remove_dir_all(root);
+    }
+}
@@ -0,0 +1,100 @@
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use windows::core::*;
+use windows::Win32::{
+    Foundation::*,
+    System::DataExchange::COPYDATASTRUCT,
+    System::Threading::CreateMutexW,
+    UI::WindowsAndMessaging::*,
+};
+
+use crate::copydata::{self, Forwarded};
+use crate::error::AppError;
+use crate::window::Window;
+
+// per session, another user's instance doesn't count
+const MUTEX_NAME: PCWSTR = w!("Local\\windows-app-single-instance");
+// the first instance may still be creating its window
+const FIND_ATTEMPTS: u32 = 20;
+const FIND_INTERVAL: Duration = Duration::from_millis(100);
+const SEND_TIMEOUT_MS: u32 = 5000;
+
+/// Held by the first instance for as long as it runs.
+pub struct InstanceGuard(HANDLE);
+
+impl Drop for InstanceGuard {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = CloseHandle(self.0);
+        }
+    }
+}
+
+pub enum Instance {
+    First(InstanceGuard),
+    /// Another instance runs, the command line goes to it.
+    Second,
+}
+
+pub fn acquire() -> std::result::Result<Instance, AppError> {
+    unsafe {
+        let mutex = CreateMutexW(None, false, MUTEX_NAME)?;
+        match GetLastError() == ERROR_ALREADY_EXISTS {
+            true => {
+                let _ = CloseHandle(mutex);
+                Ok(Instance::Second)
+            },
+            false => Ok(Instance::First(InstanceGuard(mutex))),
+        }
+    }
+}
+
+/// Hands `args` to the window of the first instance with WM_COPYDATA,
+/// Ok once it took them.
+pub fn forward(args: &[String], cwd: &Path) -> std::result::Result<(), AppError> {
+    let forwarded = Forwarded { cwd: cwd.display().to_string(), args: args.to_vec() };
+    let payload = copydata::encode(&forwarded).map_err(|e| AppError::config(e.to_string()))?;
+    let window = find_window()?;
+    unsafe {
+        // the first instance may bring its window to the front
+        let mut pid = 0;
+        GetWindowThreadProcessId(window, Some(&mut pid));
+        let _ = AllowSetForegroundWindow(pid);
+
+        let cds = COPYDATASTRUCT {
+            dwData: copydata::FORWARD_ARGS,
+            cbData: payload.len() as u32,
+            lpData: payload.as_ptr() as *mut core::ffi::c_void,
+        };
+        let mut accepted = 0;
+        let sent = SendMessageTimeoutW(
+            window,
+            WM_COPYDATA,
+            WPARAM(0),
+            LPARAM(&cds as *const _ as isize),
+            SMTO_ABORTIFHUNG,
+            SEND_TIMEOUT_MS,
+            Some(&mut accepted),
+        );
+        if sent.0 == 0 {
+            return Err(Error::from_win32().into());
+        }
+        match accepted {
+            0 => Err(AppError::config("第一個執行個體不接受這個命令列")),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn find_window() -> std::result::Result<HWND, AppError> {
+    let class = HSTRING::from(Window::CLASS);
+    for _ in 1..FIND_ATTEMPTS {
+        if let Ok(window) = unsafe { FindWindowW(&class, None) } {
+            return Ok(window);
+        }
+        thread::sleep(FIND_INTERVAL);
+    }
+    Ok(unsafe { FindWindowW(&class, None) }?)
+}
@@ -0,0 +1,119 @@
+use std::path::{Path, PathBuf};
+
+use crate::cli::Options;
+use crate::tasks::{self, TaskOptions};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ShowMode {
+    #[default]
+    Normal,
+    Minimized,
+}
+
+/// What the window does once it's up, from the command line of a
+/// shortcut or of "Open with" in Explorer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StartupPlan {
+    /// Goes into the path textbox, absolute.
+    pub path: Option<PathBuf>,
+    pub task: String,
+    pub task_options: TaskOptions,
+    /// Click GO as soon as the window is shown.
+    pub autostart: bool,
+    pub show: ShowMode,
+    /// Serve the control api for scripts.
+    pub control: bool,
+    /// Why part of the command line was dropped, for the result log.
+    pub warnings: Vec<String>,
+}
+
+impl Default for StartupPlan {
+    fn default() -> Self {
+        Self {
+            path: None,
+            task: tasks::DEFAULT_TASK.to_string(),
+            task_options: TaskOptions::default(),
+            autostart: false,
+            show: ShowMode::Normal,
+            control: false,
+            warnings: Vec::new(),
+        }
+    }
+}
+
+impl StartupPlan {
+    /// Relative paths are taken from `cwd`, the working folder of the
+    /// shortcut. A run doesn't start on a path that isn't there.
+    pub fn new(options: &Options, cwd: &Path, exists: impl Fn(&Path) -> bool) -> Self {
+        let mut plan = Self {
+            task: options.task.clone().unwrap_or_else(|| tasks::DEFAULT_TASK.to_string()),
+            task_options: options.task_options.clone(),
+            autostart: options.autostart,
+            show: match options.minimized {
+                true => ShowMode::Minimized,
+                false => ShowMode::Normal,
+            },
+            control: options.ipc,
+            ..Default::default()
+        };
+        if let Some(path) = &options.path {
+            let path = match path.is_absolute() {
+                true => path.clone(),
+                false => cwd.join(path),
+            };
+            if !exists(&path) {
+                plan.warnings.push(format!("路徑不存在: {}", path.display()));
+                if plan.autostart {
+                    plan.autostart = false;
+                    plan.warnings.push("沒有自動開始執行".to_string());
+                }
+            }
+            plan.path = Some(path);
+        }
+        plan
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(path: Option<&str>, autostart: bool) -> Options {
+        Options { path: path.map(PathBuf::from), autostart, ..Default::default() }
+    }
+
+    #[test]
+    fn nothing_asked() {
+        let plan = StartupPlan::new(&Options::default(), Path::new("/"), |_| true);
+        assert_eq!(plan, StartupPlan::default());
+        assert_eq!(plan.task, "demo");
+    }
+
+    #[test]
+    fn relative_paths_are_resolved() {
+        let plan = StartupPlan::new(&options(Some("data"), true), Path::new("/home/a"), |_| true);
+        assert_eq!(plan.path, Some(PathBuf::from("/home/a/data")));
+        assert!(plan.autostart);
+        assert!(plan.warnings.is_empty());
+        let plan = StartupPlan::new(&options(Some("/srv"), false), Path::new("/home/a"), |_| true);
+        assert_eq!(plan.path, Some(PathBuf::from("/srv")));
+    }
+
+    #[test]
+    fn missing_path_does_not_autostart() {
+        let plan = StartupPlan::new(&options(Some("/gone"), true), Path::new("/"), |_| false);
+        // still shown so it can be fixed
+        assert_eq!(plan.path, Some(PathBuf::from("/gone")));
+        assert!(!plan.autostart);
+        assert_eq!(plan.warnings, ["路徑不存在: /gone", "沒有自動開始執行"]);
+    }
+
+    #[test]
+    fn task_and_show_mode() {
+        let options = Options { task: Some("demo".into()), minimized: true, ipc: true, ..Default::default() };
+        let plan = StartupPlan::new(&options, Path::new("/"), |_| true);
+        assert_eq!((plan.task.as_str(), plan.show), ("demo", ShowMode::Minimized));
+        assert!(!plan.autostart);
+        assert!(plan.control);
+    }
+}
@@ -0,0 +1,265 @@
+use std::time::{Duration, Instant};
+
+// fixed widths (in 96 dpi pixels) of the right-hand status bar parts,
+// the state part takes whatever is left
+const ELAPSED_PART_WIDTH: f32 = 90.0;
+const COUNT_PART_WIDTH: f32 = 120.0;
+const ERROR_PART_WIDTH: f32 = 90.0;
+const MIN_STATE_PART_WIDTH: f32 = 60.0;
+
+pub const PART_STATE: usize = 0;
+pub const PART_ELAPSED: usize = 1;
+pub const PART_COUNT: usize = 2;
+pub const PART_ERRORS: usize = 3;
+pub const PART_COUNT_TOTAL: usize = 4;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RunState {
+    #[default]
+    Idle,
+    Running,
+    Paused,
+    Done,
+    /// Stopped by the user, which is no failure.
+    Cancelled,
+    Failed,
+}
+
+impl RunState {
+    pub fn label(&self) -> &'static str {
+        match self {
+            RunState::Idle => "待命",
+            RunState::Running => "執行中",
+            RunState::Paused => "暫停",
+            RunState::Done => "完成",
+            RunState::Cancelled => "已取消",
+            RunState::Failed => "失敗",
+        }
+    }
+}
+
+/// How a run ended, from the result of its task.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Done,
+    Cancelled,
+    Failed,
+}
+
+/// What the status bar shows, kept apart from the control so it can be
+/// driven by worker events and checked without a window.
+#[derive(Clone, Debug, Default)]
+pub struct StatusModel {
+    state: RunState,
+    started: Option<Instant>,
+    finished: Option<Duration>,
+    processed: usize,
+    total: usize,
+    errors: usize,
+}
+
+impl StatusModel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn state(&self) -> RunState {
+        self.state
+    }
+
+    pub fn processed(&self) -> usize {
+        self.processed
+    }
+
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    pub fn start(&mut self, now: Instant) {
+        self.state = RunState::Running;
+        self.started = Some(now);
+        self.finished = None;
+        self.processed = 0;
+        self.total = 0;
+        self.errors = 0;
+    }
+
+    pub fn update(&mut self, processed: usize, total: usize) {
+        self.processed = processed;
+        self.total = total;
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.state = match (self.state, paused) {
+            (RunState::Running, true) => RunState::Paused,
+            (RunState::Paused, false) => RunState::Running,
+            (state, _) => state,
+        };
+    }
+
+    /// An error line of the task, like stderr of a command. Only counted,
+    /// the task's result decides how the run ends.
+    pub fn add_error(&mut self) {
+        self.errors += 1;
+    }
+
+    pub fn finish(&mut self, now: Instant, outcome: Outcome) {
+        if !matches!(self.state, RunState::Running | RunState::Paused) {
+            return;
+        }
+        self.state = match outcome {
+            Outcome::Done => RunState::Done,
+            Outcome::Cancelled => RunState::Cancelled,
+            Outcome::Failed => RunState::Failed,
+        };
+        self.finished = Some(self.elapsed(now));
+    }
+
+    pub fn elapsed(&self, now: Instant) -> Duration {
+        if let Some(d) = self.finished {
+            return d;
+        }
+        match self.started {
+            Some(start) => now.saturating_duration_since(start),
+            None => Duration::ZERO,
+        }
+    }
+
+    /// Text of every part, indexed by the `PART_*` constants.
+    pub fn texts(&self, now: Instant) -> [String; PART_COUNT_TOTAL] {
+        [
+            self.state.label().to_string(),
+            format_elapsed(self.elapsed(now)),
+            format_count(self.processed, self.total),
+            format!("錯誤 {}", self.errors),
+        ]
+    }
+}
+
+/// `mm:ss`, or `h:mm:ss` once a run takes an hour or more.
+pub fn format_elapsed(d: Duration) -> String {
+    let secs = d.as_secs();
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+    match h {
+        0 => format!("{:02}:{:02}", m, s),
+        _ => format!("{}:{:02}:{:02}", h, m, s),
+    }
+}
+
+/// Bytes in the largest unit that keeps the number at least 1, `1.5 MB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KB", "MB", "GB", "TB", "PB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+pub fn format_count(processed: usize, total: usize) -> String {
+    match total {
+        0 => format!("{}", processed),
+        _ => format!("{}/{}", processed, total),
+    }
+}
+
+/// Right edges of the parts for `SB_SETPARTS`; the last part runs to the
+/// edge of the bar (-1).
+pub fn part_edges(client_width: i32, scale_factor: f32) -> [i32; PART_COUNT_TOTAL] {
+    let elapsed = (ELAPSED_PART_WIDTH * scale_factor) as i32;
+    let count = (COUNT_PART_WIDTH * scale_factor) as i32;
+    let errors = (ERROR_PART_WIDTH * scale_factor) as i32;
+    let min_state = (MIN_STATE_PART_WIDTH * scale_factor) as i32;
+    let state = (client_width - elapsed - count - errors).max(min_state);
+    [state, state + elapsed, state + elapsed + count, -1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn part_edges_fill_width() {
+        assert_eq!(part_edges(800, 1.0), [500, 590, 710, -1]);
+        assert_eq!(part_edges(800, 1.5), [350, 485, 665, -1]);
+    }
+
+    #[test]
+    fn part_edges_keep_state_visible_when_narrow() {
+        assert_eq!(part_edges(100, 1.0), [60, 150, 270, -1]);
+    }
+
+    #[test]
+    fn format_elapsed_switches_to_hours() {
+        assert_eq!(format_elapsed(Duration::from_secs(5)), "00:05");
+        assert_eq!(format_elapsed(Duration::from_secs(61 * 60 + 1)), "1:01:01");
+    }
+
+    #[test]
+    fn format_size_picks_a_unit() {
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1536), "1.5 KB");
+        assert_eq!(format_size(5 * 1024 * 1024 * 1024), "5.0 GB");
+    }
+
+    #[test]
+    fn model_tracks_a_run() {
+        let t0 = Instant::now();
+        let mut model = StatusModel::new();
+        assert_eq!(model.texts(t0)[PART_STATE], "待命");
+        model.start(t0);
+        model.update(12, 30);
+        let texts = model.texts(t0 + Duration::from_secs(7));
+        assert_eq!(texts[PART_STATE], "執行中");
+        assert_eq!(texts[PART_ELAPSED], "00:07");
+        assert_eq!(texts[PART_COUNT], "12/30");
+        model.finish(t0 + Duration::from_secs(9), Outcome::Done);
+        // elapsed time stops with the run
+        let texts = model.texts(t0 + Duration::from_secs(60));
+        assert_eq!(texts[PART_STATE], "完成");
+        assert_eq!(texts[PART_ELAPSED], "00:09");
+    }
+
+    #[test]
+    fn pausing_only_applies_to_a_run() {
+        let t0 = Instant::now();
+        let mut model = StatusModel::new();
+        model.set_paused(true);
+        assert_eq!(model.state(), RunState::Idle);
+        model.start(t0);
+        model.set_paused(true);
+        assert_eq!(model.state(), RunState::Paused);
+        model.set_paused(false);
+        assert_eq!(model.state(), RunState::Running);
+        model.set_paused(true);
+        model.finish(t0, Outcome::Failed);
+        assert_eq!(model.state(), RunState::Failed);
+    }
+
+    #[test]
+    fn cancel_is_not_a_failure() {
+        let t0 = Instant::now();
+        let mut model = StatusModel::new();
+        model.start(t0);
+        model.finish(t0, Outcome::Cancelled);
+        assert_eq!(model.state(), RunState::Cancelled);
+        assert_eq!(model.texts(t0)[PART_STATE], "已取消");
+    }
+
+    #[test]
+    fn error_lines_are_only_counted() {
+        // build tools write their progress to stderr too
+        let t0 = Instant::now();
+        let mut model = StatusModel::new();
+        model.start(t0);
+        model.add_error();
+        model.finish(t0, Outcome::Done);
+        assert_eq!(model.state(), RunState::Done);
+        assert_eq!(model.texts(t0)[PART_ERRORS], "錯誤 1");
+    }
+}
@@ -0,0 +1,112 @@
+use crate::status::RunState;
+
+/// Mirrors the TBPF_* flags of `ITaskbarList3::SetProgressState`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TaskbarState {
+    NoProgress,
+    Indeterminate,
+    Normal,
+    Paused,
+    Error,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TaskbarProgress {
+    pub state: TaskbarState,
+    pub completed: u64,
+    pub total: u64,
+}
+
+/// Maps the task state shown in the status bar to the taskbar button.
+/// A run without a known total shows the marquee, a paused run keeps its
+/// value in yellow, a failed run keeps its last value (or a full bar when
+/// there was none) in red and a finished or cancelled run clears the
+/// button.
+pub fn taskbar_progress(state: RunState, done: usize, total: usize) -> TaskbarProgress {
+    let (completed, total) = (done.min(total) as u64, total as u64);
+    let state = match state {
+        RunState::Idle | RunState::Done | RunState::Cancelled => TaskbarState::NoProgress,
+        RunState::Running if total == 0 => TaskbarState::Indeterminate,
+        RunState::Running => TaskbarState::Normal,
+        RunState::Paused => TaskbarState::Paused,
+        RunState::Failed => TaskbarState::Error,
+    };
+    match state {
+        TaskbarState::Error if total == 0 => TaskbarProgress { state, completed: 1, total: 1 },
+        TaskbarState::Normal | TaskbarState::Paused | TaskbarState::Error => {
+            TaskbarProgress { state, completed, total }
+        },
+        _ => TaskbarProgress { state, completed: 0, total: 0 },
+    }
+}
+
+/// Flash the taskbar button when a run ends while the user is elsewhere,
+/// not when they cancelled it.
+pub fn should_flash(before: RunState, after: RunState, focused: bool) -> bool {
+    !focused &&
+        before == RunState::Running &&
+        matches!(after, RunState::Done | RunState::Failed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn running_maps_to_normal_or_marquee() {
+        assert_eq!(
+            taskbar_progress(RunState::Running, 12, 30),
+            TaskbarProgress { state: TaskbarState::Normal, completed: 12, total: 30 }
+        );
+        assert_eq!(
+            taskbar_progress(RunState::Running, 3, 0).state,
+            TaskbarState::Indeterminate
+        );
+    }
+
+    #[test]
+    fn paused_keeps_value() {
+        assert_eq!(
+            taskbar_progress(RunState::Paused, 12, 30),
+            TaskbarProgress { state: TaskbarState::Paused, completed: 12, total: 30 }
+        );
+    }
+
+    #[test]
+    fn overshoot_is_clamped() {
+        assert_eq!(taskbar_progress(RunState::Running, 40, 30).completed, 30);
+    }
+
+    #[test]
+    fn failure_keeps_value_and_done_clears() {
+        assert_eq!(
+            taskbar_progress(RunState::Failed, 7, 30),
+            TaskbarProgress { state: TaskbarState::Error, completed: 7, total: 30 }
+        );
+        assert_eq!(
+            taskbar_progress(RunState::Failed, 0, 0),
+            TaskbarProgress { state: TaskbarState::Error, completed: 1, total: 1 }
+        );
+        assert_eq!(
+            taskbar_progress(RunState::Done, 30, 30).state,
+            TaskbarState::NoProgress
+        );
+        assert_eq!(
+            taskbar_progress(RunState::Idle, 0, 0).state,
+            TaskbarState::NoProgress
+        );
+        assert_eq!(
+            taskbar_progress(RunState::Cancelled, 7, 30).state,
+            TaskbarState::NoProgress
+        );
+    }
+
+    #[test]
+    fn flash_only_on_unfocused_completion() {
+        assert!(should_flash(RunState::Running, RunState::Done, false));
+        assert!(should_flash(RunState::Running, RunState::Failed, false));
+        assert!(!should_flash(RunState::Running, RunState::Done, true));
+        assert!(!should_flash(RunState::Idle, RunState::Idle, false));
+        assert!(!should_flash(RunState::Running, RunState::Cancelled, false));
+    }
+}
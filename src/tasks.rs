@@ -0,0 +1,225 @@
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use crate::checksum::{Checksum, ChecksumOptions};
+use crate::error::AppError;
+use crate::progress::ProgressUpdate;
+use crate::progress_tree::ProgressTree;
+use crate::run_command::RunCommand;
+use crate::scan::{Scan, ScanOptions};
+
+/// Where a task reports to: the window through its messages, or the
+/// console when it runs headless.
+pub trait TaskSink {
+    fn progress(&self, update: &ProgressUpdate);
+    /// A line of output for the result log.
+    fn line(&self, line: &str);
+    /// A line that tells of an error, like stderr of a command.
+    fn error_line(&self, line: &str) {
+        self.line(&format!("錯誤: {}", line));
+    }
+    /// Blocks while the task is paused, false once it should stop.
+    fn keep_going(&self, progress: &ProgressUpdate) -> bool;
+}
+
+/// Work the app can run on a folder.
+pub trait Task: Send {
+    fn name(&self) -> &'static str;
+    fn run(&self, path: &Path, sink: &dyn TaskSink) -> Result<(), AppError>;
+}
+
+pub const DEFAULT_TASK: &str = "demo";
+
+/// The settings of the tasks that take any, from the command line. Each
+/// task only looks at its own.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TaskOptions {
+    /// For the command task.
+    pub command: Option<String>,
+    pub scan: ScanOptions,
+    pub checksum: ChecksumOptions,
+}
+
+/// The names `find` knows, for the usage text.
+pub fn names() -> Vec<&'static str> {
+    vec![Demo::NAME, RunCommand::NAME, Scan::NAME, Checksum::NAME]
+}
+
+pub fn is_known(name: &str) -> bool {
+    names().contains(&name)
+}
+
+/// Task `name`, set up with its part of `options`.
+pub fn find(name: &str, options: &TaskOptions) -> Option<Box<dyn Task>> {
+    match name {
+        Demo::NAME => Some(Box::new(Demo::new())),
+        RunCommand::NAME => Some(Box::new(match &options.command {
+            Some(line) => RunCommand::with_line(line),
+            None => RunCommand::new(),
+        })),
+        Scan::NAME => Some(Box::new(Scan::with_options(options.scan.clone()))),
+        Checksum::NAME => Some(Box::new(Checksum::with_options(options.checksum.clone()))),
+        _ => None,
+    }
+}
+
+/// Three weighted stages of made-up steps, to see the ui at work.
+pub struct Demo {
+    step: Duration,
+}
+
+impl Demo {
+    const NAME: &'static str = "demo";
+    // (stage, weight, steps)
+    const STAGES: [(&'static str, f64, u64); 3] = [("掃描", 1.0, 5), ("處理", 4.0, 20), ("報告", 1.0, 5)];
+
+    pub fn new() -> Self {
+        Self { step: Duration::from_millis(300) }
+    }
+
+    pub fn with_step(step: Duration) -> Self {
+        Self { step }
+    }
+}
+
+impl Default for Demo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Task for Demo {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn run(&self, _path: &Path, sink: &dyn TaskSink) -> Result<(), AppError> {
+        let mut tree = ProgressTree::new("windows-app");
+        let scopes: Vec<_> = Self::STAGES
+            .iter()
+            .map(|(name, weight, _)| tree.add_stage(tree.root(), name, *weight))
+            .collect();
+        let mut progress = ProgressUpdate::from_tree(&tree);
+        sink.progress(&progress);
+        let mut line = 0;
+        for (scope, (_, _, steps)) in scopes.into_iter().zip(Self::STAGES) {
+            tree.report(scope, 0, steps);
+            for i in 0..steps {
+                if !sink.keep_going(&progress) {
+                    return Ok(());
+                }
+                thread::sleep(self.step);
+                tree.report(scope, i+1, steps);
+                progress = ProgressUpdate::from_tree(&tree);
+                sink.progress(&progress);
+                line += 1;
+                sink.line(&format!("append line {} to results", line));
+            }
+            tree.finish(scope);
+        }
+        Ok(())
+    }
+}
+
+/// What the tests of the tasks share.
+#[cfg(test)]
+pub(crate) mod testing {
+    use std::cell::RefCell;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::TaskSink;
+    use crate::progress::ProgressUpdate;
+
+    /// A sink that keeps what the task reported.
+    #[derive(Default)]
+    pub(crate) struct Recorder {
+        pub progress: RefCell<Vec<ProgressUpdate>>,
+        pub lines: RefCell<Vec<String>>,
+        pub errors: RefCell<Vec<String>>,
+        /// The task is told to stop once it reported this many lines.
+        pub stop_after: Option<usize>,
+        /// How long each line takes, like a slow window.
+        pub delay: Duration,
+    }
+
+    impl TaskSink for Recorder {
+        fn progress(&self, update: &ProgressUpdate) {
+            self.progress.borrow_mut().push(update.clone());
+        }
+
+        fn line(&self, line: &str) {
+            thread::sleep(self.delay);
+            self.lines.borrow_mut().push(line.to_string());
+        }
+
+        fn error_line(&self, line: &str) {
+            self.errors.borrow_mut().push(line.to_string());
+        }
+
+        fn keep_going(&self, _progress: &ProgressUpdate) -> bool {
+            self.stop_after.is_none_or(|n| self.lines.borrow().len() < n)
+        }
+    }
+
+    /// An empty folder of its own for the test `name`.
+    pub(crate) fn temp_folder(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("windows-app-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::testing::Recorder;
+
+    #[test]
+    fn demo_runs_its_stages() {
+        let sink = Recorder::default();
+        Demo::with_step(Duration::ZERO).run(Path::new(""), &sink).unwrap();
+        assert_eq!(sink.lines.borrow().len(), 30);
+        let progress = sink.progress.borrow();
+        assert_eq!(progress.first().unwrap().fraction, Some(0.0));
+        assert_eq!(progress.last().unwrap().fraction, Some(1.0));
+        assert!(progress.iter().any(|p| p.stage == "處理"));
+    }
+
+    #[test]
+    fn demo_stops_when_told() {
+        let sink = Recorder { stop_after: Some(3), ..Default::default() };
+        Demo::with_step(Duration::ZERO).run(Path::new(""), &sink).unwrap();
+        assert_eq!(sink.lines.borrow().len(), 3);
+    }
+
+    #[test]
+    fn finds_tasks_by_name() {
+        let options = TaskOptions::default();
+        assert_eq!(find(DEFAULT_TASK, &options).unwrap().name(), "demo");
+        assert_eq!(find("command", &options).unwrap().name(), "command");
+        assert_eq!(find("scan", &options).unwrap().name(), "scan");
+        assert_eq!(find("checksum", &options).unwrap().name(), "checksum");
+        assert!(find("nope", &options).is_none());
+        assert!(is_known(DEFAULT_TASK));
+        assert!(!is_known("nope"));
+    }
+
+    #[test]
+    fn tasks_get_their_options() {
+        let sink = Recorder::default();
+        let error = find("command", &TaskOptions::default()).unwrap().run(Path::new(""), &sink).unwrap_err();
+        assert_eq!(error.to_string(), "bad configuration: 沒有設定命令，請用 --command");
+        #[cfg(unix)]
+        {
+            let options = TaskOptions { command: Some("echo hi".into()), ..Default::default() };
+            let sink = Recorder::default();
+            find("command", &options).unwrap().run(Path::new(""), &sink).unwrap();
+            assert_eq!(sink.lines.borrow()[1], "hi");
+        }
+    }
+}
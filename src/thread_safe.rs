@@ -3,4 +3,6 @@ use windows::Win32::Foundation::HWND;
 // Thread-safe window handle wrapper
 #[derive(Clone)]
 pub struct ThreadSafeHwnd(pub HWND);
-unsafe impl Send for ThreadSafeHwnd {}
\ No newline at end of file
+unsafe impl Send for ThreadSafeHwnd {}
+// a handle is only a number, any thread may send to it
+unsafe impl Sync for ThreadSafeHwnd {}
@@ -0,0 +1,148 @@
+/// What the tooltip registry needs from the window hosting the controls.
+pub trait ControlHost {
+    type Handle: Copy + PartialEq;
+
+    fn control_text(&self, control: Self::Handle) -> String;
+    fn is_text_truncated(&self, control: Self::Handle) -> bool;
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TooltipBinding {
+    /// Always shows the given text.
+    Text(String),
+    /// Shows the control's own text when it doesn't fit, the given text
+    /// otherwise (may be empty for no tooltip).
+    FullTextWhenTruncated(String),
+}
+
+/// Control-to-tooltip bindings. The texts are resolved when the tooltip
+/// asks for them (TTN_GETDISPINFO), so dynamic bindings follow the
+/// current content of the control.
+#[derive(Clone, Debug)]
+pub struct TooltipRegistry<H> {
+    bindings: Vec<(H, TooltipBinding)>,
+}
+
+impl<H> Default for TooltipRegistry<H> {
+    fn default() -> Self {
+        Self { bindings: Vec::new() }
+    }
+}
+
+impl<H: Copy + PartialEq> TooltipRegistry<H> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Binds or rebinds `control`, returns true when it wasn't bound yet
+    /// and the tool still has to be added to the tooltip control.
+    pub fn bind(&mut self, control: H, binding: TooltipBinding) -> bool {
+        match self.bindings.iter_mut().find(|(h, _)| *h == control) {
+            Some((_, b)) => {
+                *b = binding;
+                false
+            },
+            None => {
+                self.bindings.push((control, binding));
+                true
+            },
+        }
+    }
+
+    /// Returns true when `control` was bound.
+    pub fn unbind(&mut self, control: H) -> bool {
+        let len = self.bindings.len();
+        self.bindings.retain(|(h, _)| *h != control);
+        self.bindings.len() != len
+    }
+
+    pub fn is_bound(&self, control: H) -> bool {
+        self.bindings.iter().any(|(h, _)| *h == control)
+    }
+
+    pub fn controls(&self) -> impl Iterator<Item = H> + '_ {
+        self.bindings.iter().map(|(h, _)| *h)
+    }
+
+    /// Text to show for `control` right now, `None` for no tooltip.
+    pub fn text_for<C>(&self, host: &C, control: H) -> Option<String>
+    where C: ControlHost<Handle = H> {
+        let (_, binding) = self.bindings.iter().find(|(h, _)| *h == control)?;
+        let text = match binding {
+            TooltipBinding::Text(text) => text.clone(),
+            TooltipBinding::FullTextWhenTruncated(text) => {
+                match host.is_text_truncated(control) {
+                    true => host.control_text(control),
+                    false => text.clone(),
+                }
+            },
+        };
+        match text.is_empty() {
+            true => None,
+            false => Some(text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FakeHost {
+        texts: Vec<(u32, String)>,
+        width: usize,
+    }
+
+    impl ControlHost for FakeHost {
+        type Handle = u32;
+
+        fn control_text(&self, control: u32) -> String {
+            self.texts.iter()
+                .find(|(h, _)| *h == control)
+                .map(|(_, t)| t.clone())
+                .unwrap_or_default()
+        }
+
+        fn is_text_truncated(&self, control: u32) -> bool {
+            self.control_text(control).chars().count() > self.width
+        }
+    }
+
+    fn host() -> FakeHost {
+        FakeHost {
+            texts: vec![(1, "GO".to_string()), (2, r"C:\a\very\long\path".to_string())],
+            width: 8,
+        }
+    }
+
+    #[test]
+    fn bind_reports_new_tools_only() {
+        let mut reg = TooltipRegistry::new();
+        assert!(reg.bind(1, TooltipBinding::Text("run".into())));
+        assert!(!reg.bind(1, TooltipBinding::Text("start".into())));
+        assert_eq!(reg.text_for(&host(), 1).as_deref(), Some("start"));
+        assert_eq!(reg.controls().collect::<Vec<_>>(), vec![1]);
+    }
+
+    #[test]
+    fn unbound_controls_have_no_tooltip() {
+        let mut reg = TooltipRegistry::new();
+        reg.bind(1, TooltipBinding::Text("run".into()));
+        assert_eq!(reg.text_for(&host(), 2), None);
+        assert!(reg.unbind(1));
+        assert!(!reg.unbind(1));
+        assert_eq!(reg.text_for(&host(), 1), None);
+    }
+
+    #[test]
+    fn dynamic_binding_follows_truncation() {
+        let mut reg = TooltipRegistry::new();
+        reg.bind(2, TooltipBinding::FullTextWhenTruncated("path".into()));
+        let mut host = host();
+        assert_eq!(reg.text_for(&host, 2).as_deref(), Some(r"C:\a\very\long\path"));
+        host.width = 100;
+        assert_eq!(reg.text_for(&host, 2).as_deref(), Some("path"));
+        reg.bind(2, TooltipBinding::FullTextWhenTruncated(String::new()));
+        assert_eq!(reg.text_for(&host, 2), None);
+    }
+}
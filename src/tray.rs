@@ -0,0 +1,259 @@
+use crate::status::Outcome;
+
+// limits of NOTIFYICONDATAW, counted in utf-16 units without the nul
+pub const TIP_MAX: usize = 127;
+pub const INFO_MAX: usize = 255;
+pub const INFO_TITLE_MAX: usize = 63;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrayCommand {
+    Show,
+    Run,
+    Pause,
+    Cancel,
+    MinimizeToTray,
+    Quit,
+}
+
+impl TrayCommand {
+    // menu item ids, kept clear of the control ids
+    pub const ALL: [TrayCommand; 6] = [
+        TrayCommand::Show,
+        TrayCommand::Run,
+        TrayCommand::Pause,
+        TrayCommand::Cancel,
+        TrayCommand::MinimizeToTray,
+        TrayCommand::Quit,
+    ];
+
+    pub fn id(&self) -> usize {
+        match self {
+            TrayCommand::Show => 101,
+            TrayCommand::Run => 102,
+            TrayCommand::Cancel => 103,
+            TrayCommand::MinimizeToTray => 104,
+            TrayCommand::Quit => 105,
+            TrayCommand::Pause => 106,
+        }
+    }
+
+    pub fn from_id(id: usize) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.id() == id)
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            TrayCommand::Show => "顯示視窗",
+            TrayCommand::Run => "執行",
+            TrayCommand::Pause => "暫停",
+            TrayCommand::Cancel => "取消",
+            TrayCommand::MinimizeToTray => "關閉時縮小到系統匣",
+            TrayCommand::Quit => "結束",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CloseAction {
+    HideToTray,
+    Quit,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Notification {
+    pub title: String,
+    pub text: String,
+    pub is_error: bool,
+}
+
+/// State of the notification-area icon, the window applies it with
+/// `Shell_NotifyIconW` after each change.
+#[derive(Clone, Debug, Default)]
+pub struct TrayModel {
+    app_name: String,
+    window_visible: bool,
+    minimize_to_tray: bool,
+    running: bool,
+    paused: bool,
+    tooltip: String,
+    pending: Option<Notification>,
+}
+
+impl TrayModel {
+    pub fn new(app_name: &str) -> Self {
+        Self {
+            app_name: app_name.to_string(),
+            window_visible: true,
+            tooltip: truncate_utf16(app_name, TIP_MAX),
+            ..Default::default()
+        }
+    }
+
+    pub fn tooltip(&self) -> &str {
+        &self.tooltip
+    }
+
+    pub fn window_visible(&self) -> bool {
+        self.window_visible
+    }
+
+    pub fn minimize_to_tray(&self) -> bool {
+        self.minimize_to_tray
+    }
+
+    pub fn set_minimize_to_tray(&mut self, enable: bool) {
+        self.minimize_to_tray = enable;
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused && self.running;
+    }
+
+    pub fn is_enabled(&self, command: TrayCommand) -> bool {
+        match command {
+            TrayCommand::Run => !self.running,
+            TrayCommand::Pause | TrayCommand::Cancel => self.running,
+            _ => true,
+        }
+    }
+
+    pub fn on_close_requested(&mut self) -> CloseAction {
+        match self.minimize_to_tray {
+            true => {
+                self.window_visible = false;
+                CloseAction::HideToTray
+            },
+            false => CloseAction::Quit,
+        }
+    }
+
+    pub fn on_show(&mut self) {
+        self.window_visible = true;
+    }
+
+    pub fn on_started(&mut self) {
+        self.running = true;
+        self.pending = None;
+        self.set_tooltip(&format!("{} - 執行中", self.app_name));
+    }
+
+    pub fn on_progress(&mut self, done: usize, total: usize) {
+        let text = match total {
+            0 => format!("{} - {}", self.app_name, done),
+            _ => format!(
+                "{} - {}/{} ({}%)",
+                self.app_name,
+                done,
+                total,
+                (done.min(total) * 100 / total)
+            ),
+        };
+        self.set_tooltip(&text);
+    }
+
+    pub fn on_finished(&mut self, outcome: Outcome, detail: &str) {
+        self.running = false;
+        self.paused = false;
+        let title = match outcome {
+            Outcome::Done => format!("{} - 完成", self.app_name),
+            Outcome::Cancelled => format!("{} - 已取消", self.app_name),
+            Outcome::Failed => format!("{} - 失敗", self.app_name),
+        };
+        self.set_tooltip(&title);
+        self.pending = Some(Notification {
+            title: truncate_utf16(&title, INFO_TITLE_MAX),
+            text: truncate_utf16(detail, INFO_MAX),
+            is_error: outcome == Outcome::Failed,
+        });
+    }
+
+    /// Takes the balloon waiting to be shown, if any.
+    pub fn take_notification(&mut self) -> Option<Notification> {
+        self.pending.take()
+    }
+
+    fn set_tooltip(&mut self, text: &str) {
+        self.tooltip = truncate_utf16(text, TIP_MAX);
+    }
+}
+
+/// Cuts `s` so it fits in `max` utf-16 units without splitting a character.
+pub fn truncate_utf16(s: &str, max: usize) -> String {
+    let mut units = 0;
+    let mut end = 0;
+    for (i, c) in s.char_indices() {
+        units += c.len_utf16();
+        if units > max {
+            return s[..i].to_string();
+        }
+        end = i + c.len_utf8();
+    }
+    s[..end].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn close_hides_only_when_enabled() {
+        let mut tray = TrayModel::new("app");
+        assert_eq!(tray.on_close_requested(), CloseAction::Quit);
+        tray.set_minimize_to_tray(true);
+        assert_eq!(tray.on_close_requested(), CloseAction::HideToTray);
+        assert!(!tray.window_visible());
+        tray.on_show();
+        assert!(tray.window_visible());
+    }
+
+    #[test]
+    fn run_and_cancel_follow_the_task() {
+        let mut tray = TrayModel::new("app");
+        assert!(tray.is_enabled(TrayCommand::Run));
+        assert!(!tray.is_enabled(TrayCommand::Cancel));
+        tray.on_started();
+        assert!(!tray.is_enabled(TrayCommand::Run));
+        assert!(tray.is_enabled(TrayCommand::Cancel));
+        tray.set_paused(true);
+        assert!(tray.paused());
+        tray.on_finished(Outcome::Done, "");
+        assert!(!tray.paused());
+        assert!(!tray.is_enabled(TrayCommand::Pause));
+    }
+
+    #[test]
+    fn progress_tooltip() {
+        let mut tray = TrayModel::new("app");
+        tray.on_started();
+        tray.on_progress(12, 30);
+        assert_eq!(tray.tooltip(), "app - 12/30 (40%)");
+    }
+
+    #[test]
+    fn finishing_queues_one_notification() {
+        let mut tray = TrayModel::new("app");
+        tray.on_started();
+        tray.on_finished(Outcome::Failed, "boom");
+        let n = tray.take_notification().unwrap();
+        assert_eq!(n.title, "app - 失敗");
+        assert!(n.is_error);
+        assert_eq!(tray.take_notification(), None);
+        tray.on_started();
+        tray.on_finished(Outcome::Cancelled, "");
+        let n = tray.take_notification().unwrap();
+        assert_eq!(n.title, "app - 已取消");
+        assert!(!n.is_error);
+    }
+
+    #[test]
+    fn truncate_keeps_surrogate_pairs_whole() {
+        assert_eq!(truncate_utf16("abc", 2), "ab");
+        assert_eq!(truncate_utf16("a😀b", 2), "a");
+        assert_eq!(truncate_utf16("a😀b", 3), "a😀");
+        assert_eq!(TrayCommand::from_id(TrayCommand::Quit.id()), Some(TrayCommand::Quit));
+    }
+}
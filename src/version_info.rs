@@ -0,0 +1,309 @@
+// layout of the VS_VERSIONINFO resource written by winres in build.rs:
+// every node is wLength, wValueLength, wType, a nul terminated utf-16
+// key, padding to 32 bits, the value, padding, then the child nodes
+const FIXED_FILE_INFO_SIGNATURE: u32 = 0xFEEF04BD;
+const FIXED_FILE_INFO_SIZE: usize = 52;
+const NODE_HEADER_SIZE: usize = 6;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VersionInfo {
+    pub file_version: Option<[u16; 4]>,
+    pub product_version: Option<[u16; 4]>,
+    /// (language/codepage like "040904b0", key, value)
+    pub strings: Vec<(String, String, String)>,
+}
+
+impl VersionInfo {
+    /// Value of a string like "ProductName" from the first table that has it.
+    pub fn string(&self, key: &str) -> Option<&str> {
+        self.strings
+            .iter()
+            .find(|(_, k, _)| k == key)
+            .map(|(_, _, v)| v.as_str())
+    }
+}
+
+pub fn format_version(v: [u16; 4]) -> String {
+    format!("{}.{}.{}.{}", v[0], v[1], v[2], v[3])
+}
+
+/// Text of the about dialog, the resource strings with the commit and
+/// date captured at build time.
+pub fn about_text(info: &VersionInfo, commit: &str, build_date: &str) -> String {
+    let mut lines = Vec::new();
+    if let Some(name) = info.string("ProductName") {
+        lines.push(name.to_string());
+    }
+    let file_version = info.string("FileVersion")
+        .map(str::to_string)
+        .or(info.file_version.map(format_version));
+    if let Some(v) = file_version {
+        lines.push(format!("檔案版本: {}", v));
+    }
+    let product_version = info.string("ProductVersion")
+        .map(str::to_string)
+        .or(info.product_version.map(format_version));
+    if let Some(v) = product_version {
+        lines.push(format!("產品版本: {}", v));
+    }
+    lines.push(format!("Commit: {}", commit));
+    lines.push(format!("建置日期: {}", build_date));
+    if let Some(c) = info.string("LegalCopyright") {
+        lines.push(c.to_string());
+    }
+    lines.join("\r\n")
+}
+
+struct Node<'a> {
+    key: String,
+    value_type: u16,
+    value: &'a [u8],
+    children: &'a [u8],
+}
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+fn read_u16(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes([*bytes.get(at)?, *bytes.get(at + 1)?]))
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+/// Reads a nul terminated utf-16 string at `at`, returns it with the
+/// offset after the nul.
+fn read_wstr(bytes: &[u8], at: usize) -> Option<(String, usize)> {
+    let mut units = Vec::new();
+    let mut i = at;
+    loop {
+        let c = read_u16(bytes, i)?;
+        i += 2;
+        if c == 0 {
+            break;
+        }
+        units.push(c);
+    }
+    Some((String::from_utf16_lossy(&units), i))
+}
+
+/// Parses one node at the start of `bytes`, returns it and its length.
+fn read_node(bytes: &[u8]) -> Option<(Node<'_>, usize)> {
+    let length = read_u16(bytes, 0)? as usize;
+    let value_length = read_u16(bytes, 2)? as usize;
+    let value_type = read_u16(bytes, 4)?;
+    if length < NODE_HEADER_SIZE || length > bytes.len() {
+        return None;
+    }
+    let bytes = &bytes[..length];
+    let (key, after_key) = read_wstr(bytes, NODE_HEADER_SIZE)?;
+    let value_start = align4(after_key).min(length);
+    // text values count utf-16 units, binary ones bytes
+    let value_bytes = match value_type {
+        1 => value_length * 2,
+        _ => value_length,
+    };
+    let value_end = (value_start + value_bytes).min(length);
+    let children_start = align4(value_end).min(length);
+    Some((
+        Node {
+            key,
+            value_type,
+            value: &bytes[value_start..value_end],
+            children: &bytes[children_start..],
+        },
+        length,
+    ))
+}
+
+fn children(mut bytes: &[u8]) -> Vec<Node<'_>> {
+    let mut nodes = Vec::new();
+    while bytes.len() >= NODE_HEADER_SIZE {
+        let Some((node, length)) = read_node(bytes) else {
+            break;
+        };
+        nodes.push(node);
+        bytes = &bytes[align4(length).min(bytes.len())..];
+    }
+    nodes
+}
+
+fn text_value(node: &Node) -> String {
+    match node.value_type {
+        1 => read_wstr(node.value, 0)
+            .map(|(s, _)| s)
+            // values without a terminating nul
+            .unwrap_or_else(|| {
+                let units: Vec<u16> = node.value
+                    .chunks_exact(2)
+                    .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                    .collect();
+                String::from_utf16_lossy(&units)
+            }),
+        _ => String::new(),
+    }
+}
+
+/// Parses a VS_VERSIONINFO blob as returned by LoadResource or
+/// GetFileVersionInfoW. Returns `None` when the root node is malformed,
+/// damaged children are skipped.
+pub fn parse(bytes: &[u8]) -> Option<VersionInfo> {
+    let (root, _) = read_node(bytes)?;
+    if root.key != "VS_VERSION_INFO" {
+        return None;
+    }
+    let mut info = VersionInfo::default();
+    if root.value.len() >= FIXED_FILE_INFO_SIZE &&
+        read_u32(root.value, 0) == Some(FIXED_FILE_INFO_SIGNATURE)
+    {
+        let version = |at: usize| -> Option<[u16; 4]> {
+            let ms = read_u32(root.value, at)?;
+            let ls = read_u32(root.value, at + 4)?;
+            Some([(ms >> 16) as u16, ms as u16, (ls >> 16) as u16, ls as u16])
+        };
+        info.file_version = version(8);
+        info.product_version = version(16);
+    }
+    for child in children(root.children) {
+        if child.key != "StringFileInfo" {
+            continue;
+        }
+        for table in children(child.children) {
+            for string in children(table.children) {
+                info.strings.push((
+                    table.key.clone(),
+                    string.key.clone(),
+                    text_value(&string),
+                ));
+            }
+        }
+    }
+    Some(info)
+}
+
+/// Reads the version resource embedded in the running executable.
+#[cfg(windows)]
+pub fn load_own() -> Option<VersionInfo> {
+    use windows::core::PCWSTR;
+    use windows::Win32::{
+        System::LibraryLoader::*,
+        UI::WindowsAndMessaging::RT_VERSION,
+    };
+
+    // VS_VERSION_INFO is resource id 1
+    const VS_VERSION_INFO: PCWSTR = PCWSTR(1 as _);
+    unsafe {
+        let module = GetModuleHandleW(None).ok()?;
+        let resource = FindResourceW(module, VS_VERSION_INFO, RT_VERSION);
+        if resource.is_invalid() {
+            return None;
+        }
+        let size = SizeofResource(module, resource) as usize;
+        let data = LoadResource(module, resource).ok()?;
+        let ptr = LockResource(data) as *const u8;
+        if ptr.is_null() {
+            return None;
+        }
+        parse(std::slice::from_raw_parts(ptr, size))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // builds nodes the way rc.exe lays them out
+    fn node(key: &str, value_type: u16, value: &[u8], value_length: usize, children: &[Vec<u8>]) -> Vec<u8> {
+        let mut b = vec![0u8; NODE_HEADER_SIZE];
+        for c in key.encode_utf16().chain(std::iter::once(0)) {
+            b.extend(c.to_le_bytes());
+        }
+        b.resize(align4(b.len()), 0);
+        b.extend(value);
+        for child in children {
+            b.resize(align4(b.len()), 0);
+            b.extend(child);
+        }
+        let length = b.len() as u16;
+        b[0..2].copy_from_slice(&length.to_le_bytes());
+        b[2..4].copy_from_slice(&(value_length as u16).to_le_bytes());
+        b[4..6].copy_from_slice(&value_type.to_le_bytes());
+        b
+    }
+
+    fn string(key: &str, value: &str) -> Vec<u8> {
+        let units: Vec<u16> = value.encode_utf16().chain(std::iter::once(0)).collect();
+        let bytes: Vec<u8> = units.iter().flat_map(|c| c.to_le_bytes()).collect();
+        node(key, 1, &bytes, units.len(), &[])
+    }
+
+    fn fixed_info(file: [u16; 4], product: [u16; 4]) -> Vec<u8> {
+        let mut b = Vec::new();
+        b.extend(FIXED_FILE_INFO_SIGNATURE.to_le_bytes());
+        b.extend(0x0001_0000u32.to_le_bytes());
+        for v in [file, product] {
+            b.extend((((v[0] as u32) << 16) | v[1] as u32).to_le_bytes());
+            b.extend((((v[2] as u32) << 16) | v[3] as u32).to_le_bytes());
+        }
+        b.resize(FIXED_FILE_INFO_SIZE, 0);
+        b
+    }
+
+    fn fixture() -> Vec<u8> {
+        let table = node("040904b0", 1, &[], 0, &[
+            string("FileVersion", "0.1.0.0"),
+            string("ProductName", "windows-app"),
+            string("LegalCopyright", "© 2024 Kin|Jiaching. All rights reserved."),
+        ]);
+        let strings = node("StringFileInfo", 1, &[], 0, &[table]);
+        let translation = node("Translation", 0, &[0x09, 0x04, 0xb0, 0x04], 4, &[]);
+        let vars = node("VarFileInfo", 1, &[], 0, &[translation]);
+        let fixed = fixed_info([0, 1, 0, 0], [0, 1, 0, 0]);
+        node("VS_VERSION_INFO", 0, &fixed, fixed.len(), &[strings, vars])
+    }
+
+    #[test]
+    fn parses_fixed_info_and_strings() {
+        let info = parse(&fixture()).unwrap();
+        assert_eq!(info.file_version, Some([0, 1, 0, 0]));
+        assert_eq!(info.product_version, Some([0, 1, 0, 0]));
+        assert_eq!(info.string("ProductName"), Some("windows-app"));
+        assert_eq!(info.string("FileVersion"), Some("0.1.0.0"));
+        assert_eq!(info.strings[0].0, "040904b0");
+        assert_eq!(info.string("Missing"), None);
+    }
+
+    #[test]
+    fn rejects_other_roots_and_truncated_blobs() {
+        assert_eq!(parse(&node("Other", 0, &[], 0, &[])), None);
+        assert_eq!(parse(&[]), None);
+        let blob = fixture();
+        assert_eq!(parse(&blob[..blob.len() / 2]), None);
+    }
+
+    #[test]
+    fn skips_damaged_children() {
+        let mut blob = fixture();
+        // claim a child longer than the buffer
+        let first_child = align4(NODE_HEADER_SIZE + "VS_VERSION_INFO".len() * 2 + 2) +
+            FIXED_FILE_INFO_SIZE;
+        blob[first_child..first_child + 2].copy_from_slice(&u16::MAX.to_le_bytes());
+        let info = parse(&blob).unwrap();
+        assert_eq!(info.file_version, Some([0, 1, 0, 0]));
+        assert!(info.strings.is_empty());
+    }
+
+    #[test]
+    fn about_text_lists_versions_and_build() {
+        let info = parse(&fixture()).unwrap();
+        let text = about_text(&info, "abc1234", "2024-05-01");
+        assert_eq!(
+            text,
+            "windows-app\r\n檔案版本: 0.1.0.0\r\n產品版本: 0.1.0.0\r\n\
+             Commit: abc1234\r\n建置日期: 2024-05-01\r\n\
+             © 2024 Kin|Jiaching. All rights reserved."
+        );
+    }
+}
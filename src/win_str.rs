@@ -55,4 +55,23 @@ pub fn multi_byte_to_wide_char(
         }
         Err(Cow::Owned(String::from("convert failed")))
     }
-}
\ No newline at end of file
+}
+/// Copies `s` into a fixed size wide buffer such as the ones in
+/// NOTIFYICONDATAW, truncating and always leaving a trailing nul.
+pub fn str_to_wide_buf(s: &str, buf: &mut [u16]) {
+    if buf.is_empty() {
+        return;
+    }
+    let max = buf.len() - 1;
+    let mut len = 0;
+    for c in s.chars() {
+        let mut units = [0u16; 2];
+        let units = c.encode_utf16(&mut units);
+        if len + units.len() > max {
+            break;
+        }
+        buf[len..len + units.len()].copy_from_slice(units);
+        len += units.len();
+    }
+    buf[len] = 0;
+}
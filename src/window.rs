@@ -1,7 +1,10 @@
 use std::{
     mem::zeroed,
+    path::{Path, PathBuf},
     collections::HashMap,
     sync::{Arc, Mutex},
+    thread,
+    time::Instant,
 };
 use windows::{
     core::*,
@@ -14,24 +17,140 @@ use windows::Win32::{
         Controls::*,
         HiDpi::*,
         Input::KeyboardAndMouse::*,
+        Shell::*,
+        Controls::Dialogs::*,
     },
+    System::Com::*,
     System::{
         LibraryLoader::*,
         DataExchange::COPYDATASTRUCT,
-        SystemServices::*,
     },
     Graphics::Gdi::*,
 };
 use crate::{
     app::App,
+    application::{Application, Creation},
+    error::{AppError, Context},
+    log_viewer::LogViewer,
+    window_registry::WindowRole,
+    cli,
+    clipboard::*,
+    copydata,
+    crash,
+    diagnostics::{self, Level},
+    controls::{
+        Align, Button, ButtonHandle, ControlBuilder, ControlHandle, ControlId,
+        ControlRegistry, Edit, EditHandle, ProgressBar, ProgressBarHandle, RawControl,
+        StatusBarHandle, TooltipHandle, PBST_ERROR, PBST_NORMAL, PBST_PAUSED,
+    },
+    progress::*,
     win_str::*,
     dialog::*,
+    events::{Command, Event, Router, BN_CLICKED},
+    ipc,
+    json::Value,
+    messages::{self, Message},
+    reentrancy::{Coalesce, StateCell},
+    startup::{ShowMode, StartupPlan},
+    status::*,
+    taskbar::*,
+    version_info,
+    result_log::*,
+    rpc::{self, Request},
+    thread_safe::ThreadSafeHwnd,
+    tooltip::*,
+    tray::*,
 };
 
+// the state of the main window as its wndproc sees it, the subclassed
+// controls reach it by messages to the main window, never by a pointer
+type State = StateCell<Window, Deferred>;
+
+/// A message that came while the window was busy. The data of the worker
+/// only lives as long as its SendMessageW, so it's copied out.
+enum Deferred {
+    Message(Message),
+    Progress(ProgressUpdate),
+    Result(String),
+    Enable(bool),
+    TaskFailed(String),
+    ErrorLine(String),
+    Repaint,
+    /// The command line of a second instance.
+    Forwarded(Vec<u8>),
+}
+
+impl Deferred {
+    unsafe fn capture(message: u32, wparam: WPARAM, lparam: LPARAM) -> Option<Self> {
+        match message {
+            WM_SIZE | WM_TIMER | WM_COMMAND | WM_CLOSE | WM_DESTROY | Window::APP_TRAY => {
+                Some(Deferred::Message(Message::crack(message, wparam.0, lparam.0)))
+            },
+            WM_PAINT => Some(Deferred::Repaint),
+            Window::APP_UPDATE_PROGRESS => {
+                Some(Deferred::Progress(payload::<ProgressUpdate>(wparam).clone()))
+            },
+            Window::APP_UPDATE_RESULT => {
+                Some(Deferred::Result(payload::<String>(wparam).clone()))
+            },
+            Window::CTRL_EN_DIS => Some(Deferred::Enable(*payload::<bool>(wparam))),
+            Window::APP_TASK_FAILED => {
+                Some(Deferred::TaskFailed(payload::<String>(wparam).clone()))
+            },
+            Window::APP_ERROR_LINE => {
+                Some(Deferred::ErrorLine(payload::<String>(wparam).clone()))
+            },
+            WM_COPYDATA => forwarded_bytes(lparam).map(Deferred::Forwarded),
+            _ => None,
+        }
+    }
+}
+
+impl Coalesce for Deferred {
+    fn replaces(&self, older: &Self) -> bool {
+        match (self, older) {
+            (Deferred::Message(message), Deferred::Message(older)) => message.replaces(older),
+            (Deferred::Progress(_), Deferred::Progress(_)) => true,
+            (Deferred::Repaint, Deferred::Repaint) => true,
+            _ => false,
+        }
+    }
+}
+
+/// The data a worker sent with `App::post_message`, a COPYDATASTRUCT in
+/// the wparam.
+unsafe fn payload<'a, T>(wparam: WPARAM) -> &'a T {
+    let cds = &*(wparam.0 as *const COPYDATASTRUCT);
+    &*(cds.lpData as *const T)
+}
+
+/// A request of the control api on its way to the ui thread and the
+/// response on its way back.
+struct ControlCall<'a> {
+    request: &'a Request,
+    response: Option<Value>,
+}
+
+/// The bytes of a WM_COPYDATA from a second instance, copied since they
+/// only live as long as the message. None for any other sender.
+unsafe fn forwarded_bytes(lparam: LPARAM) -> Option<Vec<u8>> {
+    let cds = (lparam.0 as *const COPYDATASTRUCT).as_ref()?;
+    if cds.dwData != copydata::FORWARD_ARGS || cds.lpData.is_null() || cds.cbData == 0 {
+        return None;
+    }
+    let len = (cds.cbData as usize).min(copydata::MAX_SIZE + 1);
+    Some(std::slice::from_raw_parts(cds.lpData as *const u8, len).to_vec())
+}
+
 #[derive(Default)]
 pub(crate) struct StrResource {
     pub(crate) path: HSTRING,
     pub(crate) run: HSTRING,
+    pub(crate) path_tip: String,
+    pub(crate) run_tip: String,
+    pub(crate) path_txt_tip: String,
+    pub(crate) progress_tip: String,
+    pub(crate) result_tip: String,
 }
 
 impl StrResource {
@@ -39,6 +158,11 @@ impl StrResource {
         Self {
             path: HSTRING::from("路徑"),
             run: HSTRING::from("GO"),
+            path_tip: String::from("選擇要處理的資料夾"),
+            run_tip: String::from("開始執行工作"),
+            path_txt_tip: String::from("要處理的資料夾路徑"),
+            progress_tip: String::from("目前工作的進度"),
+            result_tip: String::from("工作輸出的紀錄"),
         }
     }
 }
@@ -47,11 +171,29 @@ impl StrResource {
 pub struct Window {
     app: App,  
     main: HWND,
-    result_log: HWND,
-    progress_bar: HWND,
-    progress_txt: HWND,
-    path_txt: HWND,
+    log: ResultLog,
+    find_dialog: HWND,
+    find: Box<FINDREPLACEW>,
+    find_what: Vec<u16>,
+    find_msg: u32,
+    font: HFONT,
+    status_bar: StatusBarHandle<HWND>,
+    tooltip: TooltipHandle<HWND>,
+    tooltips: TooltipRegistry<HWND>,
+    // keeps the text handed out in TTN_GETDISPINFOW alive
+    tooltip_text: HSTRING,
+    status: StatusModel,
+    progress: ProgressModel,
+    tray: TrayModel,
+    tray_added: bool,
+    taskbar: Option<ITaskbarList3>,
+    taskbar_created: u32,
+    // layout rect and window of each control by id
     controls: HashMap<usize, Rect>,
+    registry: ControlRegistry<HWND>,
+    router: Router<Window>,
+    // from the command line, applied once the window is up
+    startup: StartupPlan,
     local: StrResource,
     width: u32,
     height: u32,
@@ -59,6 +201,7 @@ pub struct Window {
     btn_width: f32,
     progress_txt_width: f32,
     oneline_height: f32,
+    status_height: f32,
     padding: f32,
 }
 
@@ -66,92 +209,84 @@ impl Window {
     pub const APP_UPDATE_PROGRESS: u32 = WM_USER + 1;
     pub const APP_UPDATE_RESULT: u32 = WM_USER + 2;
     pub const CTRL_EN_DIS: u32 = WM_USER + 3;
-    const ID_BTN_PATH: usize = 1;
-    const ID_BTN_RUN: usize = 2;
-    const ID_TEXTBOX_RESULT: usize = 3;
-    const ID_TEXTBOX_PATH: usize = 4;
-    const ID_PROGRESS_BAR: usize = 5;
-    const ID_PROGRESS_TXT: usize = 6;
-
-    pub fn new(
+    const APP_TRAY: u32 = WM_USER + 4;
+    pub const APP_TASK_FAILED: u32 = WM_USER + 5;
+    // a request of the control api, wparam is a ControlCall
+    const APP_CONTROL: u32 = WM_USER + 6;
+    pub const APP_ERROR_LINE: u32 = WM_USER + 7;
+    // WM_PASTE in the path textbox, the result says whether it was taken
+    const APP_PASTE_PATH: u32 = WM_USER + 8;
+    const ID_TRAY_ICON: u32 = 1;
+    const ID_BTN_PATH: ControlId<Button> = ControlId::new(1);
+    const ID_BTN_RUN: ControlId<Button> = ControlId::new(2);
+    const ID_TEXTBOX_RESULT: ControlId<Edit> = ControlId::new(3);
+    const ID_TEXTBOX_PATH: ControlId<Edit> = ControlId::new(4);
+    const ID_PROGRESS_BAR: ControlId<ProgressBar> = ControlId::new(5);
+    const ID_PROGRESS_TXT: ControlId<Edit> = ControlId::new(6);
+    const ID_STATUS_BAR: usize = 7;
+    const ID_TIMER_ELAPSED: usize = 1;
+    const ID_MENU_ABOUT: usize = 301;
+    const ID_MENU_LOG_VIEWER: usize = 302;
+    const ID_MENU_VERBOSE_LOG: usize = 303;
+
+    /// Found by a second instance, so it's not as generic as "window".
+    pub const CLASS: &'static str = "windows-app";
+
+    /// Creates the main window, it runs until `Application::run` returns.
+    pub fn open(
         title: &str, 
         width: u32, 
         height: u32, 
-        app: App
-    ) -> Result<Self> {
-        unsafe {
-            let instance = GetModuleHandleW(None)?;
+        app: App,
+        startup: StartupPlan,
+    ) -> std::result::Result<HWND, AppError> {
+        Application::register_class(Self::CLASS, Some(Self::wndproc))
+            .context("註冊視窗類別")?;
 
-            let window_class = w!("window");
-            let wc = WNDCLASSW {
-                hCursor: LoadCursorW(None, IDC_ARROW)?,
-                hInstance: instance.into(),
-                lpszClassName: window_class,
-                style: CS_HREDRAW | CS_VREDRAW,
-                lpfnWndProc: Some(Self::wndproc),
-                ..Default::default()
+        let window_style = match startup.show {
+            ShowMode::Normal => WS_OVERLAPPEDWINDOW | WS_VISIBLE,
+            ShowMode::Minimized => WS_OVERLAPPEDWINDOW | WS_VISIBLE | WS_MINIMIZE,
+        };
+        let (w, h) = unsafe {
+            let mut rect = RECT {
+                left: 0,
+                top: 0,
+                right: width as i32,
+                bottom: height as i32,
             };
+            AdjustWindowRect(&mut rect, window_style, false)?;
+            (rect.right - rect.left, rect.bottom - rect.top)
+        };
 
-            let atom = RegisterClassW(&wc);
-            debug_assert!(atom != 0);
-
-            let window_style = WS_OVERLAPPEDWINDOW | WS_VISIBLE;
-            let (w, h) = {
-                let mut rect = RECT {
-                    left: 0,
-                    top: 0,
-                    right: width as i32,
-                    bottom: height as i32,
-                };
-                AdjustWindowRect(&mut rect, window_style, false)?;
-                (rect.right - rect.left, rect.bottom - rect.top)
-            };
-
-            let mut result = Box::new(
-                Self {
-                    main: HWND(std::ptr::null_mut()),
-                    controls: HashMap::new(),
-                    app,
-                    local: StrResource::new(),
-                    width,
-                    height,
-                    btn_width: 80.0,
-                    progress_txt_width: 80.0,
-                    oneline_height: 24.0,
-                    padding: 5.0,
-                    ..Default::default()
-                });
-
-            // create main window
-            result.main = CreateWindowExW(
-                WINDOW_EX_STYLE::default(),
-                window_class,
-                &HSTRING::from(title),
-                window_style,
-                CW_USEDEFAULT,
-                CW_USEDEFAULT,
-                w,
-                h,
-                None,
-                None,
-                instance,
-                Some(result.as_mut() as *mut _ as _),
-            )?;
-
-            let mut message = MSG::default();
-
-            while GetMessageW(&mut message, None, 0, 0).into() {
-                if !<BOOL as Into<bool>>::into(
-                    IsDialogMessageW(result.main, &mut message)
-                ) {
-                    // translates keystrokes (key down, key up) into characters
-                    let _ = TranslateMessage(&message);
-                    DispatchMessageW(&message);
-                }
-            }
+        // WM_NCCREATE takes the state out, it stays here and is dropped
+        // when the window never gets that far
+        let mut creation = Creation::new(
+            Self {
+                main: HWND(std::ptr::null_mut()),
+                controls: HashMap::new(),
+                app,
+                startup,
+                local: StrResource::new(),
+                tray: TrayModel::new(title),
+                width,
+                height,
+                btn_width: 80.0,
+                progress_txt_width: 240.0,
+                oneline_height: 24.0,
+                padding: 5.0,
+                ..Default::default()
+            });
 
-            Ok(*result)
-        }
+        // create main window
+        Application::create_window(
+            Self::CLASS,
+            title,
+            window_style,
+            w,
+            h,
+            WindowRole::Main,
+            &mut creation,
+        )
     }
 
     extern "system" fn wndproc(
@@ -160,22 +295,79 @@ impl Window {
         wparam: WPARAM,
         lparam: LPARAM,
     ) -> LRESULT {
-        unsafe {
-            if message == WM_NCCREATE {
-                let cs = lparam.0 as *const CREATESTRUCTW;
-                let this = (*cs).lpCreateParams as *mut Self;
-                (*this).main = window;
-
-                SetWindowLongPtrW(window, GWLP_USERDATA, this as _);
-            } else {
-                let this = GetWindowLongPtrW(window, GWLP_USERDATA) as *mut Self;
+        Application::guard(window, message, wparam, lparam, || unsafe {
+            Self::window_proc(window, message, wparam, lparam)
+        })
+    }
 
-                if !this.is_null() {
-                    return (*this).message_handler(message, wparam, lparam);
-                }
+    unsafe fn window_proc(
+        window: HWND,
+        message: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        if message == WM_NCCREATE {
+            if let Some(mut this) = Creation::<Self>::from_lparam(lparam).take() {
+                this.main = window;
+                State::attach(window, this);
             }
+        } else if message == WM_NCDESTROY {
+            // the last message of the window, a handler that destroyed
+            // it still holds the state until it returns
+            State::detach(window);
+            Application::window_destroyed(window);
+        } else if let Some(state) = State::of(window) {
+            return Self::handle(&state, window, message, wparam, lparam);
+        }
 
-            DefWindowProcW(window, message, wparam, lparam)
+        DefWindowProcW(window, message, wparam, lparam)
+    }
+
+    /// Hands `message` to the window, unless a handler up the stack already
+    /// has it: SendMessageW from the handler itself, a modal loop (message
+    /// box, file dialog) or DestroyWindow. Those messages are handled right
+    /// after that handler returns, there's never more than one `&mut Window`.
+    unsafe fn handle(
+        state: &State, window: HWND, message: u32, wparam: WPARAM, lparam: LPARAM
+    ) -> LRESULT {
+        let Some(mut this) = state.try_enter() else {
+            return match Deferred::capture(message, wparam, lparam) {
+                Some(deferred) => {
+                    state.defer(deferred);
+                    match message {
+                        // validates the area so it isn't sent again at once
+                        WM_PAINT => DefWindowProcW(window, message, wparam, lparam),
+                        // the sender only learns it was taken, not how it went
+                        WM_COPYDATA => LRESULT(1),
+                        _ => LRESULT(0),
+                    }
+                },
+                None => DefWindowProcW(window, message, wparam, lparam),
+            };
+        };
+        let result = this.message_handler(message, wparam, lparam);
+        drop(this);
+        state.drain(|this, deferred| this.on_deferred(deferred));
+        result
+    }
+
+    fn on_deferred(&mut self, deferred: Deferred) {
+        match deferred {
+            Deferred::Message(message) => {
+                let (msg, wparam, lparam) = message.to_raw();
+                self.message_handler(msg, WPARAM(wparam), LPARAM(lparam));
+            },
+            Deferred::Progress(update) => self.on_update_progress(&update),
+            Deferred::Result(data) => self.on_update_result(&data),
+            Deferred::Enable(enable) => self.on_ctrl_en_dis(enable),
+            Deferred::TaskFailed(text) => self.on_task_failed(&text),
+            Deferred::ErrorLine(line) => self.on_error_line(&line),
+            Deferred::Repaint => unsafe {
+                let _ = InvalidateRect(self.main, None, true);
+            },
+            Deferred::Forwarded(bytes) => {
+                self.on_forwarded(&bytes);
+            },
         }
     }
 
@@ -183,109 +375,430 @@ impl Window {
         &mut self, message: u32, wparam: WPARAM, lparam: LPARAM
     ) -> LRESULT {
         unsafe {
+            // registered message, so it can't be a match arm
+            if message == self.taskbar_created && message != 0 {
+                self.on_taskbar_created();
+                return LRESULT(0);
+            }
+            if message == self.find_msg && message != 0 {
+                self.on_find_msg();
+                return LRESULT(0);
+            }
             match message {
-                WM_CREATE => {
-                    self.set_window();
-                    let _ = self.build_ui();
-                    self.set_ctrl_font();
-                    self.init();
-                    LRESULT(0)
+                WM_CREATE => match self.on_create() {
+                    Ok(()) => LRESULT(0),
+                    // a window without its controls is no use, the error
+                    // goes back to `open` which reports it
+                    Err(e) => {
+                        Creation::<Self>::from_lparam(lparam).fail(e);
+                        LRESULT(-1)
+                    },
+                },
+                WM_CLOSE => {
+                    match self.dispatch(Event::Close) {
+                        true => LRESULT(0),
+                        false => DefWindowProcW(self.main, message, wparam, lparam),
+                    }
                 },
                 WM_DESTROY => {
-                    PostQuitMessage(0);
+                    self.remove_tray_icon();
                     LRESULT(0)
                 },
                 WM_PAINT => {
                     self.on_paint();
                     LRESULT(0)
                 },
-                WM_SIZE => {
-                    self.update_rect(lparam);
-                    self.update_position();
-                    LRESULT(0)
+                WM_SIZE | WM_TIMER | WM_COMMAND | WM_CONTEXTMENU => {
+                    self.on_cracked(Message::crack(message, wparam.0, lparam.0))
                 },
-                WM_COMMAND => {
-                    match wparam.0 {
-                        Self::ID_BTN_PATH => {
-                            self.on_path_btn();
-                        },
-                        Self::ID_BTN_RUN => {
-                            self.on_go_btn();
-                        },
-                        _ => {
-                            self.on_textbox(wparam); 
-                        },
-                    }
+                WM_NOTIFY => {
+                    self.on_notify(lparam)
+                },
+                WM_COPYDATA => match forwarded_bytes(lparam) {
+                    Some(bytes) => LRESULT(self.on_forwarded(&bytes) as isize),
+                    None => LRESULT(0),
+                },
+                Self::APP_TRAY => {
+                    self.on_tray(lparam);
                     LRESULT(0)
                 },
                 Self::APP_UPDATE_RESULT => {
-                    self.on_update_result(wparam); 
+                    self.on_update_result(payload::<String>(wparam)); 
                     LRESULT(0)
                 },
                 Self::APP_UPDATE_PROGRESS => {
-                    self.on_update_progress(wparam);
+                    self.on_update_progress(payload::<ProgressUpdate>(wparam));
                     LRESULT(0)
                 },
                 Self::CTRL_EN_DIS => {
-                    self.on_ctrl_en_dis(wparam);
+                    self.on_ctrl_en_dis(*payload::<bool>(wparam));
+                    LRESULT(0)
+                },
+                Self::APP_TASK_FAILED => {
+                    self.on_task_failed(payload::<String>(wparam));
+                    LRESULT(0)
+                },
+                Self::APP_ERROR_LINE => {
+                    self.on_error_line(payload::<String>(wparam));
                     LRESULT(0)
                 },
+                Self::APP_PASTE_PATH => LRESULT(self.paste_path() as isize),
+                Self::APP_CONTROL => {
+                    let call = &mut *(wparam.0 as *mut ControlCall);
+                    call.response = rpc::handle(self, call.request);
+                    LRESULT(1)
+                },
                 _ => DefWindowProcW(self.main, message, wparam, lparam),
             }
         }
     }
 
-    fn on_ctrl_en_dis(&mut self, wparam: WPARAM) {
+    fn on_create(&mut self) -> std::result::Result<(), AppError> {
+        self.set_window();
+        self.build_menu().context("建立選單")?;
+        self.set_ctrl_font();
+        self.build_ui().context("建立控制項")?;
+        self.build_tooltips().context("建立提示")?;
+        self.build_router();
+        self.init();
+        Ok(())
+    }
+
+    fn on_ctrl_en_dis(&mut self, enable_ctrl: bool) {
         unsafe {
-            // Get COPYDATASTRUCT from WPARAM
-            let cds = &*(wparam.0 as *const COPYDATASTRUCT);
-            // Get MyData from COPYDATASTRUCT
-            let enable_ctrl = &*(cds.lpData as *const bool);
             // Handle the message from worker thread
             // enable disable the remove button
-            self.enable_window(Self::ID_BTN_RUN as u32, *enable_ctrl);
+            self.run_btn().enable(enable_ctrl);
+            // the worker disables the controls when it starts and
+            // enables them again when it is done
+            match enable_ctrl {
+                false => {
+                    self.status.start(Instant::now());
+                    self.progress.reset();
+                    self.tray.on_started();
+                    SetTimer(self.main, Self::ID_TIMER_ELAPSED, 1000, None);
+                },
+                true => {
+                    let now = Instant::now();
+                    let before = self.status.state();
+                    let outcome = match (self.app.is_failed(), self.app.is_cancelled()) {
+                        (true, _) => Outcome::Failed,
+                        (false, true) => Outcome::Cancelled,
+                        (false, false) => Outcome::Done,
+                    };
+                    self.status.finish(now, outcome);
+                    if self.status.state() == RunState::Failed {
+                        self.progress.set_state(ProgressState::Error);
+                        self.refresh_progress();
+                    }
+                    let focused = GetForegroundWindow() == self.main;
+                    if should_flash(before, self.status.state(), focused) {
+                        self.flash_window();
+                    }
+                    let texts = self.status.texts(now);
+                    self.tray.on_finished(
+                        outcome,
+                        &format!("{}  {}", texts[PART_COUNT], texts[PART_ELAPSED])
+                    );
+                    let _ = KillTimer(self.main, Self::ID_TIMER_ELAPSED);
+                },
+            }
+            self.refresh_status();
+            self.update_tray_icon();
+            self.update_taskbar();
+        }
+    }
+
+    fn on_update_result(&mut self, data: &str) {
+        // Handle the message from worker thread
+        crash::remember(data);
+        diagnostics::task_output(data);
+        self.log.append(data);
+        self.result_log().append_line(data);
+    }
+
+    // counted in the status bar, the task's result says if the run failed
+    fn on_error_line(&mut self, line: &str) {
+        self.status.add_error();
+        self.refresh_status();
+        self.on_update_result(&format!("錯誤: {}", line));
+    }
+
+    // the worker panicked, the controls are enabled again already
+    fn on_task_failed(&self, text: &str) {
+        report_error(self.main, &AppError::task(text).context("執行工作"));
+    }
+
+    fn on_update_progress(&mut self, update: &ProgressUpdate) {
+        // Handle the message from worker thread
+        self.progress.apply(Instant::now(), update);
+        self.refresh_progress();
+        let paused = update.state == ProgressState::Paused;
+        self.status.update(update.done, update.total);
+        self.status.set_paused(paused);
+        self.refresh_status();
+        self.tray.set_paused(paused);
+        self.tray.on_progress(update.done, update.total);
+        self.update_tray_icon();
+        self.update_taskbar();
+    }
+
+    fn refresh_progress(&self) {
+        // update progress text
+        self.progress_txt().set_text(&self.progress.text());
+        let bar = self.progress_bar();
+        let marquee = self.progress.state() == ProgressState::Indeterminate;
+        bar.set_marquee(marquee);
+        if marquee {
+            return;
+        }
+        let state = match self.progress.state() {
+            ProgressState::Paused => PBST_PAUSED,
+            ProgressState::Error => PBST_ERROR,
+            _ => PBST_NORMAL,
+        };
+        bar.set_state(state);
+        bar.set_pos(self.progress.percent());
+    }
+
+    fn path_btn(&self) -> ButtonHandle<HWND> {
+        self.registry.handle(Self::ID_BTN_PATH)
+    }
+
+    fn run_btn(&self) -> ButtonHandle<HWND> {
+        self.registry.handle(Self::ID_BTN_RUN)
+    }
+
+    fn path_txt(&self) -> EditHandle<HWND> {
+        self.registry.handle(Self::ID_TEXTBOX_PATH)
+    }
+
+    fn result_log(&self) -> EditHandle<HWND> {
+        self.registry.handle(Self::ID_TEXTBOX_RESULT)
+    }
+
+    fn progress_bar(&self) -> ProgressBarHandle<HWND> {
+        self.registry.handle(Self::ID_PROGRESS_BAR)
+    }
+
+    fn progress_txt(&self) -> EditHandle<HWND> {
+        self.registry.handle(Self::ID_PROGRESS_TXT)
+    }
+
+    fn on_taskbar_created(&mut self) {
+        unsafe {
+            // explorer (re)created our taskbar button, the interface has
+            // to be set up again after a restart of explorer
+            let _ = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
+            let taskbar: Result<ITaskbarList3> = 
+                CoCreateInstance(&TaskbarList, None, CLSCTX_INPROC_SERVER);
+            self.taskbar = match taskbar {
+                Ok(taskbar) if taskbar.HrInit().is_ok() => Some(taskbar),
+                _ => None,
+            };
         }
+        self.update_taskbar();
     }
 
-    fn on_update_result(&self, wparam: WPARAM) {
+    fn update_taskbar(&self) {
+        let Some(taskbar) = &self.taskbar else {
+            return;
+        };
+        // staged tasks count per stage, the button shows the whole task
+        let (done, total) = match self.progress.fraction() {
+            Some(_) => (self.progress.percent(), 100),
+            None => (self.status.processed(), self.status.total()),
+        };
+        let progress = taskbar_progress(self.status.state(), done, total);
+        let flag = match progress.state {
+            TaskbarState::NoProgress => TBPF_NOPROGRESS,
+            TaskbarState::Indeterminate => TBPF_INDETERMINATE,
+            TaskbarState::Normal => TBPF_NORMAL,
+            TaskbarState::Paused => TBPF_PAUSED,
+            TaskbarState::Error => TBPF_ERROR,
+        };
         unsafe {
-            // Get COPYDATASTRUCT from WPARAM
-            let cds = &*(wparam.0 as *const COPYDATASTRUCT);
-            // Get MyData from COPYDATASTRUCT
-            let data = &*(cds.lpData as *const String);
-            // Handle the message from worker thread
-            self.append_to_textbox(self.result_log, data);
+            let _ = taskbar.SetProgressState(self.main, flag);
+            if progress.total > 0 {
+                let _ = taskbar.SetProgressValue(
+                    self.main, progress.completed, progress.total
+                );
+            }
         }
     }
 
-    fn on_update_progress(&self, wparam: WPARAM) {
-        // Handle the message from worker thread
+    fn flash_window(&self) {
         unsafe {
-            // Get COPYDATASTRUCT from WPARAM
-            let cds = &*(wparam.0 as *const COPYDATASTRUCT);
-            // Get MyData from COPYDATASTRUCT
-            let progress = &*(cds.lpData as *const (usize, usize, String));
-            // update progress text
-            let text = HSTRING::from(&progress.2);
-            let _ = SetWindowTextW(self.progress_txt, hstr_to_pcwstr(&text));
-            // handle progress bar
-            // Get current position
-            // let current_pos = SendMessageW(
-            //     hwnd_progress, PBM_GETPOS, WPARAM(0), LPARAM(0)
-            // );
-            // Calculate new position (reset to 0 when reaching 100)
-            let pos = progress.0 as f32 / progress.1 as f32 * 100.0;
-            let new_pos = match pos <= 100.0 {
-                true => pos as usize,
-                false => 100
+            let info = FLASHWINFO {
+                cbSize: std::mem::size_of::<FLASHWINFO>() as u32,
+                hwnd: self.main,
+                dwFlags: FLASHW_TRAY | FLASHW_TIMERNOFG,
+                uCount: 0,
+                dwTimeout: 0,
             };
-            // Update progress bar
-            SendMessageW(
-                self.progress_bar, PBM_SETPOS, WPARAM(new_pos), LPARAM(0)
+            let _ = FlashWindowEx(&info);
+        }
+    }
+
+    fn on_close(&mut self) {
+        unsafe {
+            match self.tray.on_close_requested() {
+                CloseAction::HideToTray => {
+                    let _ = ShowWindow(self.main, SW_HIDE);
+                },
+                CloseAction::Quit => {
+                    let _ = DestroyWindow(self.main);
+                },
+            }
+        }
+    }
+
+    fn on_tray(&mut self, lparam: LPARAM) {
+        // the low word is the mouse message on the icon
+        match messages::loword(lparam.0 as usize) as u32 {
+            WM_LBUTTONDBLCLK => self.on_tray_command(TrayCommand::Show),
+            WM_RBUTTONUP | WM_CONTEXTMENU => self.show_tray_menu(),
+            _ => {}
+        }
+    }
+
+    fn on_tray_command(&mut self, command: TrayCommand) {
+        unsafe {
+            match command {
+                TrayCommand::Show => {
+                    self.tray.on_show();
+                    let _ = ShowWindow(self.main, SW_SHOW);
+                    let _ = ShowWindow(self.main, SW_RESTORE);
+                    let _ = SetForegroundWindow(self.main);
+                },
+                TrayCommand::Run => {
+                    if self.tray.is_enabled(TrayCommand::Run) {
+                        self.on_go_btn();
+                    }
+                },
+                TrayCommand::Pause => {
+                    self.app.pause(!self.tray.paused());
+                },
+                TrayCommand::Cancel => self.app.cancel(),
+                TrayCommand::MinimizeToTray => {
+                    let enable = !self.tray.minimize_to_tray();
+                    self.tray.set_minimize_to_tray(enable);
+                },
+                TrayCommand::Quit => {
+                    let _ = DestroyWindow(self.main);
+                },
+            }
+        }
+    }
+
+    fn show_tray_menu(&self) {
+        unsafe {
+            let Ok(menu) = CreatePopupMenu() else {
+                return;
+            };
+            for command in TrayCommand::ALL {
+                if command == TrayCommand::Quit {
+                    let _ = AppendMenuW(menu, MF_SEPARATOR, 0, None);
+                }
+                let mut flags = MF_STRING;
+                if !self.tray.is_enabled(command) {
+                    flags |= MF_GRAYED;
+                }
+                let checked = match command {
+                    TrayCommand::MinimizeToTray => self.tray.minimize_to_tray(),
+                    TrayCommand::Pause => self.tray.paused(),
+                    _ => false,
+                };
+                if checked {
+                    flags |= MF_CHECKED;
+                }
+                let label = HSTRING::from(command.label());
+                let _ = AppendMenuW(menu, flags, command.id(), &label);
+            }
+            let mut point = POINT::default();
+            let _ = GetCursorPos(&mut point);
+            // required so the menu closes when the user clicks elsewhere
+            let _ = SetForegroundWindow(self.main);
+            let _ = TrackPopupMenu(
+                menu,
+                TPM_RIGHTBUTTON,
+                point.x,
+                point.y,
+                0,
+                self.main,
+                None
             );
+            let _ = DestroyMenu(menu);
+        }
+    }
+
+    fn tray_icon_data(&self) -> NOTIFYICONDATAW {
+        let mut data = NOTIFYICONDATAW {
+            cbSize: std::mem::size_of::<NOTIFYICONDATAW>() as u32,
+            hWnd: self.main,
+            uID: Self::ID_TRAY_ICON,
+            uFlags: NIF_TIP,
+            ..Default::default()
+        };
+        str_to_wide_buf(self.tray.tooltip(), &mut data.szTip);
+        data
+    }
+
+    fn add_tray_icon(&mut self) {
+        unsafe {
+            let mut data = self.tray_icon_data();
+            data.uFlags |= NIF_ICON | NIF_MESSAGE;
+            data.uCallbackMessage = Self::APP_TRAY;
+            data.hIcon = LoadIconW(None, IDI_APPLICATION).unwrap_or_default();
+            self.tray_added = Shell_NotifyIconW(NIM_ADD, &data).as_bool();
         }
     }
 
+    fn update_tray_icon(&mut self) {
+        if !self.tray_added {
+            return;
+        }
+        unsafe {
+            let mut data = self.tray_icon_data();
+            if let Some(notification) = self.tray.take_notification() {
+                data.uFlags |= NIF_INFO;
+                str_to_wide_buf(&notification.title, &mut data.szInfoTitle);
+                str_to_wide_buf(&notification.text, &mut data.szInfo);
+                data.dwInfoFlags = match notification.is_error {
+                    true => NIIF_ERROR,
+                    false => NIIF_INFO,
+                };
+            }
+            let _ = Shell_NotifyIconW(NIM_MODIFY, &data);
+        }
+    }
+
+    fn remove_tray_icon(&mut self) {
+        if !self.tray_added {
+            return;
+        }
+        unsafe {
+            let data = self.tray_icon_data();
+            let _ = Shell_NotifyIconW(NIM_DELETE, &data);
+            self.tray_added = false;
+        }
+    }
+
+    fn refresh_status(&self) {
+        let texts = self.status.texts(Instant::now());
+        for (part, text) in texts.iter().enumerate() {
+            self.status_bar.set_part_text(part, text);
+        }
+    }
+
+    fn update_status_bar(&self, width: u32) {
+        if self.status_bar.raw().is_invalid() {
+            return;
+        }
+        self.status_bar.reposition();
+        self.status_bar.set_parts(&part_edges(width as i32, self.scale_factor));
+    }
+
     fn set_ctrl_font(&mut self) {
         unsafe {
             // Create a scaled font
@@ -306,41 +819,8 @@ impl Window {
                 DEFAULT_PITCH.0 as _,     // PitchAndFamily
                 w!("Segoe UI"),          // Face Name
             );
-            // Set font for both controls
-            SendMessageW(
-                self.path_txt, 
-                WM_SETFONT, 
-                WPARAM(font.0 as usize),
-                LPARAM(1)
-            );
-            SendMessageW(
-                self.progress_txt, 
-                WM_SETFONT, 
-                WPARAM(font.0 as usize),
-                LPARAM(1)
-            );
-            SendMessageW(
-                self.result_log, 
-                WM_SETFONT, 
-                WPARAM(font.0 as usize),
-                LPARAM(1)
-            );
-            if let Ok(hwnd) = GetDlgItem(self.main, Self::ID_BTN_PATH as i32) {
-                SendMessageW(
-                    hwnd, 
-                    WM_SETFONT, 
-                    WPARAM(font.0 as usize),
-                    LPARAM(1)
-                );
-            }
-            if let Ok(hwnd) = GetDlgItem(self.main, Self::ID_BTN_RUN as i32) {
-                SendMessageW(
-                    hwnd, 
-                    WM_SETFONT, 
-                    WPARAM(font.0 as usize),
-                    LPARAM(1)
-                );
-            }
+            // the controls pick it up when they are created
+            self.font = font;
         }
     }
 
@@ -370,34 +850,77 @@ impl Window {
     }
 
     fn on_go_btn(&self) {
-            self.app.run_progress_bar(Arc::new(Mutex::new(self.app.clone())));
-    }
-
-    fn on_textbox(&mut self, wparam: WPARAM) {
-        let notification = Self::hiword(wparam.0 as isize) as u32;
-        let control_id = Self::loword(wparam.0 as isize) as usize;
-        
-        match notification {
-            EN_CHANGE => {
-                // Identify which textbox changed
-                match control_id {
-                    Self::ID_TEXTBOX_PATH => {
-                        unsafe {
-                            if let Ok(control_hwnd) = 
-                                GetDlgItem(self.main, control_id as i32) 
-                            {
-                                let text_length = 
-                                    GetWindowTextLengthW(control_hwnd) + 1;
-                                let mut buffer = vec![0u16; text_length as usize];
-                                GetWindowTextW(control_hwnd, &mut buffer);
-                            }
-                        }
-                    }
-                    _ => {}
+            let path = PathBuf::from(self.path_txt().text());
+            self.app.run_progress_bar(Arc::new(Mutex::new(self.app.clone())), path);
+    }
+
+    fn on_cracked(&mut self, message: Message) -> LRESULT {
+        match message {
+            Message::Size { width, height, .. } => {
+                self.dispatch(Event::Resize { width: width as u32, height: height as u32 });
+            },
+            Message::Timer { id } => {
+                if id == Self::ID_TIMER_ELAPSED {
+                    self.refresh_status();
                 }
             },
-            _ => {}
+            Message::Command { id, code, hwnd } => {
+                let control = match hwnd {
+                    0 => None,
+                    hwnd => Some(HWND(hwnd as _)),
+                };
+                let command = Command { id: id as usize, code: code as u32, control };
+                if !self.dispatch(Event::Command(command)) {
+                    return self.default_proc(message);
+                }
+            },
+            Message::ContextMenu { hwnd, position } => {
+                match self.registry.id_of(HWND(hwnd as _)) == Some(Self::ID_TEXTBOX_RESULT.value()) {
+                    true => self.show_log_menu(position),
+                    false => return self.default_proc(message),
+                }
+            },
+            message => return self.default_proc(message),
+        }
+        LRESULT(0)
+    }
+
+    fn default_proc(&self, message: Message) -> LRESULT {
+        let (msg, wparam, lparam) = message.to_raw();
+        unsafe { DefWindowProcW(self.main, msg, WPARAM(wparam), LPARAM(lparam)) }
+    }
+
+    fn build_router(&mut self) {
+        let router = &mut self.router;
+        router.on_click(Self::ID_BTN_PATH.value(), |w| w.on_path_btn());
+        router.on_click(Self::ID_BTN_RUN.value(), |w| w.on_go_btn());
+        router.on_menu(Self::ID_MENU_ABOUT, |w| w.on_about());
+        router.on_menu(Self::ID_MENU_VERBOSE_LOG, |w| w.on_verbose_log());
+        router.on_menu(Self::ID_MENU_LOG_VIEWER, |w| {
+            if let Err(e) = LogViewer::open(&w.log.text(), w.font) {
+                report_error(w.main, &e.context("開啟結果視窗"));
+            }
+        });
+        for command in TrayCommand::ALL {
+            router.on_menu(command.id(), move |w| w.on_tray_command(command));
+        }
+        for command in LogCommand::ALL {
+            router.on_menu(command.id(), move |w| w.on_log_command(command));
         }
+        router.on_resize(|w, width, height| {
+            w.update_status_bar(width);
+            w.update_rect(width, height);
+            w.update_position();
+        });
+        router.on_close(|w| w.on_close());
+    }
+
+    fn dispatch(&mut self, event: Event<HWND>) -> bool {
+        // the handlers get the window itself, so the router is lent out
+        let mut router = std::mem::take(&mut self.router);
+        let handled = router.dispatch(self, &event);
+        self.router = router;
+        handled
     }
 
     fn on_paint(&self) {
@@ -406,9 +929,9 @@ impl Window {
             let mut ps: PAINTSTRUCT = zeroed();
             let hdc = BeginPaint(self.main, &mut ps);
             let mut rect: RECT = zeroed();
-            GetClientRect(self.main, &mut rect).unwrap();
+            let _ = GetClientRect(self.main, &mut rect);
             FillRect(hdc, &rect, GetSysColorBrush(COLOR_WINDOW));
-            EndPaint(self.main, &ps).unwrap();
+            let _ = EndPaint(self.main, &ps);
         }
         // redraw controls
         // self.update_position();
@@ -417,34 +940,39 @@ impl Window {
     fn build_ui(&mut self) -> Result<()> {
         unsafe {
             let instance = GetModuleHandleW(None)?;
-            // Create path textbox
-            let path_tb_rect = Rect {
-                X: self.padding, 
-                Y: self.padding, 
-                Width: self.width as f32 - self.btn_width * 2.0 - self.padding * 4.0, 
-                Height: self.oneline_height
-            };
-            self.controls.insert(Self::ID_TEXTBOX_PATH, path_tb_rect);
-            self.path_txt = CreateWindowExW(
+            // Create status bar, it sizes itself so only its height is kept
+            self.status_bar = StatusBarHandle(CreateWindowExW(
                 WINDOW_EX_STYLE::default(),
-                w!("EDIT"),
+                STATUSCLASSNAMEW,
                 w!(""),
                 WINDOW_STYLE(
-                    WS_VISIBLE.0 | 
-                    WS_CHILD.0 | 
-                    WS_BORDER.0 |
-                    ES_AUTOHSCROLL as u32 |
-                    SS_CENTERIMAGE.0
+                    WS_CHILD.0 |
+                    WS_VISIBLE.0 |
+                    SBARS_SIZEGRIP
                 ),
-                path_tb_rect.X as i32,
-                path_tb_rect.Y as i32,
-                path_tb_rect.Width as i32,
-                path_tb_rect.Height as i32,
+                0,
+                0,
+                0,
+                0,
                 self.main,
-                HMENU(Self::ID_TEXTBOX_PATH as _),
+                HMENU(Self::ID_STATUS_BAR as _),
                 instance,
                 None,
-            )?;
+            )?);
+            self.status_bar.set_font(self.font.0 as usize);
+            let (_, top, _, bottom) = self.status_bar.window_rect();
+            self.status_height = (bottom - top) as f32;
+
+            // Create path textbox
+            let path_tb_rect = Rect {
+                X: self.padding, 
+                Y: self.padding, 
+                Width: self.width as f32 - self.btn_width * 2.0 - self.padding * 4.0, 
+                Height: self.oneline_height
+            };
+            let path_txt = self.create(Edit::new(Self::ID_TEXTBOX_PATH).border(), path_tb_rect)?;
+            // handle pasting folders copied in explorer
+            let _ = SetWindowSubclass(path_txt.raw(), Some(Self::path_subclass_proc), 0, 0);
 
             // Create progress bar
             let progress_bar_rect = Rect {
@@ -453,30 +981,11 @@ impl Window {
                 Width: self.width as f32 - self.padding * 2.0 - self.progress_txt_width, 
                 Height: self.oneline_height 
             };
-            self.controls.insert(Self::ID_PROGRESS_BAR, progress_bar_rect);
-            self.progress_bar = CreateWindowExW(
-                WINDOW_EX_STYLE::default(),
-                w!("msctls_progress32"),
-                w!(""),
-                WINDOW_STYLE( WS_CHILD.0 | WS_VISIBLE.0),
-                progress_bar_rect.X as i32,
-                progress_bar_rect.Y as i32,
-                progress_bar_rect.Width as i32,
-                progress_bar_rect.Height as i32,
-                self.main,
-                HMENU(Self::ID_PROGRESS_BAR as _),
-                instance,
-                None,
+            self.create(
+                ProgressBar::new(Self::ID_PROGRESS_BAR).range(0, 100),
+                progress_bar_rect
             )?;
 
-            // Initialize progress bar
-            SendMessageW(
-                self.progress_bar, 
-                PBM_SETRANGE32, 
-                WPARAM(0), 
-                LPARAM(100)
-            );
-
             // Create progress txt
             let progress_txt_rect = Rect {
                 X: progress_bar_rect.X + progress_bar_rect.Width, 
@@ -484,27 +993,9 @@ impl Window {
                 Width: self.progress_txt_width, 
                 Height: self.oneline_height 
             };
-            self.controls.insert(Self::ID_PROGRESS_TXT, progress_txt_rect);
-            self.progress_txt = CreateWindowExW(
-                WINDOW_EX_STYLE::default(),
-                w!("EDIT"),
-                w!(""),
-                WINDOW_STYLE( 
-                    WS_CHILD.0 | 
-                    WS_VISIBLE.0 | 
-                    ES_READONLY as u32 |
-                    ES_CENTER as u32 |
-                    SS_CENTER.0 |
-                    SS_CENTERIMAGE.0
-                ),
-                progress_txt_rect.X as i32,
-                progress_txt_rect.Y as i32,
-                progress_txt_rect.Width as i32,
-                progress_txt_rect.Height as i32,
-                self.main,
-                HMENU(Self::ID_PROGRESS_TXT as _),
-                instance,
-                None,
+            self.create(
+                Edit::new(Self::ID_PROGRESS_TXT).read_only().align(Align::Center),
+                progress_txt_rect
             )?;
 
             // Create result textbox
@@ -514,33 +1005,11 @@ impl Window {
                 Width: self.width as f32 - self.padding * 2.0, 
                 Height: self.height as f32 - 
                     self.oneline_height * 2.0 - 
-                    self.padding * 4.0
+                    self.padding * 4.0 -
+                    self.status_height
             };
-            self.controls.insert(Self::ID_TEXTBOX_RESULT, result_tb_rect);
-            self.result_log = CreateWindowExW(
-                WINDOW_EX_STYLE::default(),
-                w!("EDIT"),
-                w!(""),
-                WINDOW_STYLE(
-                    WS_VISIBLE.0 | 
-                    WS_CHILD.0 | 
-                    WS_BORDER.0 | 
-                    WS_VSCROLL.0 | 
-                    ES_MULTILINE as u32 | 
-                    ES_AUTOVSCROLL as u32 | 
-                    ES_READONLY as u32
-                ),
-                result_tb_rect.X as i32,
-                result_tb_rect.Y as i32,
-                result_tb_rect.Width as i32,
-                result_tb_rect.Height as i32,
-                self.main,
-                HMENU(Self::ID_TEXTBOX_RESULT as _),
-                instance,
-                None,
-            )?;
-            //set default max words (64k)
-            SendMessageW(self.result_log, EM_LIMITTEXT, WPARAM(0), LPARAM(0));
+            self.controls.insert(Self::ID_TEXTBOX_RESULT.value(), result_tb_rect);
+            self.create_result_log()?;
 
             // Create path button
             let path_btn_rect = Rect {
@@ -549,20 +1018,9 @@ impl Window {
                 Width: self.btn_width, 
                 Height: self.oneline_height
             };
-            self.controls.insert(Self::ID_BTN_PATH, path_btn_rect);
-            CreateWindowExW(
-                WINDOW_EX_STYLE::default(),
-                w!("BUTTON"),
-                hstr_to_pcwstr(&self.local.path),
-                WS_VISIBLE | WS_CHILD,
-                path_btn_rect.X as i32,
-                path_btn_rect.Y as i32,
-                path_btn_rect.Width as i32,
-                path_btn_rect.Height as i32,
-                self.main,
-                HMENU(Self::ID_BTN_PATH as _),
-                instance,
-                None,
+            self.create(
+                Button::new(Self::ID_BTN_PATH).text(&self.local.path.to_string()),
+                path_btn_rect
             )?;
 
             // Create run button
@@ -572,139 +1030,659 @@ impl Window {
                 Width: self.btn_width, 
                 Height: self.oneline_height
             };
-            self.controls.insert(Self::ID_BTN_RUN, run_btn_rect);
-            CreateWindowExW(
-                WINDOW_EX_STYLE::default(),
-                w!("BUTTON"),
-                hstr_to_pcwstr(&self.local.run),
-                WS_VISIBLE | WS_CHILD,
-                run_btn_rect.X as i32,
-                run_btn_rect.Y as i32,
-                run_btn_rect.Width as i32,
-                run_btn_rect.Height as i32,
+            self.create(
+                Button::new(Self::ID_BTN_RUN).text(&self.local.run.to_string()),
+                run_btn_rect
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Creates the child window described by `builder` at `rect`, keeps
+    /// the rect for the layout, registers the window and gives it the ui
+    /// font.
+    fn create<B: ControlBuilder>(&mut self, builder: B, rect: Rect) -> Result<B::Handle<HWND>> {
+        let spec = builder
+            .spec()
+            .map_err(|e| Error::new(E_INVALIDARG, e.to_string()))?;
+        unsafe {
+            let instance = GetModuleHandleW(None)?;
+            let class = HSTRING::from(spec.class.class_name());
+            let text = HSTRING::from(spec.text.as_str());
+            let hwnd = CreateWindowExW(
+                WINDOW_EX_STYLE(spec.ex_style),
+                &class,
+                &text,
+                WINDOW_STYLE(spec.style),
+                rect.X as i32,
+                rect.Y as i32,
+                rect.Width as i32,
+                rect.Height as i32,
                 self.main,
-                HMENU(Self::ID_BTN_RUN as _),
+                HMENU(spec.id as _),
                 instance,
                 None,
             )?;
+            self.controls.insert(spec.id, rect);
+            if let Err(e) = self.registry.insert(spec.id, B::CLASS, hwnd) {
+                let _ = DestroyWindow(hwnd);
+                return Err(Error::new(E_INVALIDARG, e.to_string()));
+            }
+            if !self.font.is_invalid() {
+                hwnd.set_font(self.font.0 as usize);
+            }
+            if let Some((min, max)) = spec.range {
+                ProgressBarHandle(hwnd).set_range(min, max);
+            }
+            Ok(B::handle(hwnd))
+        }
+    }
+
+    fn create_result_log(&mut self) -> Result<()> {
+        unsafe {
+            let rect = self.controls[&Self::ID_TEXTBOX_RESULT.value()];
+            let mut edit = Edit::new(Self::ID_TEXTBOX_RESULT)
+                .multiline()
+                .read_only()
+                .border()
+                .vscroll();
+            // a multiline edit wraps unless it scrolls horizontally
+            if !self.log.wrap() {
+                edit = edit.hscroll();
+            }
+            let log = self.create(edit, rect)?;
+            //set default max words (64k)
+            log.limit_text(0);
+            // route the context menu and shortcuts to the main window
+            let _ = SetWindowSubclass(log.raw(), Some(Self::log_subclass_proc), 0, 0);
+        }
+        Ok(())
+    }
+
+    unsafe extern "system" fn log_subclass_proc(
+        hwnd: HWND,
+        message: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+        _id: usize,
+        _data: usize,
+    ) -> LRESULT {
+        let parent = GetParent(hwnd).unwrap_or_default();
+        match message {
+            WM_CONTEXTMENU => {
+                SendMessageW(parent, WM_CONTEXTMENU, WPARAM(hwnd.0 as usize), lparam);
+                return LRESULT(0);
+            },
+            WM_KEYDOWN => {
+                let ctrl = GetKeyState(VK_CONTROL.0 as i32) < 0;
+                let shift = GetKeyState(VK_SHIFT.0 as i32) < 0;
+                if let Some(command) = LogCommand::from_shortcut(ctrl, shift, wparam.0 as u16) {
+                    SendMessageW(parent, WM_COMMAND, WPARAM(command.id()), LPARAM(0));
+                    return LRESULT(0);
+                }
+            },
+            // swallow the control characters of the shortcuts
+            WM_CHAR if GetKeyState(VK_CONTROL.0 as i32) < 0 => {
+                return LRESULT(0);
+            },
+            WM_NCDESTROY => {
+                let _ = RemoveWindowSubclass(hwnd, Some(Self::log_subclass_proc), 0);
+            },
+            _ => {}
+        }
+        DefSubclassProc(hwnd, message, wparam, lparam)
+    }
+
+    fn show_log_menu(&self, position: Option<(i32, i32)>) {
+        unsafe {
+            let Ok(menu) = CreatePopupMenu() else {
+                return;
+            };
+            let (start, end) = self.result_log().selection();
+            for command in LogCommand::ALL {
+                if matches!(command, LogCommand::Clear | LogCommand::SaveAs) {
+                    let _ = AppendMenuW(menu, MF_SEPARATOR, 0, None);
+                }
+                let mut flags = MF_STRING;
+                let enabled = match command {
+                    LogCommand::Copy => start != end,
+                    LogCommand::WrapLines => true,
+                    _ => !self.log.is_empty(),
+                };
+                if !enabled {
+                    flags |= MF_GRAYED;
+                }
+                if command == LogCommand::WrapLines && self.log.wrap() {
+                    flags |= MF_CHECKED;
+                }
+                let text = HSTRING::from(command.menu_text());
+                let _ = AppendMenuW(menu, flags, command.id(), &text);
+            }
+            // no position when opened from the keyboard
+            let point = match position {
+                Some((x, y)) => POINT { x, y },
+                None => {
+                    let (left, top, _, _) = self.result_log().window_rect();
+                    POINT { x: left, y: top }
+                },
+            };
+            let _ = TrackPopupMenu(
+                menu,
+                TPM_RIGHTBUTTON,
+                point.x,
+                point.y,
+                0,
+                self.main,
+                None
+            );
+            let _ = DestroyMenu(menu);
+        }
+    }
+
+    fn on_log_command(&mut self, command: LogCommand) {
+        match command {
+            LogCommand::Copy => {
+                let (start, end) = self.result_log().selection();
+                let text = self.log.selected_text(start, end);
+                self.report_clipboard(self.clipboard().set_text(&text));
+            },
+            LogCommand::CopyAll => {
+                let text = self.log.text();
+                let html = format!("<pre>{}</pre>", escape_html(&text));
+                self.report_clipboard(self.clipboard().set_text_and_html(&text, &html));
+            },
+            LogCommand::SelectAll => self.result_log().select_all(),
+            LogCommand::Clear => {
+                self.log.clear();
+                self.result_log().set_text("");
+            },
+            LogCommand::SaveAs => self.save_log(),
+            LogCommand::Find => self.show_find_dialog(),
+            LogCommand::WrapLines => {
+                self.log.toggle_wrap();
+                self.recreate_result_log();
+            },
+        }
+    }
+
+    fn clipboard(&self) -> Clipboard<Win32Clipboard> {
+        Clipboard::new(Win32Clipboard::new(self.main))
+    }
+
+    fn report_clipboard(&self, result: std::io::Result<()>) {
+        if let Err(e) = result.context("剪貼簿") {
+            report_error(self.main, &e);
+        }
+    }
+
+    /// Pastes into the path textbox, taking folders copied in explorer as
+    /// well as plain text. Returns false to let the edit control paste.
+    fn paste_path(&self) -> bool {
+        let mut clipboard = self.clipboard();
+        let path = match clipboard.files() {
+            Ok(Some(files)) if !files.is_empty() => Some(files[0].clone()),
+            _ => match clipboard.text() {
+                Ok(Some(text)) => path_from_text(&text),
+                _ => None,
+            },
+        };
+        match path {
+            Some(path) => {
+                self.set_path_text(&path);
+                true
+            },
+            None => false,
+        }
+    }
+
+    unsafe extern "system" fn path_subclass_proc(
+        hwnd: HWND,
+        message: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+        _id: usize,
+        _data: usize,
+    ) -> LRESULT {
+        match message {
+            WM_PASTE => {
+                // the main window pastes, nonzero when it took the paste
+                let parent = GetParent(hwnd).unwrap_or_default();
+                if SendMessageW(parent, Self::APP_PASTE_PATH, WPARAM(0), LPARAM(0)).0 != 0 {
+                    return LRESULT(0);
+                }
+            },
+            WM_NCDESTROY => {
+                let _ = RemoveWindowSubclass(hwnd, Some(Self::path_subclass_proc), 0);
+            },
+            _ => {}
+        }
+        DefSubclassProc(hwnd, message, wparam, lparam)
+    }
+
+    fn save_log(&self) {
+        let path = match save_file(self.main, "result.txt") {
+            Ok(path) if !path.is_empty() => path,
+            _ => return,
+        };
+        let saved = std::fs::File::create(&path)
+            .and_then(|file| self.log.save(std::io::BufWriter::new(file)))
+            .with_context(|| format!("儲存 {}", path));
+        if let Err(e) = saved {
+            report_error(self.main, &e);
+        }
+    }
+
+    fn recreate_result_log(&mut self) {
+        unsafe {
+            let Some(old) = self.registry.remove(Self::ID_TEXTBOX_RESULT.value()) else {
+                return;
+            };
+            self.tooltips.unbind(old);
+            let _ = DestroyWindow(old);
+            if self.create_result_log().is_err() {
+                return;
+            }
+            self.result_log().set_text(&self.log.text());
+            self.set_tooltip(
+                self.result_log().raw(), 
+                TooltipBinding::Text(self.local.result_tip.clone())
+            );
+        }
+    }
+
+    fn show_find_dialog(&mut self) {
+        unsafe {
+            if !self.find_dialog.is_invalid() {
+                let _ = SetFocus(self.find_dialog);
+                return;
+            }
+            if self.find_msg == 0 {
+                self.find_msg = RegisterWindowMessageW(FINDMSGSTRINGW);
+                self.find_what = vec![0u16; 256];
+            }
+            // both live on the heap, the dialog keeps pointers to them
+            *self.find = FINDREPLACEW {
+                lStructSize: std::mem::size_of::<FINDREPLACEW>() as u32,
+                hwndOwner: self.main,
+                Flags: FR_DOWN | FR_HIDEWHOLEWORD,
+                lpstrFindWhat: PWSTR(self.find_what.as_mut_ptr()),
+                wFindWhatLen: self.find_what.len() as u16,
+                ..Default::default()
+            };
+            self.find_dialog = FindTextW(self.find.as_mut());
+            Application::add_dialog(self.find_dialog);
+        }
+    }
+
+    fn on_find_msg(&mut self) {
+        let flags = self.find.Flags;
+        if flags.contains(FR_DIALOGTERM) {
+            Application::remove_dialog(self.find_dialog);
+            self.find_dialog = HWND::default();
+            return;
+        }
+        if !flags.contains(FR_FINDNEXT) {
+            return;
+        }
+        let len = self.find_what.iter().position(|c| *c == 0).unwrap_or(0);
+        let query = String::from_utf16_lossy(&self.find_what[..len]);
+        let (start, end) = self.result_log().selection();
+        let down = flags.contains(FR_DOWN);
+        let from = match down {
+            true => end,
+            false => start,
+        };
+        match self.log.find(&query, from, flags.contains(FR_MATCHCASE), down) {
+            Some((start, end)) => {
+                self.result_log().select(start, end);
+                self.result_log().scroll_caret();
+            },
+            None => {
+                let msg = HSTRING::from(format!("找不到 \"{}\"", query));
+                pop_info(self.find_dialog, &msg);
+            },
+        }
+    }
+
+    fn build_menu(&mut self) -> Result<()> {
+        unsafe {
+            let menu = CreateMenu()?;
+            let view = CreatePopupMenu()?;
+            AppendMenuW(view, MF_STRING, Self::ID_MENU_LOG_VIEWER, w!("結果視窗(&L)"))?;
+            let verbose = match diagnostics::enabled(Level::Debug) {
+                true => MF_CHECKED,
+                false => MF_UNCHECKED,
+            };
+            AppendMenuW(
+                view, MF_STRING | verbose, Self::ID_MENU_VERBOSE_LOG, w!("詳細記錄(&D)")
+            )?;
+            AppendMenuW(menu, MF_POPUP, view.0 as usize, w!("檢視(&V)"))?;
+            let help = CreatePopupMenu()?;
+            AppendMenuW(help, MF_STRING, Self::ID_MENU_ABOUT, w!("關於(&A)..."))?;
+            AppendMenuW(menu, MF_POPUP, help.0 as usize, w!("說明(&H)"))?;
+            SetMenu(self.main, menu)?;
         }
+        Ok(())
+    }
+
+    // debug lines in the diagnostics log, on top of the --log-level flag
+    fn on_verbose_log(&self) {
+        let verbose = !diagnostics::enabled(Level::Debug);
+        let (level, check) = match verbose {
+            true => (Level::Debug, MF_CHECKED),
+            false => (Level::Info, MF_UNCHECKED),
+        };
+        diagnostics::set_level(level);
+        diagnostics::info("window", &format!("log level {}", level.as_str()));
+        unsafe {
+            CheckMenuItem(GetMenu(self.main), Self::ID_MENU_VERBOSE_LOG as u32, check.0);
+        }
+    }
+
+    fn on_about(&self) {
+        let info = version_info::load_own().unwrap_or_default();
+        let text = version_info::about_text(
+            &info, env!("GIT_COMMIT"), env!("BUILD_DATE")
+        );
+        let _ = show_about(self.main, "關於", &text);
+    }
 
+    fn build_tooltips(&mut self) -> Result<()> {
+        unsafe {
+            let instance = GetModuleHandleW(None)?;
+            self.tooltip = TooltipHandle(CreateWindowExW(
+                WINDOW_EX_STYLE::default(),
+                TOOLTIPS_CLASSW,
+                None,
+                WINDOW_STYLE(WS_POPUP.0 | TTS_ALWAYSTIP | TTS_NOPREFIX),
+                CW_USEDEFAULT,
+                CW_USEDEFAULT,
+                CW_USEDEFAULT,
+                CW_USEDEFAULT,
+                self.main,
+                None,
+                instance,
+                None,
+            )?);
+        }
+        // allow long paths to wrap instead of running off the screen
+        self.tooltip.set_max_width((400.0 * self.scale_factor) as i32);
+        let bindings = [
+            (
+                self.path_btn().raw(),
+                TooltipBinding::Text(self.local.path_tip.clone())
+            ),
+            (
+                self.run_btn().raw(),
+                TooltipBinding::Text(self.local.run_tip.clone())
+            ),
+            (
+                self.path_txt().raw(),
+                TooltipBinding::FullTextWhenTruncated(self.local.path_txt_tip.clone())
+            ),
+            (self.progress_bar().raw(), TooltipBinding::Text(self.local.progress_tip.clone())),
+            (self.progress_txt().raw(), TooltipBinding::Text(self.local.progress_tip.clone())),
+            (self.result_log().raw(), TooltipBinding::Text(self.local.result_tip.clone())),
+        ];
+        for (control, binding) in bindings {
+            self.set_tooltip(control, binding);
+        }
         Ok(())
     }
 
-    fn update_rect(&mut self, lparam: LPARAM) {
+    fn set_tooltip(&mut self, control: HWND, binding: TooltipBinding) {
+        if control.is_invalid() || !self.tooltips.bind(control, binding) {
+            return;
+        }
+        self.tooltip.add_tool(self.main, control);
+    }
+
+    fn on_notify(&mut self, lparam: LPARAM) -> LRESULT {
+        unsafe {
+            let hdr = &*(lparam.0 as *const NMHDR);
+            if hdr.code == TTN_GETDISPINFOW && hdr.hwndFrom == self.tooltip.raw() {
+                let info = &mut *(lparam.0 as *mut NMTTDISPINFOW);
+                // with TTF_IDISHWND the id is the handle of the control
+                let control = HWND(hdr.idFrom as _);
+                let text = self.tooltips.text_for(self, control).unwrap_or_default();
+                self.tooltip_text = HSTRING::from(text);
+                info.lpszText = PWSTR(self.tooltip_text.as_ptr() as *mut u16);
+            }
+        }
+        LRESULT(0)
+    }
+
+    fn update_rect(&mut self, width: u32, height: u32) {
         if self.controls.is_empty() {
             return;
         }
-        let (width, height) = (Self::loword(lparam.0), Self::hiword(lparam.0));
         // update path textbox
-        let path_tb_rect = self.controls.get_mut(&Self::ID_TEXTBOX_PATH).unwrap();
+        let path_tb_rect = self.controls.get_mut(&Self::ID_TEXTBOX_PATH.value()).unwrap();
         path_tb_rect.Width = width as f32 - self.btn_width * 2.0 - self.padding * 4.0;
         // update progress bar
-        let progress_bar_rect = self.controls.get_mut(&Self::ID_PROGRESS_BAR).unwrap();
+        let progress_bar_rect = self.controls.get_mut(&Self::ID_PROGRESS_BAR.value()).unwrap();
         progress_bar_rect.Width = width as f32 - self.padding * 2.0 - self.progress_txt_width; 
         // update progress txt
-        let progress_bar_rect = self.controls.get(&Self::ID_PROGRESS_BAR).cloned().unwrap();
-        let progress_txt_rect = self.controls.get_mut(&Self::ID_PROGRESS_TXT).unwrap();
+        let progress_bar_rect = self.controls.get(&Self::ID_PROGRESS_BAR.value()).cloned().unwrap();
+        let progress_txt_rect = self.controls.get_mut(&Self::ID_PROGRESS_TXT.value()).unwrap();
         progress_txt_rect.X = progress_bar_rect.X + progress_bar_rect.Width; 
         // update result textbox
-        let path_tb_rect = self.controls.get(&Self::ID_TEXTBOX_PATH).cloned().unwrap();
-        let rect = self.controls.get_mut(&Self::ID_TEXTBOX_RESULT).unwrap();
+        let path_tb_rect = self.controls.get(&Self::ID_TEXTBOX_PATH.value()).cloned().unwrap();
+        let rect = self.controls.get_mut(&Self::ID_TEXTBOX_RESULT.value()).unwrap();
         rect.Width = width as f32 - self.padding * 2.0; 
-        rect.Height = height as f32 - self.padding * 4.0 - self.oneline_height * 2.0;
+        rect.Height = height as f32 - 
+            self.padding * 4.0 - 
+            self.oneline_height * 2.0 - 
+            self.status_height;
         // update path button
-        let rect = self.controls.get_mut(&Self::ID_BTN_PATH).unwrap();
+        let rect = self.controls.get_mut(&Self::ID_BTN_PATH.value()).unwrap();
         rect.X = path_tb_rect.X + path_tb_rect.Width + self.padding;
         // update run button
-        let rect = self.controls.get_mut(&Self::ID_BTN_RUN).unwrap();
+        let rect = self.controls.get_mut(&Self::ID_BTN_RUN.value()).unwrap();
         rect.X = path_tb_rect.X + path_tb_rect.Width + self.btn_width + self.padding * 2.0;
     }
 
     fn update_position(&self) {
-        unsafe {
-            self.controls.iter().for_each(|(id, rect)| {
-                if let Ok(hwnd) = GetDlgItem(self.main, *id as i32) {
-                    let _ = SetWindowPos(
-                        hwnd,
-                        None,
-                        rect.X as i32,
-                        rect.Y as i32,
-                        rect.Width as i32,
-                        rect.Height as i32,
-                        SWP_NOZORDER | SWP_NOOWNERZORDER,
-                    );
-                }
-                // scroll path textbox to start position 
-                if let Ok(hwnd) = GetDlgItem(self.main, Self::ID_TEXTBOX_PATH as i32) {
-                    SendMessageW(hwnd, EM_SETSEL, WPARAM(0), LPARAM(0));
-                }
-            });
+        for (id, hwnd) in self.registry.iter() {
+            if let Some(rect) = self.controls.get(&id) {
+                hwnd.set_rect(
+                    rect.X as i32,
+                    rect.Y as i32,
+                    rect.Width as i32,
+                    rect.Height as i32
+                );
+            }
         }
+        // scroll path textbox to start position 
+        self.path_txt().select(0, 0);
     }
 
     fn on_path_btn(&mut self) {
-        if let Ok(s) = select_folder() {
+        if let Ok(s) = select_folder(self.main) {
             if s.is_empty() {
                 return;
             }
             // display the selected path
-            self.set_path_text(&HSTRING::from(&s));
+            self.set_path_text(&s);
         }
     }
 
-    fn set_path_text(&self, path: &HSTRING) {
-        unsafe {
-            let _ = SetWindowTextW(self.path_txt, hstr_to_pcwstr(&path));
+    /// Serves the control api on a thread of its own. Requests are carried
+    /// out on the ui thread, the same as clicks on the controls.
+    pub fn serve_control(window: HWND) {
+        let window = ThreadSafeHwnd(window);
+        let handler = move |request: &Request| {
+            let mut call = ControlCall { request, response: None };
+            let handled = unsafe {
+                // the whole wrapper is moved in, not the bare HWND
+                SendMessageW(window.clone().0, Self::APP_CONTROL, WPARAM(&mut call as *mut _ as usize), LPARAM(0))
+            };
+            match handled.0 {
+                0 => request.id.as_ref().map(|id| {
+                    rpc::error_response(Some(id), rpc::UNAVAILABLE, "the window is busy, try again")
+                }),
+                _ => call.response,
+            }
+        };
+        thread::spawn(move || {
+            diagnostics::info("ipc", &format!("listening on {}", ipc::pipe::PIPE_NAME));
+            if let Err(e) = ipc::pipe::run(Arc::new(handler)) {
+                diagnostics::warn("ipc", &format!("control api stopped: {}", e));
+            }
+        });
+    }
+
+    // the control api changes nothing while a task runs, like the controls
+    fn idle(&self) -> std::result::Result<(), AppError> {
+        match self.tray.is_enabled(TrayCommand::Run) {
+            true => Ok(()),
+            false => Err(AppError::config("工作執行中")),
         }
     }
 
-    fn append_to_textbox(&self, textbox: HWND, content: &str) {
+    fn set_path_text(&self, path: &str) {
+        self.path_txt().set_text(path);
+    }
+
+    fn init(&mut self) { 
+        self.app.init_app(self.main);
         unsafe {
-            // Get the length of text in the edit control
-            let text_length = 
-                SendMessageW(textbox, WM_GETTEXTLENGTH, WPARAM(0), LPARAM(0));
-            // Set the selection to the end of the text
-            // This effectively moves the caret to the end
-            SendMessageW(
-                textbox,
-                EM_SETSEL,
-                WPARAM(text_length.0 as usize),
-                LPARAM(text_length.0)
-            );
-            // Append content
-            let result_log = HSTRING::from(&format!("{}\r\n", content));
-            SendMessageW(
-                textbox, 
-                EM_REPLACESEL, 
-                WPARAM(1), 
-                LPARAM(result_log.as_ptr() as isize)
-            );
-            
-            // Get current line count
-            let line_count = 
-                SendMessageW(textbox, EM_GETLINECOUNT, WPARAM(0), LPARAM(0));
-            // Scroll to bottom
-            SendMessageW(textbox, EM_LINESCROLL, WPARAM(0), LPARAM(line_count.0));
+            self.taskbar_created = RegisterWindowMessageW(w!("TaskbarButtonCreated"));
         }
+        self.refresh_status();
+        self.add_tray_icon();
+        self.apply_startup();
     }
 
-    fn enable_window(&self, id: u32, enable: bool) {
-        unsafe {
-            if let Ok(ctrl) = GetDlgItem(self.main, id as i32) {
-                let _ = EnableWindow(ctrl, BOOL(enable as i32));
+    /// The command line of a second instance, as if this window was
+    /// started with it. Returns whether it was taken.
+    fn on_forwarded(&mut self, bytes: &[u8]) -> bool {
+        let forwarded = match copydata::decode(bytes) {
+            Ok(forwarded) => forwarded,
+            Err(e) => {
+                diagnostics::warn("single_instance", &format!("dropped payload: {}", e));
+                return false;
+            },
+        };
+        let options = match cli::parse(&forwarded.args) {
+            Ok(cli::Command::Gui(options)) => options,
+            other => {
+                diagnostics::warn("single_instance", &format!("not for the window: {:?}", other));
+                return false;
+            },
+        };
+        diagnostics::info("single_instance", &format!("forwarded {:?}", forwarded.args));
+        self.startup = StartupPlan::new(&options, Path::new(&forwarded.cwd), |path| path.exists());
+        if self.startup.show == ShowMode::Normal {
+            self.on_tray_command(TrayCommand::Show);
+        }
+        self.apply_startup();
+        true
+    }
+
+    fn apply_startup(&mut self) {
+        let mut startup = std::mem::take(&mut self.startup);
+        if let Some(path) = &startup.path {
+            self.set_path_text(&path.display().to_string());
+        }
+        // a forwarded command line doesn't stop a run that's going
+        let idle = self.tray.is_enabled(TrayCommand::Run);
+        if startup.autostart && !idle {
+            startup.autostart = false;
+            startup.warnings.push("工作執行中，沒有自動開始".to_string());
+        }
+        if idle {
+            self.app.set_task(&startup.task);
+            self.app.set_task_options(startup.task_options.clone());
+        }
+        for warning in &startup.warnings {
+            self.on_update_result(warning);
+        }
+        if startup.autostart {
+            // clicked once the message loop runs, like the user would
+            let click = messages::make_wparam(Self::ID_BTN_RUN.value() as u16, BN_CLICKED as u16);
+            unsafe {
+                let _ = PostMessageW(
+                    self.main, WM_COMMAND, WPARAM(click), LPARAM(self.run_btn().raw().0 as isize)
+                );
             }
         }
     }
+}
 
-    fn init(&mut self) { 
-        self.app.init_app(self.main);
+impl rpc::Control for Window {
+    fn status(&self) -> rpc::Status {
+        rpc::Status {
+            state: self.status.state(),
+            path: self.path_txt().text(),
+            task: self.app.task().to_string(),
+            percent: self.progress.percent(),
+            stage: self.progress.stage().to_string(),
+            done: self.progress.done(),
+            total: self.progress.total(),
+            elapsed: self.status.elapsed(Instant::now()),
+        }
+    }
+
+    fn set_path(&mut self, path: &Path) -> std::result::Result<(), AppError> {
+        self.idle()?;
+        self.set_path_text(&path.display().to_string());
+        Ok(())
+    }
+
+    fn start(&mut self, task: Option<&str>, command: Option<&str>) -> std::result::Result<(), AppError> {
+        self.idle()?;
+        if let Some(task) = task {
+            self.app.set_task(task);
+        }
+        if let Some(line) = command {
+            let mut options = self.app.task_options().clone();
+            options.command = Some(line.to_string());
+            self.app.set_task_options(options);
+        }
+        self.on_go_btn();
+        Ok(())
+    }
+
+    fn cancel(&mut self) -> bool {
+        let running = self.tray.is_enabled(TrayCommand::Cancel);
+        if running {
+            self.app.cancel();
+        }
+        running
+    }
+
+    fn tail_log(&self, lines: usize) -> Vec<String> {
+        rpc::tail(self.log.lines(), lines)
     }
+}
+
+impl ControlHost for Window {
+    type Handle = HWND;
 
-    fn loword(l: isize) -> isize {
-        l & 0xffff
+    fn control_text(&self, control: HWND) -> String {
+        control.text()
     }
 
-    fn hiword(l: isize) -> isize {
-        (l >> 16) & 0xffff
+    fn is_text_truncated(&self, control: HWND) -> bool {
+        unsafe {
+            let text: Vec<u16> = control.text().encode_utf16().collect();
+            if text.is_empty() {
+                return false;
+            }
+            let mut client: RECT = zeroed();
+            if GetClientRect(control, &mut client).is_err() {
+                return false;
+            }
+            // measure with the font the control draws with
+            let hdc = GetDC(control);
+            let old = SelectObject(hdc, HGDIOBJ(control.font() as _));
+            let mut size = SIZE::default();
+            let _ = GetTextExtentPoint32W(hdc, &text, &mut size);
+            SelectObject(hdc, old);
+            ReleaseDC(control, hdc);
+            size.cx > client.right - client.left
+        }
     }
-}
\ No newline at end of file
+}
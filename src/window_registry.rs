@@ -0,0 +1,153 @@
+/// Main windows keep the application running, tool windows (log viewer,
+/// settings) close with it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WindowRole {
+    Main,
+    Tool,
+}
+
+/// Window classes and top-level windows of the application. The message
+/// loop quits once the last main window is gone.
+#[derive(Clone, Debug)]
+pub struct WindowRegistry<H> {
+    classes: Vec<String>,
+    windows: Vec<(H, WindowRole, String)>,
+    dialogs: Vec<H>,
+}
+
+impl<H> Default for WindowRegistry<H> {
+    fn default() -> Self {
+        Self { classes: Vec::new(), windows: Vec::new(), dialogs: Vec::new() }
+    }
+}
+
+impl<H: Copy + PartialEq> WindowRegistry<H> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records `class`, true the first time so it's registered only once.
+    pub fn register_class(&mut self, class: &str) -> bool {
+        if self.is_class_registered(class) {
+            return false;
+        }
+        self.classes.push(class.to_string());
+        true
+    }
+
+    pub fn is_class_registered(&self, class: &str) -> bool {
+        self.classes.iter().any(|c| c == class)
+    }
+
+    pub fn add(&mut self, window: H, role: WindowRole, class: &str) {
+        if self.contains(window) {
+            return;
+        }
+        self.windows.push((window, role, class.to_string()));
+    }
+
+    /// Forgets a destroyed window, returns true when it was the last main
+    /// window and the loop should quit.
+    pub fn remove(&mut self, window: H) -> bool {
+        let Some(at) = self.windows.iter().position(|(w, _, _)| *w == window) else {
+            return false;
+        };
+        let (_, role, _) = self.windows.remove(at);
+        role == WindowRole::Main && self.main_count() == 0
+    }
+
+    pub fn contains(&self, window: H) -> bool {
+        self.windows.iter().any(|(w, _, _)| *w == window)
+    }
+
+    pub fn main_count(&self) -> usize {
+        self.windows.iter().filter(|(_, role, _)| *role == WindowRole::Main).count()
+    }
+
+    /// The open window of `class`, to bring a tool window back instead of
+    /// opening a second one.
+    pub fn find_class(&self, class: &str) -> Option<H> {
+        self.windows.iter().find(|(_, _, c)| c == class).map(|(w, _, _)| *w)
+    }
+
+    /// Tool windows that have to close with the last main window.
+    pub fn tools(&self) -> Vec<H> {
+        self.windows
+            .iter()
+            .filter(|(_, role, _)| *role == WindowRole::Tool)
+            .map(|(w, _, _)| *w)
+            .collect()
+    }
+
+    /// Modeless dialogs get their keyboard messages through
+    /// IsDialogMessageW in the loop.
+    pub fn add_dialog(&mut self, dialog: H) {
+        if !self.dialogs.contains(&dialog) {
+            self.dialogs.push(dialog);
+        }
+    }
+
+    pub fn remove_dialog(&mut self, dialog: H) {
+        self.dialogs.retain(|d| *d != dialog);
+    }
+
+    pub fn dialogs(&self) -> &[H] {
+        &self.dialogs
+    }
+
+    pub fn windows(&self) -> impl Iterator<Item = H> + '_ {
+        self.windows.iter().map(|(w, _, _)| *w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classes_register_once() {
+        let mut registry = WindowRegistry::<u32>::new();
+        assert!(registry.register_class("window"));
+        assert!(!registry.register_class("window"));
+        assert!(registry.register_class("log_viewer"));
+        assert!(registry.is_class_registered("log_viewer"));
+    }
+
+    #[test]
+    fn loop_ends_with_the_last_main_window() {
+        let mut registry = WindowRegistry::new();
+        registry.add(1, WindowRole::Main, "window");
+        registry.add(2, WindowRole::Main, "window");
+        registry.add(3, WindowRole::Tool, "log_viewer");
+        assert!(!registry.remove(3));
+        assert!(!registry.remove(1));
+        assert_eq!(registry.main_count(), 1);
+        assert!(registry.remove(2));
+        // unknown or already removed windows don't quit twice
+        assert!(!registry.remove(2));
+    }
+
+    #[test]
+    fn tools_alone_do_not_keep_the_loop() {
+        let mut registry = WindowRegistry::new();
+        registry.add(1, WindowRole::Main, "window");
+        registry.add(2, WindowRole::Tool, "log_viewer");
+        registry.add(2, WindowRole::Tool, "log_viewer");
+        assert_eq!(registry.tools(), vec![2]);
+        assert_eq!(registry.find_class("log_viewer"), Some(2));
+        assert!(registry.remove(1));
+        assert_eq!(registry.windows().collect::<Vec<_>>(), vec![2]);
+    }
+
+    #[test]
+    fn dialogs_are_tracked_separately() {
+        let mut registry = WindowRegistry::new();
+        registry.add(1, WindowRole::Main, "window");
+        registry.add_dialog(9);
+        registry.add_dialog(9);
+        assert_eq!(registry.dialogs(), &[9]);
+        registry.remove_dialog(9);
+        assert!(registry.dialogs().is_empty());
+        assert!(!registry.contains(9));
+    }
+}